@@ -0,0 +1,279 @@
+//! Work out the status of an async-commit transaction from the primary lock and what is
+//! found on its secondary keys, the way the lock resolver of TiDB does.
+//!
+//! An async-commit transaction is committed once all its keys are prewritten, at the
+//! max min_commit_ts of the locks. So it's committed if any secondary is, and it's rolled
+//! back if any secondary is neither locked nor committed, as the missing lock is rolled
+//! back by `CheckSecondaryLocks` and can never be prewritten again.
+use crate::explain;
+use crate::lock::{Lock, LockType};
+use crate::mvcc::TimeStamp;
+use crate::store::{CommitRecord, MvccStore};
+use anyhow::anyhow;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+/// What is found on a key of the transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyState {
+    /// The lock on the key, which may be of another transaction.
+    Locked(Box<Lock>),
+    Committed(TimeStamp),
+    RolledBack,
+    /// Neither a lock nor a record of the transaction.
+    NotFound,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    Committed(TimeStamp),
+    RolledBack,
+    /// A secondary is prewritten without async commit, the transaction is decided by the
+    /// primary lock as a 2PC transaction.
+    FallBackTo2pc,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsyncCommitStatus {
+    pub decision: Decision,
+    pub steps: Vec<String>,
+}
+
+impl fmt::Display for AsyncCommitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{}", step)?;
+        }
+        match self.decision {
+            Decision::Committed(commit_ts) => write!(f, "=> committed at {}", commit_ts),
+            Decision::RolledBack => write!(f, "=> rolled back"),
+            Decision::FallBackTo2pc => write!(f, "=> fell back to 2PC, check the primary"),
+        }
+    }
+}
+
+/// Decide the status of the transaction of the primary lock. `secondaries` is what is
+/// found on each of its secondary keys.
+pub fn resolve_async_commit(
+    primary_key: &[u8],
+    primary: &Lock,
+    secondaries: &[(Vec<u8>, KeyState)],
+) -> anyhow::Result<AsyncCommitStatus> {
+    let mut steps = vec![format!(
+        "primary {}: {}",
+        explain::explain_key(primary_key),
+        primary
+    )];
+    if !primary.use_async_commit {
+        steps.push("the primary lock doesn't use async commit".to_string());
+        return Ok(AsyncCommitStatus {
+            decision: Decision::FallBackTo2pc,
+            steps,
+        });
+    }
+    let mut commit_ts = primary.min_commit_ts;
+    for key in primary.secondary_keys() {
+        let state = secondaries
+            .iter()
+            .find(|(secondary, _)| secondary == key)
+            .map(|(_, state)| state)
+            .ok_or_else(|| anyhow!("the state of {} is not given", explain::explain_key(key)))?;
+        let name = format!("secondary {}", explain::explain_key(key));
+        let decision = match state {
+            KeyState::Locked(lock) if lock.ts != primary.ts => {
+                steps.push(format!(
+                    "{}: locked by another transaction {}, the lock of ours is missing",
+                    name, lock.ts
+                ));
+                Some(Decision::RolledBack)
+            }
+            KeyState::Locked(lock) if lock.lock_type == LockType::Pessimistic => {
+                steps.push(format!(
+                    "{}: pessimistically locked but not prewritten, the lock is rolled back",
+                    name
+                ));
+                Some(Decision::RolledBack)
+            }
+            KeyState::Locked(lock) if !lock.use_async_commit => {
+                steps.push(format!("{}: {}, not using async commit", name, lock));
+                Some(Decision::FallBackTo2pc)
+            }
+            KeyState::Locked(lock) => {
+                steps.push(format!(
+                    "{}: prewritten with min_commit_ts {}",
+                    name, lock.min_commit_ts
+                ));
+                commit_ts = commit_ts.max(lock.min_commit_ts);
+                None
+            }
+            KeyState::Committed(ts) => {
+                steps.push(format!("{}: committed at {}", name, ts));
+                Some(Decision::Committed(*ts))
+            }
+            KeyState::RolledBack => {
+                steps.push(format!("{}: rolled back", name));
+                Some(Decision::RolledBack)
+            }
+            KeyState::NotFound => {
+                steps.push(format!("{}: not prewritten, a rollback is written", name));
+                Some(Decision::RolledBack)
+            }
+        };
+        if let Some(decision) = decision {
+            return Ok(AsyncCommitStatus { decision, steps });
+        }
+    }
+    steps.push(format!(
+        "all the {} secondaries are prewritten, the commit ts is the max min_commit_ts",
+        primary.secondary_keys().len()
+    ));
+    Ok(AsyncCommitStatus {
+        decision: Decision::Committed(commit_ts),
+        steps,
+    })
+}
+
+/// What is found on `key` for the transaction started at `start_ts`.
+pub fn key_state(store: &MvccStore, key: &[u8], start_ts: TimeStamp) -> anyhow::Result<KeyState> {
+    if let Some(lock) = store.lock(key)?.filter(|lock| lock.ts == start_ts) {
+        return Ok(KeyState::Locked(Box::new(lock)));
+    }
+    Ok(match store.commit_record(key, start_ts)? {
+        CommitRecord::Committed(commit_ts) => KeyState::Committed(commit_ts),
+        CommitRecord::RolledBack => KeyState::RolledBack,
+        CommitRecord::Overlapped(_) | CommitRecord::NotFound => match store.lock(key)? {
+            Some(lock) => KeyState::Locked(Box::new(lock)),
+            None => KeyState::NotFound,
+        },
+    })
+}
+
+/// Decide the status of the transaction started at `start_ts` with the records in the store.
+pub fn resolve_in_store(
+    store: &MvccStore,
+    primary_key: &[u8],
+    start_ts: TimeStamp,
+) -> anyhow::Result<AsyncCommitStatus> {
+    let primary = match key_state(store, primary_key, start_ts)? {
+        KeyState::Locked(lock) if lock.ts == start_ts => lock,
+        state => {
+            let name = format!("primary {}", explain::explain_key(primary_key));
+            let (decision, step) = match state {
+                KeyState::Committed(commit_ts) => (
+                    Decision::Committed(commit_ts),
+                    format!("{}: committed at {}", name, commit_ts),
+                ),
+                _ => (
+                    Decision::RolledBack,
+                    format!("{}: the lock is gone without a commit record", name),
+                ),
+            };
+            return Ok(AsyncCommitStatus {
+                decision,
+                steps: vec![step],
+            });
+        }
+    };
+    let secondaries = primary
+        .secondary_keys()
+        .iter()
+        .map(|key| Ok((key.clone(), key_state(store, key, start_ts)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    resolve_async_commit(primary_key, &primary, &secondaries)
+}
+
+#[wasm_bindgen]
+impl MvccStore {
+    /// Explain the status of the async-commit transaction of the primary key.
+    pub fn explain_async_commit(
+        &self,
+        primary_key: &[u8],
+        start_ts: u64,
+    ) -> Result<String, JsValue> {
+        resolve_in_store(self, primary_key, TimeStamp(start_ts))
+            .map(|status| status.to_string())
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::txn::{Mutation, Simulator, TxnOptions};
+
+    fn locked(ts: u64, min_commit_ts: u64) -> KeyState {
+        let mut lock =
+            Lock::new(LockType::Put, b"pk".to_vec(), TimeStamp(ts), 3000).with_async_commit(vec![]);
+        lock.min_commit_ts = TimeStamp(min_commit_ts);
+        KeyState::Locked(Box::new(lock))
+    }
+
+    #[test]
+    fn test_resolve_async_commit() {
+        let mut primary = Lock::new(LockType::Put, b"pk".to_vec(), TimeStamp(10), 3000)
+            .with_async_commit(vec![b"s1".to_vec(), b"s2".to_vec()]);
+        primary.min_commit_ts = TimeStamp(11);
+        let decide = |s1, s2| {
+            let secondaries = vec![(b"s1".to_vec(), s1), (b"s2".to_vec(), s2)];
+            resolve_async_commit(b"pk", &primary, &secondaries)
+                .unwrap()
+                .decision
+        };
+        assert_eq!(
+            decide(locked(10, 15), locked(10, 12)),
+            Decision::Committed(TimeStamp(15))
+        );
+        assert_eq!(
+            decide(locked(10, 15), KeyState::Committed(TimeStamp(20))),
+            Decision::Committed(TimeStamp(20))
+        );
+        assert_eq!(
+            decide(locked(10, 15), KeyState::NotFound),
+            Decision::RolledBack
+        );
+        assert_eq!(decide(locked(9, 15), locked(10, 12)), Decision::RolledBack);
+        // A secondary still pessimistically locked by us was never prewritten.
+        let pessimistic = Lock::new(LockType::Pessimistic, b"pk".to_vec(), TimeStamp(10), 3000);
+        assert_eq!(
+            decide(locked(10, 15), KeyState::Locked(Box::new(pessimistic))),
+            Decision::RolledBack
+        );
+
+        let secondaries = vec![(b"s1".to_vec(), locked(10, 13))];
+        let status = resolve_async_commit(b"pk", &primary, &secondaries);
+        assert_eq!(
+            status.unwrap_err().to_string(),
+            "the state of \"s2\" is not given"
+        );
+    }
+
+    #[test]
+    fn test_resolve_in_store() {
+        let mut sim = Simulator::default();
+        let options = TxnOptions {
+            async_commit: true,
+            ..Default::default()
+        };
+        let mutations = vec![
+            Mutation::Put(b"pk".to_vec(), b"v".to_vec()),
+            Mutation::Put(b"sk".to_vec(), b"v".to_vec()),
+        ];
+        sim.prewrite(&mutations, b"pk", TimeStamp(10), &options)
+            .unwrap();
+        let status = resolve_in_store(&sim.store, b"pk", TimeStamp(10)).unwrap();
+        assert_eq!(status.decision, Decision::Committed(TimeStamp(11)));
+        assert_eq!(
+            status.steps[1..],
+            [
+                "secondary \"sk\": prewritten with min_commit_ts 11",
+                "all the 1 secondaries are prewritten, the commit ts is the max min_commit_ts"
+            ]
+        );
+
+        sim.rollback(&[b"sk".to_vec()], TimeStamp(10)).unwrap();
+        let status = resolve_in_store(&sim.store, b"pk", TimeStamp(10)).unwrap();
+        assert!(status
+            .to_string()
+            .ends_with("secondary \"sk\": rolled back\n=> rolled back"));
+    }
+}
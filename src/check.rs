@@ -0,0 +1,330 @@
+//! Look for anomalies in the MVCC records of a store, which a healthy TiKV never leaves.
+use crate::explain;
+use crate::lock::{Lock, LockType};
+use crate::mvcc::{Key, TimeStamp, Write, WriteType, CF_DEFAULT, CF_LOCK, CF_WRITE};
+use crate::store::MvccStore;
+use std::collections::BTreeMap;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Anomaly {
+    /// A record which cannot be decoded.
+    Corrupted,
+    /// A `Put` without short value whose value is not in the default CF.
+    MissingDefault,
+    /// A value in the default CF no write or lock refers to.
+    OrphanDefault,
+    /// A lock of a transaction which is already finished, or older than the newest commit.
+    StaleLock,
+    /// The same transaction is committed more than once.
+    DuplicateCommit,
+    /// The same transaction is both committed and rolled back.
+    RollbackConflict,
+    /// The GC fence doesn't match the next version.
+    GcFence,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub anomaly: Anomaly,
+    /// The user key, or the key as stored if it cannot be decoded.
+    pub key: Vec<u8>,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {}: {}",
+            self.anomaly,
+            explain::explain_key(&self.key),
+            self.message
+        )
+    }
+}
+
+/// The records of a user key in the three CFs, the writes are newest first.
+#[derive(Default)]
+struct KeyRecords {
+    lock: Option<Lock>,
+    writes: Vec<(TimeStamp, Write)>,
+    values: Vec<TimeStamp>,
+}
+
+impl KeyRecords {
+    fn check(&self, key: &[u8], findings: &mut Vec<Finding>) {
+        let mut report = |anomaly, message: String| {
+            findings.push(Finding {
+                anomaly,
+                key: key.to_vec(),
+                message,
+            })
+        };
+
+        let mut committed: BTreeMap<TimeStamp, TimeStamp> = BTreeMap::new();
+        for (commit_ts, write) in &self.writes {
+            if write.write_type == WriteType::Rollback {
+                continue;
+            }
+            if let Some(other) = committed.insert(write.start_ts, *commit_ts) {
+                report(
+                    Anomaly::DuplicateCommit,
+                    format!(
+                        "the transaction {} is committed at both {} and {}",
+                        write.start_ts, commit_ts, other
+                    ),
+                );
+            }
+        }
+
+        for (i, (commit_ts, write)) in self.writes.iter().enumerate() {
+            let record = format!("write@{}: {}", commit_ts, write);
+            if write.write_type == WriteType::Put
+                && write.short_value_ref().is_none()
+                && !self.values.contains(&write.start_ts)
+            {
+                report(
+                    Anomaly::MissingDefault,
+                    format!("{}, but default@{} is missing", record, write.start_ts),
+                );
+            }
+            if write.write_type == WriteType::Rollback || write.has_overlapped_rollback {
+                // A rollback overlapped on another transaction's commit is at our start ts.
+                let start_ts = if write.has_overlapped_rollback {
+                    *commit_ts
+                } else {
+                    write.start_ts
+                };
+                if let Some(other) = committed.get(&start_ts) {
+                    report(
+                        Anomaly::RollbackConflict,
+                        format!(
+                            "{}, but the transaction {} is committed at {}",
+                            record, start_ts, other
+                        ),
+                    );
+                }
+            }
+            if let Some(fence) = write.gc_fence {
+                // The next newer version which is a `Put` or `Delete`.
+                let next = self.writes[..i].iter().rev().find(|(_, newer)| {
+                    matches!(newer.write_type, WriteType::Put | WriteType::Delete)
+                });
+                let message = match next {
+                    _ if !fence.is_zero() && fence <= *commit_ts => {
+                        Some("the fence is not newer than the commit".to_string())
+                    }
+                    // A zero fence only says there was no newer version when it was written.
+                    Some((next_ts, _)) if !fence.is_zero() && *next_ts != fence => {
+                        Some(format!("but the next version is committed at {}", next_ts))
+                    }
+                    _ => None,
+                };
+                if let Some(message) = message {
+                    report(Anomaly::GcFence, format!("{}, {}", record, message));
+                }
+            }
+        }
+
+        for start_ts in &self.values {
+            let referred_by_write = self.writes.iter().any(|(_, write)| {
+                write.write_type == WriteType::Put
+                    && write.short_value_ref().is_none()
+                    && write.start_ts == *start_ts
+            });
+            let referred_by_lock = self.lock.as_ref().is_some_and(|lock| {
+                lock.lock_type == LockType::Put
+                    && lock.short_value_ref().is_none()
+                    && lock.ts == *start_ts
+            });
+            if !referred_by_write && !referred_by_lock {
+                report(
+                    Anomaly::OrphanDefault,
+                    format!("default@{} is not referred by any write or lock", start_ts),
+                );
+            }
+        }
+
+        if let Some(lock) = &self.lock {
+            let finished = self
+                .writes
+                .iter()
+                .find(|(_, write)| write.start_ts == lock.ts);
+            // A pessimistic lock may be acquired after a newer commit.
+            let read_ts = lock.ts.max(lock.for_update_ts);
+            let newer = self.writes.iter().find(|(commit_ts, write)| {
+                write.write_type != WriteType::Rollback && *commit_ts > read_ts
+            });
+            let message = match (finished, newer) {
+                (Some((commit_ts, write)), _) if write.write_type == WriteType::Rollback => Some(
+                    format!("but the transaction is rolled back at {}", commit_ts),
+                ),
+                (Some((commit_ts, _)), _) => {
+                    Some(format!("but the transaction is committed at {}", commit_ts))
+                }
+                (None, Some((commit_ts, _))) => {
+                    Some(format!("older than the commit at {}", commit_ts))
+                }
+                _ => None,
+            };
+            if let Some(message) = message {
+                report(Anomaly::StaleLock, format!("lock: {}, {}", lock, message));
+            }
+        }
+    }
+}
+
+/// Check the records of every key in the store.
+pub fn check_store(store: &MvccStore) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut keys: BTreeMap<Vec<u8>, KeyRecords> = BTreeMap::new();
+    let mut corrupted = |cf, key: &[u8], error: anyhow::Error| {
+        findings.push(Finding {
+            anomaly: Anomaly::Corrupted,
+            key: key.to_vec(),
+            message: format!("{} CF: {}", cf, error),
+        })
+    };
+
+    for (key, value) in store.entries(CF_LOCK) {
+        match Lock::parse_rust(value) {
+            Ok(lock) => keys.entry(key.clone()).or_default().lock = Some(lock),
+            Err(e) => corrupted(CF_LOCK, key, e),
+        }
+    }
+    for (key, value) in store.entries(CF_WRITE) {
+        let record = Key::from_encoded(key.clone());
+        match record.split_on_ts().and_then(|(user_key, commit_ts)| {
+            Ok((user_key.to_vec(), commit_ts, Write::parse_rust(value)?))
+        }) {
+            Ok((user_key, commit_ts, write)) => {
+                let records = keys.entry(user_key).or_default();
+                records.writes.push((commit_ts, write));
+            }
+            Err(e) => corrupted(CF_WRITE, key, e),
+        }
+    }
+    for (key, _) in store.entries(CF_DEFAULT) {
+        match Key::from_encoded(key.clone()).split_on_ts() {
+            Ok((user_key, start_ts)) => keys
+                .entry(user_key.to_vec())
+                .or_default()
+                .values
+                .push(start_ts),
+            Err(e) => corrupted(CF_DEFAULT, key, e),
+        }
+    }
+
+    for (key, records) in keys {
+        let user_key = Key::from_encoded(key.clone()).to_raw().unwrap_or(key);
+        records.check(&user_key, &mut findings);
+    }
+    findings
+}
+
+#[wasm_bindgen]
+impl MvccStore {
+    /// Explain the anomalies in the store, one per line.
+    pub fn check(&self) -> String {
+        check_store(self)
+            .iter()
+            .map(|finding| format!("{}\n", finding))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mvcc::Modify;
+
+    fn put_write(key: &[u8], commit_ts: u64, write: &Write) -> Modify {
+        let key = Key::from_raw(key).append_ts(TimeStamp(commit_ts));
+        Modify::Put(CF_WRITE, key, write.to_bytes())
+    }
+
+    fn put(start_ts: u64, short_value: Option<&[u8]>) -> Write {
+        Write::new(
+            WriteType::Put,
+            TimeStamp(start_ts),
+            short_value.map(<[u8]>::to_vec),
+        )
+    }
+
+    fn anomalies(store: &MvccStore) -> Vec<Anomaly> {
+        check_store(store).iter().map(|f| f.anomaly).collect()
+    }
+
+    #[test]
+    fn test_check_store() {
+        let mut store = MvccStore::new();
+        let mut fenced = put(10, Some(b"v"));
+        fenced.gc_fence = Some(TimeStamp(25));
+        let mut overlapped = put(28, Some(b"v"));
+        overlapped.has_overlapped_rollback = true;
+        let mut zero_fenced = put(10, Some(b"v"));
+        zero_fenced.gc_fence = Some(TimeStamp::zero());
+        store.apply(vec![
+            put_write(b"c", 11, &zero_fenced),
+            put_write(b"c", 21, &put(20, Some(b"v"))),
+            put_write(b"a", 11, &fenced),
+            put_write(b"a", 21, &put(20, None)),
+            put_write(b"a", 30, &overlapped),
+            put_write(b"a", 31, &put(30, Some(b"v"))),
+            Modify::Put(
+                CF_DEFAULT,
+                Key::from_raw(b"a").append_ts(TimeStamp(5)),
+                vec![],
+            ),
+        ]);
+        assert_eq!(
+            anomalies(&store),
+            vec![
+                Anomaly::RollbackConflict,
+                Anomaly::MissingDefault,
+                Anomaly::GcFence,
+                Anomaly::OrphanDefault
+            ]
+        );
+        assert_eq!(
+            check_store(&store)[2].to_string(),
+            "GcFence \"a\": write@11: Put, start_ts: 10, short_value: \"v\", gc_fence: 25, \
+             but the next version is committed at 21"
+        );
+        store.load(CF_WRITE, b"b", b"oops");
+        assert_eq!(anomalies(&store)[0], Anomaly::Corrupted);
+    }
+
+    #[test]
+    fn test_check_locks() {
+        let mut store = MvccStore::new();
+        let lock = Lock::new(LockType::Put, b"k".to_vec(), TimeStamp(20), 3000);
+        store.apply(vec![
+            put_write(b"k", 11, &put(10, Some(b"v"))),
+            put_write(b"k", 12, &put(10, Some(b"v"))),
+            put_write(b"k", 21, &put(20, Some(b"v"))),
+            Modify::Put(CF_LOCK, Key::from_raw(b"k"), lock.to_bytes()),
+        ]);
+        let findings = check_store(&store);
+        assert_eq!(
+            findings.iter().map(|f| f.anomaly).collect::<Vec<_>>(),
+            vec![Anomaly::DuplicateCommit, Anomaly::StaleLock]
+        );
+        assert!(findings[1]
+            .to_string()
+            .ends_with("but the transaction is committed at 21"));
+
+        let lock = Lock::new(LockType::Put, b"k".to_vec(), TimeStamp(15), 3000);
+        store.apply(vec![Modify::Put(
+            CF_LOCK,
+            Key::from_raw(b"k"),
+            lock.to_bytes(),
+        )]);
+        assert!(check_store(&store)[1]
+            .to_string()
+            .ends_with("older than the commit at 21"));
+        assert_eq!(check_store(&MvccStore::new()), vec![]);
+    }
+}
@@ -1,3 +1,6 @@
+use crate::explain;
+use crate::trace::{EncodeMethod, ParsingTrace};
+
 const ENC_GROUP_SIZE: usize = 8;
 const ENC_MARKER: u8 = b'\xff';
 
@@ -16,7 +19,7 @@ pub fn encode_bytes(key: &[u8]) -> Vec<u8> {
         result.push(ENC_MARKER - fill_count);
     }
     if result.is_empty() || *result.last().unwrap() == 255 {
-        result.extend(core::iter::repeat(0).take(ENC_GROUP_SIZE));
+        result.extend(core::iter::repeat_n(0, ENC_GROUP_SIZE));
         result.push(ENC_MARKER - ENC_GROUP_SIZE as u8);
     }
     debug_assert_eq!(result.len() % 9, 0);
@@ -30,18 +33,86 @@ fn decode_chunk(chunk: &[u8]) -> &[u8] {
 }
 
 pub fn decode_bytes(code: &[u8]) -> Vec<u8> {
-    code.chunks(9)
-        .map(decode_chunk)
-        .flatten()
-        .cloned()
-        .collect()
+    code.chunks(9).flat_map(decode_chunk).cloned().collect()
 }
 
+#[allow(dead_code)]
 fn could_be_encoded_bytes(code: &[u8]) -> bool {
     code.chunks(9)
         .all(|chunk| chunk.len() == 9 && *chunk.last().unwrap() > 0xff - 8)
 }
 
+/// Decode the memcomparable encoded bytes at the beginning of `code`,
+/// returns the decoded bytes and how many bytes of `code` were consumed,
+/// or `None` if `code` doesn't start with a valid encoded bytes.
+pub fn decode_bytes_prefix(code: &[u8]) -> Option<(Vec<u8>, usize)> {
+    let mut result = Vec::new();
+    for (i, chunk) in code.chunks(ENC_GROUP_SIZE + 1).enumerate() {
+        if chunk.len() != ENC_GROUP_SIZE + 1 {
+            return None;
+        }
+        let marker = chunk[ENC_GROUP_SIZE];
+        if marker == ENC_MARKER {
+            result.extend_from_slice(&chunk[..ENC_GROUP_SIZE]);
+            continue;
+        }
+        let pad_count = (ENC_MARKER - marker) as usize;
+        if pad_count > ENC_GROUP_SIZE
+            || chunk[ENC_GROUP_SIZE - pad_count..ENC_GROUP_SIZE]
+                .iter()
+                .any(|&b| b != 0)
+        {
+            return None;
+        }
+        result.extend_from_slice(&chunk[..ENC_GROUP_SIZE - pad_count]);
+        return Some((result, (i + 1) * (ENC_GROUP_SIZE + 1)));
+    }
+    None
+}
+
+/// Same as `decode_bytes_prefix`, but returns how the consumed bytes are laid out
+/// in groups instead of how many bytes are consumed.
+pub fn trace_bytes_prefix(code: &[u8]) -> Option<(Vec<u8>, ParsingTrace)> {
+    let (raw, consumed) = decode_bytes_prefix(code)?;
+    let groups = code[..consumed]
+        .chunks(ENC_GROUP_SIZE + 1)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let start = i * (ENC_GROUP_SIZE + 1);
+            let pad_count = (ENC_MARKER - chunk[ENC_GROUP_SIZE]) as usize;
+            let data_len = ENC_GROUP_SIZE - pad_count;
+            let mut children = Vec::new();
+            if data_len > 0 {
+                children.push(
+                    ParsingTrace::leaf(start, data_len, "data", EncodeMethod::Bytes)
+                        .with_value(explain::escape(&chunk[..data_len])),
+                );
+            }
+            if pad_count > 0 {
+                children.push(ParsingTrace::leaf(
+                    start + data_len,
+                    pad_count,
+                    "padding",
+                    EncodeMethod::Bytes,
+                ));
+            }
+            children.push(
+                ParsingTrace::leaf(
+                    start + ENC_GROUP_SIZE,
+                    1,
+                    "marker",
+                    EncodeMethod::SingleByte,
+                )
+                .with_value(format!("{} bytes padded", pad_count)),
+            );
+            ParsingTrace::node("group", children)
+        })
+        .collect();
+    let mut trace = ParsingTrace::node("encoded", groups).with_value(explain::escape(&raw));
+    trace.encoded_in = EncodeMethod::Memcomparable;
+    Some((raw, trace))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -85,4 +156,49 @@ mod tests {
             assert_eq!(output, expected);
         }
     }
+    #[test]
+    fn test_decode_bytes_prefix() {
+        let mut code = encode_bytes(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let encoded_len = code.len();
+        code.extend_from_slice(&[0xff; 8]);
+        assert_eq!(
+            decode_bytes_prefix(&code),
+            Some((vec![1, 2, 3, 4, 5, 6, 7, 8, 9], encoded_len))
+        );
+        assert_eq!(
+            decode_bytes_prefix(&[1, 2, 3, 0, 0, 0, 0, 0, 250])
+                .unwrap()
+                .1,
+            9
+        );
+        assert_eq!(decode_bytes_prefix(&[1, 2, 3, 0, 0, 0, 0, 1, 250]), None);
+        assert_eq!(decode_bytes_prefix(&[1, 2, 3, 4, 5, 6, 7, 8, 255]), None);
+        assert_eq!(decode_bytes_prefix(b"t\x80"), None);
+    }
+
+    #[test]
+    fn test_trace_bytes_prefix() {
+        let mut code = encode_bytes(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        code.extend_from_slice(&[0xff; 8]);
+        let (raw, trace) = trace_bytes_prefix(&code).unwrap();
+        assert_eq!(raw, vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!((trace.start, trace.width), (0, 18));
+        let leaves: Vec<_> = trace
+            .leaves()
+            .into_iter()
+            .map(|(path, t)| (path, t.start, t.width))
+            .collect();
+        assert_eq!(
+            leaves,
+            vec![
+                ("group.data".to_string(), 0, 8),
+                ("group.marker".to_string(), 8, 1),
+                ("group.data".to_string(), 9, 1),
+                ("group.padding".to_string(), 10, 7),
+                ("group.marker".to_string(), 17, 1),
+            ]
+        );
+        let (_, trace) = trace_bytes_prefix(&encode_bytes(&[])).unwrap();
+        assert_eq!(trace.children[0].children.len(), 2);
+    }
 }
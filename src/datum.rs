@@ -0,0 +1,265 @@
+//! Datums in TiDB's key codec, which encodes index column values and common handles.
+//!
+//! Each datum is a flag byte telling the type followed by the encoded value.
+use crate::chunk;
+use crate::db_to_kv;
+use crate::explain;
+use crate::trace::{EncodeMethod, ParsingTrace, TracingReader};
+use crate::varint;
+use anyhow::{anyhow, bail};
+use std::convert::TryInto;
+use std::fmt;
+
+const NIL_FLAG: u8 = 0;
+const BYTES_FLAG: u8 = 1;
+const COMPACT_BYTES_FLAG: u8 = 2;
+const INT_FLAG: u8 = 3;
+const UINT_FLAG: u8 = 4;
+const FLOAT_FLAG: u8 = 5;
+const DECIMAL_FLAG: u8 = 6;
+const DURATION_FLAG: u8 = 7;
+const VARINT_FLAG: u8 = 8;
+const UVARINT_FLAG: u8 = 9;
+const JSON_FLAG: u8 = 10;
+const MAX_FLAG: u8 = 250;
+
+const SIGN_MASK: u64 = 0x8000000000000000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Datum {
+    Null,
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    Bytes(Vec<u8>),
+    /// A MySQL `TIME` in nanoseconds.
+    Duration(i64),
+    /// Greater than any other datum, used in range boundaries.
+    Max,
+}
+
+fn flag_name(flag: u8) -> Option<&'static str> {
+    Some(match flag {
+        NIL_FLAG => "Null",
+        BYTES_FLAG => "Bytes",
+        COMPACT_BYTES_FLAG => "CompactBytes",
+        INT_FLAG => "Int",
+        UINT_FLAG => "Uint",
+        FLOAT_FLAG => "Float",
+        DECIMAL_FLAG => "Decimal",
+        DURATION_FLAG => "Duration",
+        VARINT_FLAG => "Varint",
+        UVARINT_FLAG => "Uvarint",
+        JSON_FLAG => "Json",
+        MAX_FLAG => "Max",
+        _ => return None,
+    })
+}
+
+fn decode_f64(code: &[u8]) -> f64 {
+    let mut u = u64::from_be_bytes(code.try_into().unwrap());
+    if u & SIGN_MASK != 0 {
+        u &= !SIGN_MASK;
+    } else {
+        u = !u;
+    }
+    f64::from_bits(u)
+}
+
+fn encode_f64(f: f64) -> [u8; 8] {
+    let mut u = f.to_bits();
+    if f >= 0.0 {
+        u |= SIGN_MASK;
+    } else {
+        u = !u;
+    }
+    u.to_be_bytes()
+}
+
+fn read_datum(reader: &mut TracingReader) -> anyhow::Result<Datum> {
+    reader.begin("datum");
+    let flag = reader.read_u8("flag", EncodeMethod::EnumFlag)?;
+    let name = flag_name(flag).ok_or_else(|| anyhow!("invalid datum flag {}", flag))?;
+    reader.annotate(name);
+    let datum = match flag {
+        NIL_FLAG => Datum::Null,
+        BYTES_FLAG => {
+            let (raw, trace) = chunk::trace_bytes_prefix(reader.rest())
+                .ok_or_else(|| anyhow!("invalid memcomparable bytes in datum"))?;
+            reader.push(trace);
+            Datum::Bytes(raw)
+        }
+        COMPACT_BYTES_FLAG => Datum::Bytes(reader.read_compact_bytes("value")?.to_vec()),
+        INT_FLAG => Datum::Int(db_to_kv::decode_i64(reader.read_fixed(
+            8,
+            "value",
+            EncodeMethod::ComparableI64,
+        )?)?),
+        UINT_FLAG => Datum::Uint(reader.read_u64("value")?),
+        FLOAT_FLAG => Datum::Float(decode_f64(reader.read_fixed(
+            8,
+            "value",
+            EncodeMethod::BigEndian,
+        )?)),
+        DURATION_FLAG => Datum::Duration(db_to_kv::decode_i64(reader.read_fixed(
+            8,
+            "value",
+            EncodeMethod::ComparableI64,
+        )?)?),
+        VARINT_FLAG => Datum::Int(reader.read_var_i64("value")?),
+        UVARINT_FLAG => Datum::Uint(reader.read_var_u64("value")?),
+        MAX_FLAG => Datum::Max,
+        _ => bail!("{} datum is not supported yet", name),
+    };
+    if !matches!(datum, Datum::Null | Datum::Max) {
+        reader.annotate(&datum);
+    }
+    reader.end_as(EncodeMethod::Datum, &datum);
+    Ok(datum)
+}
+
+/// Decode all the datums in `code`, with the trace of them.
+pub fn decode_datums(code: &[u8]) -> anyhow::Result<(Vec<Datum>, ParsingTrace)> {
+    let mut reader = TracingReader::new(code);
+    let mut datums = Vec::new();
+    while reader.peek().is_some() {
+        datums.push(read_datum(&mut reader)?);
+    }
+    let trace = reader.finish("datums", DatumList(&datums));
+    Ok((datums, trace))
+}
+
+/// Encode `datum` in the memcomparable format, which is what keys use.
+pub fn encode_datum(b: &mut Vec<u8>, datum: &Datum) {
+    match datum {
+        Datum::Null => b.push(NIL_FLAG),
+        Datum::Int(n) => {
+            b.push(INT_FLAG);
+            b.extend_from_slice(&db_to_kv::encode_i64(*n));
+        }
+        Datum::Uint(n) => {
+            b.push(UINT_FLAG);
+            b.extend_from_slice(&n.to_be_bytes());
+        }
+        Datum::Float(f) => {
+            b.push(FLOAT_FLAG);
+            b.extend_from_slice(&encode_f64(*f));
+        }
+        Datum::Bytes(v) => {
+            b.push(BYTES_FLAG);
+            b.extend_from_slice(&chunk::encode_bytes(v));
+        }
+        Datum::Duration(n) => {
+            b.push(DURATION_FLAG);
+            b.extend_from_slice(&db_to_kv::encode_i64(*n));
+        }
+        Datum::Max => b.push(MAX_FLAG),
+    }
+}
+
+pub fn encode_datums(datums: &[Datum]) -> Vec<u8> {
+    let mut b = Vec::new();
+    for datum in datums {
+        encode_datum(&mut b, datum);
+    }
+    b
+}
+
+/// Encode `v` as a `compactBytes` datum, which is what row values use instead of keys.
+pub fn encode_compact_bytes(b: &mut Vec<u8>, v: &[u8]) {
+    b.push(COMPACT_BYTES_FLAG);
+    b.extend_from_slice(&varint::encode_i64(v.len() as i64));
+    b.extend_from_slice(v);
+}
+
+impl fmt::Display for Datum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Datum::Null => write!(f, "NULL"),
+            Datum::Int(n) => write!(f, "{}", n),
+            // The suffix tells it from an `Int` when parsing the notation back.
+            Datum::Uint(n) => write!(f, "{}u", n),
+            Datum::Float(n) => write!(f, "{:?}", n),
+            Datum::Bytes(v) => write!(f, "{}", explain::escape(v)),
+            Datum::Duration(nanos) => {
+                let sign = if *nanos < 0 { "-" } else { "" };
+                let nanos = nanos.unsigned_abs();
+                let secs = nanos / 1_000_000_000;
+                write!(
+                    f,
+                    "{}{:02}:{:02}:{:02}",
+                    sign,
+                    secs / 3600,
+                    secs / 60 % 60,
+                    secs % 60
+                )?;
+                let frac = nanos % 1_000_000_000;
+                if frac != 0 {
+                    write!(f, ".{:06}", frac / 1000)?;
+                }
+                Ok(())
+            }
+            Datum::Max => write!(f, "MAX"),
+        }
+    }
+}
+
+/// Display datums separated by commas.
+pub struct DatumList<'a>(pub &'a [Datum]);
+
+impl fmt::Display for DatumList<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, datum) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", datum)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_datums() {
+        let datums = vec![
+            Datum::Bytes(b"abc".to_vec()),
+            Datum::Int(-7),
+            Datum::Uint(7),
+            Datum::Float(-1.5),
+            Datum::Duration(3_723_000_500_000),
+            Datum::Null,
+            Datum::Max,
+        ];
+        let code = encode_datums(&datums);
+        let (decoded, trace) = decode_datums(&code).unwrap();
+        assert_eq!(decoded, datums);
+        assert_eq!(
+            DatumList(&decoded).to_string(),
+            "\"abc\",-7,7u,-1.5,01:02:03.000500,NULL,MAX"
+        );
+        assert_eq!(trace.width, code.len());
+        assert_eq!(trace.children.len(), datums.len());
+        let bytes = &trace.children[0];
+        assert_eq!(bytes.encoded_in, EncodeMethod::Datum);
+        assert_eq!(bytes.children[0].value.as_deref(), Some("Bytes"));
+        assert_eq!(bytes.children[1].encoded_in, EncodeMethod::Memcomparable);
+        assert_eq!((bytes.children[1].start, bytes.children[1].width), (1, 9));
+        assert_eq!(trace.children[1].children[1].value.as_deref(), Some("-7"));
+
+        assert!(encode_f64(-1.0) < encode_f64(0.0));
+        assert!(encode_f64(1.0) < encode_f64(2.5));
+
+        let mut code = Vec::new();
+        encode_compact_bytes(&mut code, b"xy");
+        code.extend_from_slice(&[VARINT_FLAG, 3]);
+        let (decoded, _) = decode_datums(&code).unwrap();
+        assert_eq!(decoded, vec![Datum::Bytes(b"xy".to_vec()), Datum::Int(-2)]);
+
+        assert!(decode_datums(&[DECIMAL_FLAG, 1]).is_err());
+        assert!(decode_datums(&[42]).is_err());
+    }
+}
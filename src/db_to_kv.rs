@@ -1,39 +1,237 @@
+use crate::chunk;
+use crate::keyspace::{KeyMode, Keyspace};
+use anyhow::{anyhow, bail};
 use std::convert::TryInto;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsValue;
 
 #[wasm_bindgen]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Record {
+    /// The API v2 keyspace the key belongs to, `None` if the key has no keyspace prefix.
+    pub keyspace_id: Option<u32>,
     // IMO these fields should be unsigned
     // But, TiDB is TiDB 🤷‍
     pub table_id: i64,
     pub row_id: i64,
 }
 
+impl Record {
+    pub fn to_bytes_rust(&self) -> anyhow::Result<Vec<u8>> {
+        encode_record_key_rust(self.keyspace_id, self.table_id, self.row_id)
+    }
+}
+
+#[wasm_bindgen]
+impl Record {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        self.to_bytes_rust()
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    pub keyspace_id: Option<u32>,
+    pub table_id: i64,
+    pub index_id: i64,
+    values: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl Index {
+    /// The encoded index column values following the index id.
+    #[wasm_bindgen(getter)]
+    pub fn values(&self) -> Vec<u8> {
+        self.values.clone()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_values(&mut self, values: Vec<u8>) {
+        self.values = values;
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        self.to_bytes_rust()
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+}
+
+impl Index {
+    pub fn to_bytes_rust(&self) -> anyhow::Result<Vec<u8>> {
+        encode_index_key_rust(self.keyspace_id, self.table_id, self.index_id, &self.values)
+    }
+}
+
+pub const TABLE_PREFIX: u8 = b't';
+pub const RECORD_PREFIX_SEP: &[u8] = b"_r";
+pub const INDEX_PREFIX_SEP: &[u8] = b"_i";
+
 const SIGN_MASK: u64 = 0x8000000000000000;
 
+/// Decode a memcomparable encoded i64.
+pub fn decode_i64(code: &[u8]) -> anyhow::Result<i64> {
+    let bytes: [u8; 8] = code
+        .get(..8)
+        .ok_or_else(|| anyhow!("need 8 bytes for an i64, got {}", code.len()))?
+        .try_into()?;
+    // who invent this evil encoding method?
+    Ok((u64::from_be_bytes(bytes) ^ SIGN_MASK) as i64)
+}
+
+/// Encode an i64 in the memcomparable format.
+pub fn encode_i64(n: i64) -> [u8; 8] {
+    (n as u64 ^ SIGN_MASK).to_be_bytes()
+}
+
+/// Parse the `t{table_id}` prefix shared by all table related keys,
+/// returns the table id and the remaining bytes.
+pub fn parse_table_prefix(code: &[u8]) -> anyhow::Result<(i64, &[u8])> {
+    if code.first() != Some(&TABLE_PREFIX) {
+        bail!("table key should start with 't'");
+    }
+    Ok((decode_i64(&code[1..])?, &code[9..]))
+}
+
+/// Strip the TxnKV keyspace prefix of API v2 from `code` if there is one.
+pub fn split_keyspace(code: &[u8]) -> (Option<u32>, &[u8]) {
+    match Keyspace::split(code) {
+        Some((keyspace, rest)) if keyspace.mode == KeyMode::Txn => (Some(keyspace.id), rest),
+        _ => (None, code),
+    }
+}
+
+/// Put the TxnKV keyspace prefix before `key`, if `keyspace_id` is set.
+pub fn with_keyspace(keyspace_id: Option<u32>, key: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    Ok(match keyspace_id {
+        Some(id) => Keyspace::new(KeyMode::Txn, id)?.apply(&key),
+        None => key,
+    })
+}
+
+pub fn encode_table_prefix(table_id: i64) -> Vec<u8> {
+    let mut result = vec![TABLE_PREFIX];
+    result.extend_from_slice(&encode_i64(table_id));
+    result
+}
+
+pub fn encode_record_key_rust(
+    keyspace_id: Option<u32>,
+    table_id: i64,
+    row_id: i64,
+) -> anyhow::Result<Vec<u8>> {
+    let mut result = encode_table_prefix(table_id);
+    result.extend_from_slice(RECORD_PREFIX_SEP);
+    result.extend_from_slice(&encode_i64(row_id));
+    with_keyspace(keyspace_id, result)
+}
+
+#[wasm_bindgen]
+pub fn encode_record_key(
+    keyspace_id: Option<u32>,
+    table_id: i64,
+    row_id: i64,
+) -> Result<Vec<u8>, JsValue> {
+    encode_record_key_rust(keyspace_id, table_id, row_id)
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+pub fn encode_index_key_rust(
+    keyspace_id: Option<u32>,
+    table_id: i64,
+    index_id: i64,
+    values: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let mut result = encode_table_prefix(table_id);
+    result.extend_from_slice(INDEX_PREFIX_SEP);
+    result.extend_from_slice(&encode_i64(index_id));
+    result.extend_from_slice(values);
+    with_keyspace(keyspace_id, result)
+}
+
+#[wasm_bindgen]
+pub fn encode_index_key(
+    keyspace_id: Option<u32>,
+    table_id: i64,
+    index_id: i64,
+    values: &[u8],
+) -> Result<Vec<u8>, JsValue> {
+    encode_index_key_rust(keyspace_id, table_id, index_id, values)
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Add the TxnKV keyspace prefix to a key, if `memcomparable` is set, `key` is treated as
+/// memcomparable encoded (and maybe with a timestamp), and the prefix is put inside the encoding.
+#[wasm_bindgen]
+pub fn add_keyspace_prefix(
+    keyspace_id: u32,
+    key: &[u8],
+    memcomparable: bool,
+) -> Result<Vec<u8>, JsValue> {
+    add_keyspace_prefix_rust(keyspace_id, key, memcomparable)
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+pub fn add_keyspace_prefix_rust(
+    keyspace_id: u32,
+    key: &[u8],
+    memcomparable: bool,
+) -> anyhow::Result<Vec<u8>> {
+    let keyspace = Keyspace::new(KeyMode::Txn, keyspace_id)?;
+    if !memcomparable {
+        return Ok(keyspace.apply(key));
+    }
+    let (raw, consumed) = chunk::decode_bytes_prefix(key)
+        .ok_or_else(|| anyhow!("key is not memcomparable encoded"))?;
+    let mut result = chunk::encode_bytes(&keyspace.apply(&raw));
+    result.extend_from_slice(&key[consumed..]);
+    Ok(result)
+}
+
+pub fn parse_record_rust(code: &[u8]) -> anyhow::Result<Record> {
+    let (keyspace_id, code) = split_keyspace(code);
+    let (table_id, rest) = parse_table_prefix(code)?;
+    if !rest.starts_with(RECORD_PREFIX_SEP) || rest.len() != 10 {
+        bail!("Invalid record bytes");
+    }
+    let row_id = decode_i64(&rest[2..])?;
+    Ok(Record {
+        keyspace_id,
+        table_id,
+        row_id,
+    })
+}
+
 #[wasm_bindgen]
 pub fn parse_record(code: &[u8]) -> Result<Record, JsValue> {
-    if code[0] != b't' || code[9] != b'_' || code[10] != b'r' {
-        Err(JsValue::from("Invalid record bytes"))
-    } else {
-        let table_id_bytes: [u8; 8] = code[1..=8]
-            .try_into()
-            .map_err(|_| JsValue::from("Invalid record bytes"))?;
-        // who invent this evil encoding method?
-        let table_id = (u64::from_be_bytes(table_id_bytes) ^ SIGN_MASK) as i64;
-        let row_id_bytes: [u8; 8] = code[11..]
-            .try_into()
-            .map_err(|_| JsValue::from("Invalid record bytes"))?;
-        let row_id = (u64::from_be_bytes(row_id_bytes) ^ SIGN_MASK) as i64;
-        Ok(Record { table_id, row_id })
+    parse_record_rust(code).map_err(|_| JsValue::from("Invalid record bytes"))
+}
+
+pub fn parse_index_rust(code: &[u8]) -> anyhow::Result<Index> {
+    let (keyspace_id, code) = split_keyspace(code);
+    let (table_id, rest) = parse_table_prefix(code)?;
+    if !rest.starts_with(INDEX_PREFIX_SEP) {
+        bail!("Invalid index bytes");
     }
+    let index_id = decode_i64(&rest[2..])?;
+    Ok(Index {
+        keyspace_id,
+        table_id,
+        index_id,
+        values: rest[10..].to_vec(),
+    })
+}
+
+#[wasm_bindgen]
+pub fn parse_index(code: &[u8]) -> Result<Index, JsValue> {
+    parse_index_rust(code).map_err(|_| JsValue::from("Invalid index bytes"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::keyspace::MAX_KEYSPACE_ID;
 
     #[test]
     fn test_parse_record() {
@@ -43,5 +241,58 @@ mod tests {
         let result = parse_record(&code).unwrap();
         assert_eq!(result.table_id, 53);
         assert_eq!(result.row_id, 1);
+        assert!(parse_record_rust(&code[..12]).is_err());
+        // Truncated keys are errors rather than panics.
+        for len in [1, 9, 10, 11] {
+            assert!(parse_record_rust(&code[..len]).is_err());
+            assert!(parse_index_rust(&code[..len]).is_err());
+        }
+        assert!(parse_record_rust(&[]).is_err());
+    }
+
+    #[test]
+    fn test_parse_index() {
+        let code = vec![
+            116, 128, 0, 0, 0, 0, 0, 0, 53, 95, 105, 128, 0, 0, 0, 0, 0, 0, 2, 3, 128, 0, 0, 0, 0,
+            0, 0, 7,
+        ];
+        let result = parse_index_rust(&code).unwrap();
+        assert_eq!(result.table_id, 53);
+        assert_eq!(result.index_id, 2);
+        assert_eq!(result.values, vec![3, 128, 0, 0, 0, 0, 0, 0, 7]);
+        assert_eq!(encode_i64(53), code[1..9]);
+        assert_eq!(result.to_bytes_rust().unwrap(), code);
+    }
+
+    #[test]
+    fn test_keyspace() {
+        let code = encode_record_key_rust(Some(7), 53, 1).unwrap();
+        assert_eq!(&code[..4], b"x\x00\x00\x07");
+        let result = parse_record_rust(&code).unwrap();
+        assert_eq!(result.keyspace_id, Some(7));
+        assert_eq!((result.table_id, result.row_id), (53, 1));
+        assert_eq!(result.to_bytes_rust().unwrap(), code);
+        // The keyspace id only has 3 bytes.
+        assert!(encode_record_key_rust(Some(MAX_KEYSPACE_ID + 1), 53, 1).is_err());
+        assert!(encode_index_key_rust(Some(u32::MAX), 53, 2, &[]).is_err());
+        let record = Record {
+            keyspace_id: Some(1 << 24 | 7),
+            ..result
+        };
+        assert!(record.to_bytes_rust().is_err());
+
+        let index = encode_index_key_rust(None, 53, 2, &[3, 128, 0, 0, 0, 0, 0, 0, 7]).unwrap();
+        let with_keyspace = add_keyspace_prefix_rust(7, &index, false).unwrap();
+        assert_eq!(
+            parse_index_rust(&with_keyspace).unwrap().keyspace_id,
+            Some(7)
+        );
+
+        let mut encoded = chunk::encode_bytes(&index);
+        encoded.extend_from_slice(&[0xff; 8]);
+        let with_keyspace = add_keyspace_prefix_rust(7, &encoded, true).unwrap();
+        let (raw, consumed) = chunk::decode_bytes_prefix(&with_keyspace).unwrap();
+        assert_eq!(raw, [b"x\x00\x00\x07".to_vec(), index].concat());
+        assert_eq!(&with_keyspace[consumed..], &[0xff; 8]);
     }
 }
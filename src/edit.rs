@@ -0,0 +1,469 @@
+//! Take apart a key or value, change some of its fields, and put it back into bytes.
+//!
+//! Fields are addressed by name and set from strings, eg. setting `table_id` to `54`
+//! on `data(encoded(t53_r1)@10)` gives the bytes of `data(encoded(t54_r1)@10)`.
+use crate::chunk;
+use crate::db_to_kv::{self, Index, Record};
+use crate::explain;
+use crate::keyspace::{KeyMode, Keyspace};
+use crate::local::DATA_PREFIX;
+use crate::lock::{Lock, LockType};
+use crate::mvcc::{Key, TimeStamp, Write, WriteType, CF_LOCK, CF_WRITE, SHORT_VALUE_MAX_LEN};
+use crate::output::{self, OutputFormat};
+use anyhow::{anyhow, bail};
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Editable {
+    RecordKey(Record),
+    IndexKey(Index),
+    /// A key we cannot tell the structure of, which can only be replaced as a whole.
+    RawKey(Vec<u8>),
+    /// A memcomparable encoded key, maybe with a timestamp and the data prefix of TiKV.
+    MvccKey {
+        data_prefix: bool,
+        user_key: Box<Editable>,
+        ts: Option<TimeStamp>,
+    },
+    Lock(Lock),
+    Write(Write),
+}
+
+/// A field which can be changed, with its current value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EditableField {
+    pub name: String,
+    pub value: String,
+}
+
+fn field(name: &str, value: impl fmt::Display) -> EditableField {
+    EditableField {
+        name: name.to_string(),
+        value: value.to_string(),
+    }
+}
+
+fn parse_number<T: FromStr>(name: &str, value: &str) -> anyhow::Result<T> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("invalid value {:?} for {}", value, name))
+}
+
+fn parse_bool(name: &str, value: &str) -> anyhow::Result<bool> {
+    parse_number(name, value)
+}
+
+/// Bytes are given either escaped and quoted like `"t\x80"`, or in hex.
+fn parse_bytes(value: &str) -> anyhow::Result<Vec<u8>> {
+    let value = value.trim();
+    if value.starts_with('"') {
+        explain::unescape(value)
+    } else {
+        Ok(hex::decode(value)?)
+    }
+}
+
+/// Optional fields are cleared by an empty string or `none`.
+fn parse_optional<T>(
+    value: &str,
+    parse: impl FnOnce(&str) -> anyhow::Result<T>,
+) -> anyhow::Result<Option<T>> {
+    match value.trim() {
+        "" | "none" => Ok(None),
+        value => parse(value).map(Some),
+    }
+}
+
+fn parse_keyspace_id(name: &str, value: &str) -> anyhow::Result<Option<u32>> {
+    parse_optional(value, |v| {
+        Ok(Keyspace::new(KeyMode::Txn, parse_number(name, v)?)?.id)
+    })
+}
+
+/// The length of a short value is encoded in a byte.
+fn parse_short_value(value: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let value = parse_optional(value, parse_bytes)?;
+    if let Some(value) = &value {
+        if value.len() > SHORT_VALUE_MAX_LEN {
+            bail!(
+                "short value of {} bytes is longer than {}",
+                value.len(),
+                SHORT_VALUE_MAX_LEN
+            );
+        }
+    }
+    Ok(value)
+}
+
+fn optional_bytes(value: &Option<Vec<u8>>) -> String {
+    value
+        .as_ref()
+        .map_or_else(|| "none".to_string(), |v| explain::escape(v))
+}
+
+fn optional_number(value: Option<impl fmt::Display>) -> String {
+    value.map_or_else(|| "none".to_string(), |v| v.to_string())
+}
+
+fn parse_lock_type(value: &str) -> anyhow::Result<LockType> {
+    Ok(match value.trim() {
+        "Put" => LockType::Put,
+        "Delete" => LockType::Delete,
+        "Lock" => LockType::Lock,
+        "Pessimistic" => LockType::Pessimistic,
+        _ => bail!("invalid lock type {:?}", value),
+    })
+}
+
+fn parse_write_type(value: &str) -> anyhow::Result<WriteType> {
+    Ok(match value.trim() {
+        "Put" => WriteType::Put,
+        "Delete" => WriteType::Delete,
+        "Lock" => WriteType::Lock,
+        "Rollback" => WriteType::Rollback,
+        _ => bail!("invalid write type {:?}", value),
+    })
+}
+
+impl Editable {
+    /// Parse a key, keys with a structure we don't know are kept as `RawKey`.
+    pub fn parse_key(b: &[u8]) -> Editable {
+        if let Ok(record) = db_to_kv::parse_record_rust(b) {
+            return Editable::RecordKey(record);
+        }
+        if let Ok(index) = db_to_kv::parse_index_rust(b) {
+            return Editable::IndexKey(index);
+        }
+        let (data_prefix, encoded) = match b.split_first() {
+            Some((&DATA_PREFIX, rest)) if chunk::decode_bytes_prefix(rest).is_some() => {
+                (true, rest)
+            }
+            _ => (false, b),
+        };
+        if let Some((raw, consumed)) = chunk::decode_bytes_prefix(encoded) {
+            let ts = match encoded.len() - consumed {
+                0 => Some(None),
+                8 => Key::from_encoded(encoded.to_vec())
+                    .decode_ts()
+                    .ok()
+                    .map(Some),
+                _ => None,
+            };
+            if let Some(ts) = ts {
+                return Editable::MvccKey {
+                    data_prefix,
+                    user_key: Box::new(Editable::parse_key(&raw)),
+                    ts,
+                };
+            }
+        }
+        Editable::RawKey(b.to_vec())
+    }
+
+    /// Parse a value of the lock or write CF.
+    pub fn parse_value(cf: &str, b: &[u8]) -> anyhow::Result<Editable> {
+        match cf {
+            CF_LOCK => Ok(Editable::Lock(Lock::parse_rust(b)?)),
+            CF_WRITE => Ok(Editable::Write(Write::parse_rust(b)?)),
+            _ => bail!("values in CF {:?} have no structure to edit", cf),
+        }
+    }
+
+    pub fn fields(&self) -> Vec<EditableField> {
+        match self {
+            Editable::RecordKey(record) => vec![
+                field("keyspace_id", optional_number(record.keyspace_id)),
+                field("table_id", record.table_id),
+                field("row_id", record.row_id),
+            ],
+            Editable::IndexKey(index) => vec![
+                field("keyspace_id", optional_number(index.keyspace_id)),
+                field("table_id", index.table_id),
+                field("index_id", index.index_id),
+                field("values", explain::escape(&index.values())),
+            ],
+            Editable::RawKey(key) => vec![field("key", explain::escape(key))],
+            Editable::MvccKey {
+                data_prefix,
+                user_key,
+                ts,
+            } => {
+                let mut fields = vec![field("data_prefix", data_prefix)];
+                fields.extend(user_key.fields());
+                fields.push(field("ts", optional_number(*ts)));
+                fields
+            }
+            Editable::Lock(lock) => vec![
+                field("lock_type", format!("{:?}", lock.lock_type)),
+                field("primary", explain::escape(lock.primary_key())),
+                field("start_ts", lock.ts),
+                field("ttl", lock.ttl),
+                field("short_value", optional_bytes(&lock.short_value())),
+                field("for_update_ts", lock.for_update_ts),
+                field("txn_size", lock.txn_size),
+                field("min_commit_ts", lock.min_commit_ts),
+                field("txn_source", lock.txn_source),
+                field("generation", lock.generation),
+            ],
+            Editable::Write(write) => vec![
+                field("write_type", format!("{:?}", write.write_type)),
+                field("start_ts", write.start_ts),
+                field("short_value", optional_bytes(&write.short_value())),
+                field("overlapped_rollback", write.has_overlapped_rollback),
+                field("gc_fence", optional_number(write.gc_fence)),
+                field("last_change_ts", write.last_change_ts),
+                field("versions_to_last_change", write.versions_to_last_change),
+                field("txn_source", write.txn_source),
+            ],
+        }
+    }
+
+    /// Change the field called `name` to `value`, the same format as `fields` shows.
+    pub fn set(&mut self, name: &str, value: &str) -> anyhow::Result<()> {
+        match (self, name) {
+            (Editable::RecordKey(record), "keyspace_id") => {
+                record.keyspace_id = parse_keyspace_id(name, value)?;
+            }
+            (Editable::RecordKey(record), "table_id") => {
+                record.table_id = parse_number(name, value)?
+            }
+            (Editable::RecordKey(record), "row_id") => record.row_id = parse_number(name, value)?,
+            (Editable::IndexKey(index), "keyspace_id") => {
+                index.keyspace_id = parse_keyspace_id(name, value)?;
+            }
+            (Editable::IndexKey(index), "table_id") => index.table_id = parse_number(name, value)?,
+            (Editable::IndexKey(index), "index_id") => index.index_id = parse_number(name, value)?,
+            (Editable::IndexKey(index), "values") => index.set_values(parse_bytes(value)?),
+            (Editable::RawKey(key), "key") => *key = parse_bytes(value)?,
+            (Editable::MvccKey { data_prefix, .. }, "data_prefix") => {
+                *data_prefix = parse_bool(name, value)?
+            }
+            (Editable::MvccKey { ts, .. }, "ts") => {
+                *ts = parse_optional(value, |v| parse_number(name, v).map(TimeStamp))?;
+            }
+            (Editable::MvccKey { user_key, .. }, _) => user_key.set(name, value)?,
+            (Editable::Lock(lock), "lock_type") => lock.lock_type = parse_lock_type(value)?,
+            (Editable::Lock(lock), "primary") => lock.set_primary(parse_bytes(value)?),
+            (Editable::Lock(lock), "start_ts") => lock.ts = TimeStamp(parse_number(name, value)?),
+            (Editable::Lock(lock), "ttl") => lock.ttl = parse_number(name, value)?,
+            (Editable::Lock(lock), "short_value") => {
+                lock.set_short_value(parse_short_value(value)?)
+            }
+            (Editable::Lock(lock), "for_update_ts") => {
+                lock.for_update_ts = TimeStamp(parse_number(name, value)?)
+            }
+            (Editable::Lock(lock), "txn_size") => lock.txn_size = parse_number(name, value)?,
+            (Editable::Lock(lock), "min_commit_ts") => {
+                lock.min_commit_ts = TimeStamp(parse_number(name, value)?)
+            }
+            (Editable::Lock(lock), "txn_source") => lock.txn_source = parse_number(name, value)?,
+            (Editable::Lock(lock), "generation") => lock.generation = parse_number(name, value)?,
+            (Editable::Write(write), "write_type") => write.write_type = parse_write_type(value)?,
+            (Editable::Write(write), "start_ts") => {
+                write.start_ts = TimeStamp(parse_number(name, value)?)
+            }
+            (Editable::Write(write), "short_value") => {
+                write.set_short_value(parse_short_value(value)?)
+            }
+            (Editable::Write(write), "overlapped_rollback") => {
+                write.has_overlapped_rollback = parse_bool(name, value)?
+            }
+            (Editable::Write(write), "gc_fence") => {
+                write.gc_fence = parse_optional(value, |v| parse_number(name, v).map(TimeStamp))?
+            }
+            (Editable::Write(write), "last_change_ts") => {
+                write.last_change_ts = TimeStamp(parse_number(name, value)?)
+            }
+            (Editable::Write(write), "versions_to_last_change") => {
+                write.versions_to_last_change = parse_number(name, value)?
+            }
+            (Editable::Write(write), "txn_source") => write.txn_source = parse_number(name, value)?,
+            (_, name) => bail!("no field called {:?}", name),
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(match self {
+            Editable::RecordKey(record) => record.to_bytes_rust()?,
+            Editable::IndexKey(index) => index.to_bytes_rust()?,
+            Editable::RawKey(key) => key.clone(),
+            Editable::MvccKey {
+                data_prefix,
+                user_key,
+                ts,
+            } => {
+                let mut key = Key::from_raw(&user_key.to_bytes()?);
+                if let Some(ts) = ts {
+                    key = key.append_ts(*ts);
+                }
+                let mut result = if *data_prefix {
+                    vec![DATA_PREFIX]
+                } else {
+                    vec![]
+                };
+                result.extend_from_slice(key.as_encoded());
+                result
+            }
+            Editable::Lock(lock) => lock.to_bytes(),
+            Editable::Write(write) => write.to_bytes(),
+        })
+    }
+
+    /// The bytes in any of the output formats.
+    pub fn format(&self, format: OutputFormat) -> anyhow::Result<String> {
+        Ok(output::format_rust(&self.to_bytes()?, format))
+    }
+}
+
+impl fmt::Display for Editable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Editable::Lock(lock) => write!(f, "{}", lock),
+            Editable::Write(write) => write!(f, "{}", write),
+            key => match key.to_bytes() {
+                Ok(b) => write!(f, "{}", explain::explain_key(&b)),
+                Err(e) => write!(f, "{}", e),
+            },
+        }
+    }
+}
+
+/// `Editable` for the web UI.
+#[wasm_bindgen]
+pub struct Editor {
+    inner: Editable,
+}
+
+#[wasm_bindgen]
+impl Editor {
+    pub fn from_key(b: &[u8]) -> Editor {
+        Editor {
+            inner: Editable::parse_key(b),
+        }
+    }
+
+    pub fn from_value(cf: &str, b: &[u8]) -> Result<Editor, JsValue> {
+        Editable::parse_value(cf, b)
+            .map(|inner| Editor { inner })
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    pub fn fields(&self) -> JsValue {
+        crate::utils::to_js_value(&self.inner.fields())
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), JsValue> {
+        self.inner
+            .set(name, value)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        self.inner
+            .to_bytes()
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    pub fn format(&self, format: OutputFormat) -> Result<String, JsValue> {
+        self.inner
+            .format(format)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    pub fn explain(&self) -> String {
+        self.inner.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_mvcc_key() {
+        let mut key = vec![DATA_PREFIX];
+        key.extend(
+            Key::from_raw(&db_to_kv::encode_record_key_rust(None, 53, 1).unwrap())
+                .append_ts(TimeStamp(10))
+                .into_encoded(),
+        );
+        let mut editable = Editable::parse_key(&key);
+        assert_eq!(editable.to_bytes().unwrap(), key);
+        let names: Vec<_> = editable.fields().into_iter().map(|f| f.name).collect();
+        assert_eq!(
+            names,
+            vec!["data_prefix", "keyspace_id", "table_id", "row_id", "ts"]
+        );
+        editable.set("table_id", "54").unwrap();
+        editable.set("row_id", "-3").unwrap();
+        editable.set("ts", "20").unwrap();
+        assert_eq!(editable.to_string(), "data(encoded(t54_r-3)@20)");
+        editable.set("keyspace_id", "7").unwrap();
+        editable.set("data_prefix", "false").unwrap();
+        editable.set("ts", "none").unwrap();
+        assert_eq!(editable.to_string(), "encoded(keyspace 7: t54_r-3)");
+        assert!(editable.set("keyspace_id", "16777216").is_err());
+        assert_eq!(
+            editable.format(OutputFormat::Escaped).unwrap(),
+            "\"x\\x00\\x00\\x07t\\x80\\x00\\x00\\xff\\x00\\x00\\x00\\x006_r\\x7f\\xff\\xff\\xff\\xff\\xff\\xff\\xff\\xfd\\x00\\xfe\""
+        );
+        assert!(editable.set("index_id", "1").is_err());
+        assert!(editable.set("table_id", "x").is_err());
+
+        let mut index =
+            Editable::parse_key(&db_to_kv::encode_index_key_rust(None, 53, 2, &[]).unwrap());
+        index
+            .set(
+                "values",
+                "\"\\x03\\x80\\x00\\x00\\x00\\x00\\x00\\x00\\x07\"",
+            )
+            .unwrap();
+        assert_eq!(index.to_string(), "t53_i2(7)");
+        index.set("values", "").unwrap();
+        assert_eq!(index.to_string(), "t53_i2");
+
+        let mut raw = Editable::parse_key(b"abc");
+        raw.set("key", "616263ff").unwrap();
+        assert_eq!(raw.to_bytes().unwrap(), b"abc\xff");
+    }
+
+    #[test]
+    fn test_edit_values() {
+        let write = Write::parse_rust(&[b'P', 10, b'v', 1, b'x']).unwrap();
+        let mut editable = Editable::parse_value(CF_WRITE, &write.to_bytes()).unwrap();
+        editable.set("start_ts", "11").unwrap();
+        editable.set("short_value", "\"yz\"").unwrap();
+        editable.set("gc_fence", "30").unwrap();
+        assert_eq!(
+            editable.to_string(),
+            "Put, start_ts: 11, short_value: \"yz\", gc_fence: 30"
+        );
+        let reparsed = Write::parse_rust(&editable.to_bytes().unwrap()).unwrap();
+        assert_eq!(reparsed.gc_fence, Some(TimeStamp(30)));
+        // The length of a short value must fit in a byte.
+        let long = hex::encode([b'v'; SHORT_VALUE_MAX_LEN + 1]);
+        assert!(editable.set("short_value", &long).is_err());
+        editable.set("short_value", &long[2..]).unwrap();
+        // The short value flag `v` and the length 255.
+        assert!(editable
+            .format(OutputFormat::HexLower)
+            .unwrap()
+            .contains(&format!("76ff{}", &long[2..])));
+
+        let lock = Lock::new(LockType::Put, b"pk".to_vec(), TimeStamp(100), 3000);
+        let mut editable = Editable::parse_value(CF_LOCK, &lock.to_bytes()).unwrap();
+        editable.set("lock_type", "Pessimistic").unwrap();
+        editable.set("primary", "\"k\"").unwrap();
+        editable.set("for_update_ts", "101").unwrap();
+        let reparsed = Lock::parse_rust(&editable.to_bytes().unwrap()).unwrap();
+        assert_eq!(reparsed.lock_type, LockType::Pessimistic);
+        assert_eq!(reparsed.primary_key(), b"k");
+        assert_eq!(reparsed.for_update_ts, TimeStamp(101));
+        assert!(Editable::parse_value("default", b"v").is_err());
+    }
+}
@@ -0,0 +1,393 @@
+//! Guess what some key bytes stand for and describe them in a compact form.
+//!
+//! The explanation nests the way the key was built, eg. a write CF key of a row
+//! is explained as `data(encoded(t53_r1)@424659320104550401)`.
+//! The same nesting is kept in the `ParsingTrace` of the key, so one can see
+//! which bytes make up each part.
+use crate::chunk;
+use crate::datum::{self, DatumList};
+use crate::db_to_kv::{self, INDEX_PREFIX_SEP, RECORD_PREFIX_SEP, TABLE_PREFIX};
+use crate::keyspace::{KeyMode, Keyspace, KEYSPACE_PREFIX_LEN};
+use crate::local::{LocalKey, DATA_PREFIX, LOCAL_PREFIX};
+use crate::meta::{MetaKey, META_PREFIX};
+use crate::trace::{EncodeMethod, ParsingTrace, TracingReader};
+use anyhow::{anyhow, bail};
+use serde::Serialize;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+/// Some key bytes together with the explanation of them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExplainedKey {
+    pub key: Vec<u8>,
+    pub explanation: String,
+}
+
+impl ExplainedKey {
+    pub fn new(key: &[u8]) -> Self {
+        ExplainedKey {
+            key: key.to_vec(),
+            explanation: explain_key(key),
+        }
+    }
+}
+
+impl Default for ExplainedKey {
+    fn default() -> Self {
+        ExplainedKey::new(&[])
+    }
+}
+
+impl fmt::Display for ExplainedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.explanation)
+    }
+}
+
+/// Escape `b` the way a rust byte string literal would look like.
+pub fn escape(b: &[u8]) -> String {
+    let mut result = String::with_capacity(b.len() + 2);
+    result.push('"');
+    for &c in b {
+        result.extend(std::ascii::escape_default(c).map(char::from));
+    }
+    result.push('"');
+    result
+}
+
+/// The reverse of `escape`, the quotes around `s` are optional.
+pub fn unescape(s: &str) -> anyhow::Result<Vec<u8>> {
+    let s = s
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s);
+    let mut result = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes().peekable();
+    while let Some(c) = bytes.next() {
+        if c != b'\\' {
+            result.push(c);
+            continue;
+        }
+        let escaped = bytes
+            .next()
+            .ok_or_else(|| anyhow!("dangling '\\' at the end"))?;
+        result.push(match escaped {
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            // `\0`, or an octal escape like `\377` as Go and `tikv-ctl` print.
+            b'0'..=b'7' => {
+                let mut n = (escaped - b'0') as u32;
+                for _ in 0..2 {
+                    match bytes.peek() {
+                        Some(&digit @ b'0'..=b'7') => {
+                            n = n * 8 + (digit - b'0') as u32;
+                            bytes.next();
+                        }
+                        _ => break,
+                    }
+                }
+                if n > u8::MAX as u32 {
+                    bail!("invalid octal escape '\\{:o}'", n);
+                }
+                n as u8
+            }
+            b'\\' | b'\'' | b'"' => escaped,
+            b'x' => {
+                let digits = [
+                    bytes.next().unwrap_or_default(),
+                    bytes.next().unwrap_or_default(),
+                ];
+                let digits = std::str::from_utf8(&digits)?;
+                u8::from_str_radix(digits, 16)
+                    .map_err(|_| anyhow!("invalid escape '\\x{}'", digits))?
+            }
+            _ => bail!("unknown escape '\\{}'", escaped as char),
+        });
+    }
+    Ok(result)
+}
+
+fn value_of(trace: &ParsingTrace) -> &str {
+    trace.value.as_deref().unwrap_or_default()
+}
+
+fn trace_table_key(key: &[u8]) -> Option<ParsingTrace> {
+    if key.first() != Some(&TABLE_PREFIX) {
+        return None;
+    }
+    let mut reader = TracingReader::new(key);
+    reader
+        .read_fixed(1, "table_prefix", EncodeMethod::Literal)
+        .ok()?;
+    reader.annotate("t");
+    let table_id = db_to_kv::decode_i64(
+        reader
+            .read_fixed(8, "table_id", EncodeMethod::ComparableI64)
+            .ok()?,
+    )
+    .ok()?;
+    reader.annotate(table_id);
+    let mut explanation = format!("t{}", table_id);
+    let rest = reader.rest();
+    if rest.is_empty() {
+        return Some(reader.finish("table_key", explanation));
+    }
+    let separator = if rest.starts_with(RECORD_PREFIX_SEP) {
+        RECORD_PREFIX_SEP
+    } else if rest.starts_with(INDEX_PREFIX_SEP) {
+        INDEX_PREFIX_SEP
+    } else {
+        return None;
+    };
+    reader
+        .read_fixed(2, "separator", EncodeMethod::Literal)
+        .ok()?;
+    reader.annotate(String::from_utf8_lossy(separator));
+    explanation.push_str(&String::from_utf8_lossy(separator));
+    if reader.rest().is_empty() {
+        return Some(reader.finish("table_key", explanation));
+    }
+    if separator == RECORD_PREFIX_SEP {
+        if reader.rest().len() == 8 {
+            let row_id = db_to_kv::decode_i64(reader.rest()).ok()?;
+            reader
+                .read_fixed(8, "row_id", EncodeMethod::ComparableI64)
+                .ok()?;
+            reader.annotate(row_id);
+            explanation.push_str(&row_id.to_string());
+        } else {
+            // A clustered index, the handle is made of the primary key columns.
+            let (datums, mut trace) = datum::decode_datums(reader.rest()).ok()?;
+            trace.field = "handle".to_string();
+            reader.push(trace);
+            explanation.push_str(&format!("({})", DatumList(&datums)));
+        }
+    } else {
+        let index_id = db_to_kv::decode_i64(reader.rest()).ok()?;
+        reader
+            .read_fixed(8, "index_id", EncodeMethod::ComparableI64)
+            .ok()?;
+        reader.annotate(index_id);
+        explanation.push_str(&index_id.to_string());
+        let values = reader.rest();
+        if !values.is_empty() {
+            match datum::decode_datums(values) {
+                Ok((datums, mut trace)) => {
+                    trace.field = "values".to_string();
+                    reader.push(trace);
+                    explanation.push_str(&format!("({})", DatumList(&datums)));
+                }
+                Err(_) => {
+                    reader.read_rest("values");
+                    explanation.push_str(&format!(" values: {}", hex::encode(values)));
+                }
+            }
+        }
+    }
+    Some(reader.finish("table_key", explanation))
+}
+
+/// Keys TiDB writes, ie. table keys and meta keys.
+fn trace_tidb_key(key: &[u8]) -> Option<ParsingTrace> {
+    match key.first() {
+        Some(&TABLE_PREFIX) => trace_table_key(key),
+        Some(&META_PREFIX) => MetaKey::parse_with_trace(key).ok().map(|(_, trace)| trace),
+        _ => None,
+    }
+}
+
+fn trace_encoded_key(key: &[u8]) -> Option<ParsingTrace> {
+    let (decoded, mut encoded) = chunk::trace_bytes_prefix(key)?;
+    let inner = trace_key(&decoded);
+    encoded.field = "encoded_key".to_string();
+    encoded.value = Some(format!("encoded({})", value_of(&inner)));
+    let encoded = encoded.with_inner(inner);
+    match key.len() - encoded.width {
+        0 => Some(encoded),
+        8 => {
+            let ts = !crate::endian::big::decode_u64(&key[encoded.width..]);
+            let explanation = format!("{}@{}", value_of(&encoded), ts);
+            let ts = ParsingTrace::leaf(encoded.width, 8, "ts", EncodeMethod::DescendingU64)
+                .with_value(ts);
+            Some(ParsingTrace::node("mvcc_key", vec![encoded, ts]).with_value(explanation))
+        }
+        _ => None,
+    }
+}
+
+/// Tell what `key` is and how every part of it is encoded, down to the bytes
+/// inside memcomparable encoded keys.
+pub fn trace_key(key: &[u8]) -> ParsingTrace {
+    if key.first() == Some(&LOCAL_PREFIX) {
+        if let Ok(local_key) = LocalKey::parse(key) {
+            return local_key.trace();
+        }
+    }
+    if key.first() == Some(&DATA_PREFIX) && key.len() > 1 {
+        if let Some(inner) = trace_encoded_key(&key[1..]) {
+            let explanation = format!("data({})", value_of(&inner));
+            let prefix = ParsingTrace::leaf(0, 1, "data_prefix", EncodeMethod::Literal)
+                .with_value(escape(&key[..1]));
+            return ParsingTrace::node("data_key", vec![prefix, inner.shift(1)])
+                .with_value(explanation);
+        }
+    }
+    if let Some(trace) = trace_tidb_key(key) {
+        return trace;
+    }
+    if let Some((keyspace, rest)) = Keyspace::split(key) {
+        if keyspace.mode == KeyMode::Txn {
+            if let Some(inner) = trace_tidb_key(rest) {
+                let explanation = format!("keyspace {}: {}", keyspace.id, value_of(&inner));
+                return ParsingTrace::node(
+                    "key",
+                    vec![keyspace.trace(), inner.shift(KEYSPACE_PREFIX_LEN)],
+                )
+                .with_value(explanation);
+            }
+        }
+    }
+    if let Some(trace) = trace_encoded_key(key) {
+        return trace;
+    }
+    ParsingTrace::leaf(0, key.len(), "key", EncodeMethod::Bytes).with_value(escape(key))
+}
+
+/// Describe what `key` is, falls back to the escaped bytes if we cannot tell.
+pub fn explain_key(key: &[u8]) -> String {
+    trace_key(key).value.unwrap_or_default()
+}
+
+#[wasm_bindgen]
+pub fn explain(code: &[u8]) -> String {
+    explain_key(code)
+}
+
+#[wasm_bindgen]
+pub fn explain_trace(code: &[u8]) -> ParsingTrace {
+    trace_key(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explain_key() {
+        let record = vec![
+            116, 128, 0, 0, 0, 0, 0, 0, 53, 95, 114, 128, 0, 0, 0, 0, 0, 0, 1,
+        ];
+        let encoded = chunk::encode_bytes(&record);
+        let mut data_key = vec![b'z'];
+        data_key.extend_from_slice(&encoded);
+        data_key.extend_from_slice(&(!424659320104550401u64).to_be_bytes());
+        let cases = vec![
+            (record.clone(), "t53_r1"),
+            (record[..9].to_vec(), "t53"),
+            (record[..11].to_vec(), "t53_r"),
+            (encoded, "encoded(t53_r1)"),
+            (data_key, "data(encoded(t53_r1)@424659320104550401)"),
+            (
+                vec![1, 2, 0, 0, 0, 0, 0, 0, 0, 42, 3],
+                "region 42 apply state",
+            ),
+            (b"hello\x00".to_vec(), "\"hello\\x00\""),
+            (
+                chunk::encode_bytes(&db_to_kv::encode_record_key_rust(Some(7), 53, 1).unwrap()),
+                "encoded(keyspace 7: t53_r1)",
+            ),
+        ];
+        for (key, expected) in cases {
+            assert_eq!(explain_key(&key), expected);
+        }
+    }
+
+    #[test]
+    fn test_unescape() {
+        let b = b"t\x80\x00\"\\\n\0z".to_vec();
+        assert_eq!(unescape(&escape(&b)).unwrap(), b);
+        assert_eq!(unescape("ab\\x4a").unwrap(), b"abJ");
+        assert!(unescape("ab\\xzz").is_err());
+        assert!(unescape("ab\\").is_err());
+        assert_eq!(
+            unescape("t\\200\\000\\377\\0").unwrap(),
+            b"t\x80\x00\xff\x00"
+        );
+        assert!(unescape("\\400").is_err());
+    }
+
+    #[test]
+    fn test_explain_index_values() {
+        let values =
+            datum::encode_datums(&[datum::Datum::Bytes(b"abc".to_vec()), datum::Datum::Int(7)]);
+        let key = db_to_kv::encode_index_key_rust(None, 53, 2, &values).unwrap();
+        assert_eq!(explain_key(&key), "t53_i2(\"abc\",7)");
+        let key = db_to_kv::encode_index_key_rust(None, 53, 2, &[42, 1]).unwrap();
+        assert_eq!(explain_key(&key), "t53_i2 values: 2a01");
+        let mut key = db_to_kv::encode_table_prefix(53);
+        key.extend_from_slice(RECORD_PREFIX_SEP);
+        key.extend_from_slice(&datum::encode_datums(&[datum::Datum::Bytes(
+            b"pk".to_vec(),
+        )]));
+        assert_eq!(explain_key(&key), "t53_r(\"pk\")");
+    }
+
+    #[test]
+    fn test_trace_key() {
+        let values = datum::encode_datums(&[datum::Datum::Bytes(b"abc".to_vec())]);
+        let index = db_to_kv::encode_index_key_rust(None, 53, 2, &values).unwrap();
+        let mut key = vec![DATA_PREFIX];
+        key.extend_from_slice(&chunk::encode_bytes(&index));
+        key.extend_from_slice(&(!10u64).to_be_bytes());
+        let trace = trace_key(&key);
+        assert_eq!(
+            trace.value.as_deref(),
+            Some("data(encoded(t53_i2(\"abc\"))@10)")
+        );
+        assert_eq!((trace.start, trace.width), (0, key.len()));
+
+        let mvcc_key = &trace.children[1];
+        assert_eq!(mvcc_key.field, "mvcc_key");
+        let (encoded, ts) = (&mvcc_key.children[0], &mvcc_key.children[1]);
+        assert_eq!((encoded.start, encoded.width), (1, 36));
+        assert_eq!(encoded.encoded_in, EncodeMethod::Memcomparable);
+        assert_eq!((ts.start, ts.value.as_deref()), (37, Some("10")));
+
+        // The decoded table key, with offsets in the decoded bytes.
+        let table_key = encoded.inner.as_ref().unwrap();
+        assert_eq!((table_key.start, table_key.width), (0, index.len()));
+        let leaves: Vec<_> = table_key
+            .leaves()
+            .into_iter()
+            .map(|(path, t)| (path, t.start, t.width))
+            .collect();
+        assert_eq!(
+            leaves,
+            vec![
+                ("table_prefix".to_string(), 0, 1),
+                ("table_id".to_string(), 1, 8),
+                ("separator".to_string(), 9, 2),
+                ("index_id".to_string(), 11, 8),
+                ("values.datum.flag".to_string(), 19, 1),
+                ("values.datum.encoded.group.data".to_string(), 20, 3),
+                ("values.datum.encoded.group.padding".to_string(), 23, 5),
+                ("values.datum.encoded.group.marker".to_string(), 28, 1),
+            ]
+        );
+
+        let mut depth = 0;
+        trace.walk(&mut |_, d| depth = depth.max(d));
+        assert_eq!(depth, 8);
+        let json = serde_json::to_value(&trace).unwrap();
+        assert_eq!(json["children"][0]["field"], "data_prefix");
+        assert_eq!(
+            json["children"][1]["children"][0]["encoded_in"],
+            "Memcomparable"
+        );
+
+        let trace = trace_key(&db_to_kv::encode_record_key_rust(Some(7), 53, 1).unwrap());
+        assert_eq!(trace.children[0].field, "keyspace");
+        assert_eq!(trace.children[1].start, 4);
+    }
+}
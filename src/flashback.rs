@@ -0,0 +1,242 @@
+//! Find out what `FLASHBACK CLUSTER TO TIMESTAMP` restores on a key.
+//!
+//! TiKV first rolls back the locks, and puts a flashback lock on the first key of each
+//! region. Then every key changed after the version gets a new record, committed by the
+//! flashback, which is a copy of the version to restore, or a `Delete` if there was no
+//! version then. The records carry no marker, they are told by the start ts of the
+//! flashback, which is the ts of its lock.
+use crate::explain;
+use crate::mvcc::{Key, TimeStamp, Write, WriteType};
+use crate::store;
+use crate::tikv_ctl::{self, MvccHistory, MvccWrite};
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Restore {
+    /// The key is not changed after the version.
+    Unchanged,
+    /// The version to restore.
+    Put(MvccWrite),
+    /// There is no version of the key then, or it was deleted.
+    Delete,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Flashback {
+    pub version: TimeStamp,
+    pub restore: Restore,
+    pub steps: Vec<String>,
+}
+
+impl Flashback {
+    /// The record the flashback started at `start_ts` commits on the key. A `Put`
+    /// without short value comes with the value copied to the default CF at `start_ts`.
+    pub fn write(&self, start_ts: TimeStamp) -> Option<Write> {
+        match &self.restore {
+            Restore::Unchanged => None,
+            Restore::Put(version) => Some(Write::new(
+                WriteType::Put,
+                start_ts,
+                version.write.short_value_ref().cloned(),
+            )),
+            Restore::Delete => Some(Write::new(WriteType::Delete, start_ts, None)),
+        }
+    }
+}
+
+impl fmt::Display for Flashback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{}", step)?;
+        }
+        match &self.restore {
+            Restore::Unchanged => write!(f, "=> unchanged"),
+            Restore::Put(version) => write!(
+                f,
+                "=> restore write@{}: {}",
+                version.commit_ts, version.write
+            ),
+            Restore::Delete => write!(f, "=> delete"),
+        }
+    }
+}
+
+/// Work out what flashback to `version` restores from the history of the key.
+pub fn flashback_key(history: &MvccHistory, version: TimeStamp) -> Flashback {
+    let mut steps = Vec::new();
+    let key = Key::from_raw(&store::user_key_of(&history.key));
+    let flashback_ts = history
+        .lock
+        .as_ref()
+        .filter(|lock| lock.is_flashback_lock(key.as_encoded()))
+        .map(|lock| {
+            steps.push(format!("lock: the flashback lock, start_ts: {}", lock.ts));
+            lock.ts
+        });
+    let mut writes = history.writes.clone();
+    writes.sort_by_key(|write| std::cmp::Reverse(write.commit_ts));
+    for write in writes.iter().filter(|write| write.commit_ts > version) {
+        if Some(write.write.start_ts) == flashback_ts {
+            steps.push(format!(
+                "write@{}: {}, written by the flashback",
+                write.commit_ts, write.write
+            ));
+        }
+    }
+    if writes.iter().all(|write| write.commit_ts <= version) {
+        steps.push(format!("not changed after {}", version));
+        return Flashback {
+            version,
+            restore: Restore::Unchanged,
+            steps,
+        };
+    }
+    let mut restore = Restore::Delete;
+    for write in writes.iter().filter(|write| write.commit_ts <= version) {
+        let mut record = format!("write@{}: {}", write.commit_ts, write.write);
+        // The flashback reads the version without checking the GC fence, unlike reads.
+        if !write.write.check_gc_fence_as_latest_version(version) {
+            record.push_str(", the GC fence is not checked by the flashback");
+        }
+        match write.write.write_type {
+            WriteType::Lock | WriteType::Rollback => {
+                steps.push(format!("{}, skipped", record));
+                continue;
+            }
+            WriteType::Delete => steps.push(format!("{}, deleted at {}", record, version)),
+            WriteType::Put => {
+                if write.write.short_value_ref().is_none() {
+                    steps.push(format!(
+                        "{}, the value at default@{} is copied",
+                        record, write.write.start_ts
+                    ));
+                } else {
+                    steps.push(record);
+                }
+                restore = Restore::Put(write.clone());
+            }
+        }
+        break;
+    }
+    if restore == Restore::Delete && !writes.iter().any(|w| w.commit_ts <= version) {
+        steps.push(format!("no version at or before {}", version));
+    }
+    Flashback {
+        version,
+        restore,
+        steps,
+    }
+}
+
+/// Explain what flashback to `version` restores on the keys `tikv-ctl mvcc` prints.
+#[wasm_bindgen]
+pub fn explain_flashback(text: &str, version: u64) -> Result<String, JsValue> {
+    tikv_ctl::parse_mvcc_dump(text)
+        .map(|histories| {
+            histories
+                .iter()
+                .map(|history| {
+                    format!(
+                        "key: {}\n{}\n",
+                        explain::explain_key(&history.key),
+                        flashback_key(history, TimeStamp(version))
+                    )
+                })
+                .collect()
+        })
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock::{Lock, LockType};
+
+    #[test]
+    fn test_flashback_key() {
+        let put = Write::new(WriteType::Put, TimeStamp(10), Some(b"v1".to_vec()));
+        let mut history = MvccHistory::from_writes(&[
+            (30, Write::new(WriteType::Delete, TimeStamp(29), None)),
+            (21, Write::new(WriteType::Put, TimeStamp(20), None)),
+            (15, Write::new(WriteType::Rollback, TimeStamp(15), None)),
+            (11, put.clone()),
+        ]);
+        let flashback = flashback_key(&history, TimeStamp(18));
+        assert_eq!(
+            flashback.restore,
+            Restore::Put(MvccWrite {
+                commit_ts: TimeStamp(11),
+                write: put
+            })
+        );
+        assert_eq!(
+            flashback.write(TimeStamp(40)),
+            Some(Write::new(
+                WriteType::Put,
+                TimeStamp(40),
+                Some(b"v1".to_vec())
+            ))
+        );
+        assert_eq!(
+            flashback.to_string(),
+            "write@15: Rollback, start_ts: 15, skipped\n\
+             write@11: Put, start_ts: 10, short_value: \"v1\"\n\
+             => restore write@11: Put, start_ts: 10, short_value: \"v1\""
+        );
+        assert_eq!(
+            flashback_key(&history, TimeStamp(5)).restore,
+            Restore::Delete
+        );
+        assert_eq!(
+            flashback_key(&history, TimeStamp(30)).restore,
+            Restore::Unchanged
+        );
+
+        // A fenced version is restored all the same.
+        let mut fenced = history.clone();
+        fenced.writes[3].write.gc_fence = Some(TimeStamp(12));
+        let flashback = flashback_key(&fenced, TimeStamp(18));
+        assert!(matches!(flashback.restore, Restore::Put(ref w) if w.commit_ts == TimeStamp(11)));
+        assert!(flashback.steps[1].ends_with("the GC fence is not checked by the flashback"));
+
+        // The flashback started at 40 has put its lock and committed its record.
+        let encoded = Key::from_raw(b"k").into_encoded();
+        history.lock = Some(Lock::new(LockType::Put, encoded, TimeStamp(40), 0));
+        history.writes.push(MvccWrite {
+            commit_ts: TimeStamp(41),
+            write: Write::new(WriteType::Put, TimeStamp(40), None),
+        });
+        let flashback = flashback_key(&history, TimeStamp(25));
+        assert_eq!(
+            flashback.steps[..2],
+            [
+                "lock: the flashback lock, start_ts: 40",
+                "write@41: Put, start_ts: 40, written by the flashback"
+            ]
+        );
+        assert_eq!(
+            flashback.write(TimeStamp(40)).unwrap().short_value_ref(),
+            None
+        );
+        assert!(history.to_string().contains("ttl: 0 (flashback)\n"));
+    }
+
+    #[test]
+    fn test_delete_flashback_lock() {
+        // The key has no version at 5, the flashback at 40 locks it with a `Delete` lock.
+        let mut history =
+            MvccHistory::from_writes(&[(11, Write::new(WriteType::Put, TimeStamp(10), None))]);
+        let encoded = Key::from_raw(b"k").into_encoded();
+        history.lock = Some(Lock::new(LockType::Delete, encoded, TimeStamp(40), 0));
+        let flashback = flashback_key(&history, TimeStamp(5));
+        assert_eq!(flashback.restore, Restore::Delete);
+        assert_eq!(
+            flashback.to_string(),
+            "lock: the flashback lock, start_ts: 40\n\
+             no version at or before 5\n\
+             => delete"
+        );
+        assert!(history.to_string().contains("(flashback)"));
+    }
+}
@@ -0,0 +1,224 @@
+//! Find out what TiKV's GC removes from the history of a key at a safe point.
+//!
+//! Versions newer than the safe point are always kept. Below it, the newest `Put` is
+//! the one a read at the safe point gets, so it's kept and everything older is removed,
+//! along with the values in the default CF. `Lock` and `Rollback` records are useless
+//! once no transaction can start before the safe point, protected or not. A `Delete`
+//! means there is no version left to keep, so it's removed as well.
+use crate::explain;
+use crate::mvcc::{Key, Modify, TimeStamp, Write, WriteType, CF_DEFAULT, CF_WRITE};
+use crate::store;
+use crate::tikv_ctl::{self, MvccHistory};
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GcMode {
+    /// The GC worker scanning the keys, like `gc_keys` does.
+    GcWorker,
+    /// The compaction filter, which only removes the `Delete` tombstone when compacting
+    /// to the bottommost level.
+    CompactionFilter,
+    BottommostCompactionFilter,
+}
+
+/// What GC does with a record of the write CF.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GcDecision {
+    pub commit_ts: TimeStamp,
+    pub write: Write,
+    pub removed: bool,
+    /// The start ts of the value removed from the default CF with the record.
+    pub default_removed: Option<TimeStamp>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GcResult {
+    pub key: Vec<u8>,
+    pub safe_point: TimeStamp,
+    /// Newest first, like the write CF.
+    pub decisions: Vec<GcDecision>,
+}
+
+impl GcResult {
+    /// The deletions GC writes.
+    pub fn modifies(&self) -> Vec<Modify> {
+        let key = Key::from_raw(&store::user_key_of(&self.key));
+        let mut result = Vec::new();
+        for decision in self.decisions.iter().filter(|d| d.removed) {
+            let write_key = key.clone().append_ts(decision.commit_ts);
+            result.push(Modify::Delete(CF_WRITE, write_key));
+            if let Some(start_ts) = decision.default_removed {
+                result.push(Modify::Delete(CF_DEFAULT, key.clone().append_ts(start_ts)));
+            }
+        }
+        result
+    }
+}
+
+impl fmt::Display for GcResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "key: {}, safe point: {}",
+            explain::explain_key(&self.key),
+            self.safe_point
+        )?;
+        for decision in &self.decisions {
+            writeln!(
+                f,
+                "  write@{}: {}: {}, {}",
+                decision.commit_ts,
+                decision.write,
+                if decision.removed { "removed" } else { "kept" },
+                decision.reason
+            )?;
+            if let Some(start_ts) = decision.default_removed {
+                writeln!(f, "  default@{}: removed with it", start_ts)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decide what GC at the safe point does with each record of the history.
+pub fn simulate_gc(history: &MvccHistory, safe_point: TimeStamp, mode: GcMode) -> GcResult {
+    let mut writes = history.writes.clone();
+    writes.sort_by_key(|write| std::cmp::Reverse(write.commit_ts));
+    let mut remove_older = false;
+    let mut decisions = Vec::new();
+    for write in writes {
+        let (commit_ts, write) = (write.commit_ts, write.write);
+        let mut decision = GcDecision {
+            commit_ts,
+            write: write.clone(),
+            removed: true,
+            default_removed: None,
+            reason: String::new(),
+        };
+        if commit_ts > safe_point {
+            decision.removed = false;
+            decision.reason = "newer than the safe point".to_string();
+        } else if remove_older {
+            decision.reason = "older than the version kept".to_string();
+            if write.write_type == WriteType::Put && write.short_value_ref().is_none() {
+                decision.default_removed = Some(write.start_ts);
+            }
+        } else {
+            match write.write_type {
+                WriteType::Put => {
+                    remove_older = true;
+                    decision.removed = false;
+                    decision.reason = "the version to read at the safe point".to_string();
+                }
+                WriteType::Delete => {
+                    remove_older = true;
+                    match mode {
+                        GcMode::CompactionFilter => {
+                            decision.removed = false;
+                            decision.reason = "the tombstone is kept until the compaction \
+                                               to the bottommost level"
+                                .to_string();
+                        }
+                        _ => decision.reason = "the key is deleted at the safe point".to_string(),
+                    }
+                }
+                WriteType::Lock | WriteType::Rollback => {
+                    decision.reason = if write.is_protected() {
+                        "protected, but no transaction can start before the safe point"
+                    } else {
+                        "not a version"
+                    }
+                    .to_string();
+                }
+            }
+        }
+        if decision.removed && write.has_overlapped_rollback {
+            decision.reason += ", the rollback overlapped on it is gone too";
+        }
+        decisions.push(decision);
+    }
+    GcResult {
+        key: history.key.clone(),
+        safe_point,
+        decisions,
+    }
+}
+
+/// Explain what GC at the safe point removes from the keys `tikv-ctl mvcc` prints.
+#[wasm_bindgen]
+pub fn explain_gc(text: &str, safe_point: u64, mode: GcMode) -> Result<String, JsValue> {
+    tikv_ctl::parse_mvcc_dump(text)
+        .map(|histories| {
+            histories
+                .iter()
+                .map(|history| simulate_gc(history, safe_point.into(), mode).to_string())
+                .collect()
+        })
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn removed(result: &GcResult) -> Vec<u64> {
+        result
+            .decisions
+            .iter()
+            .filter(|d| d.removed)
+            .map(|d| d.commit_ts.0)
+            .collect()
+    }
+
+    #[test]
+    fn test_gc() {
+        let mut overlapped = Write::new(WriteType::Put, TimeStamp(35), Some(b"b".to_vec()));
+        overlapped.has_overlapped_rollback = true;
+        let history = MvccHistory::from_writes(&[
+            (50, Write::new(WriteType::Put, TimeStamp(45), None)),
+            (42, Write::new_rollback(TimeStamp(42), true)),
+            (40, overlapped),
+            (30, Write::new(WriteType::Lock, TimeStamp(29), None)),
+            (20, Write::new(WriteType::Put, TimeStamp(10), None)),
+        ]);
+        let result = simulate_gc(&history, TimeStamp(45), GcMode::GcWorker);
+        assert_eq!(removed(&result), vec![42, 30, 20]);
+        assert_eq!(result.decisions[3].default_removed, None);
+        assert_eq!(result.decisions[4].default_removed, Some(TimeStamp(10)));
+        assert_eq!(
+            result.to_string(),
+            "key: \"k\", safe point: 45\n  \
+             write@50: Put, start_ts: 45: kept, newer than the safe point\n  \
+             write@42: Rollback, start_ts: 42, short_value: \"p\": removed, \
+             protected, but no transaction can start before the safe point\n  \
+             write@40: Put, start_ts: 35, short_value: \"b\", overlapped_rollback: \
+             kept, the version to read at the safe point\n  \
+             write@30: Lock, start_ts: 29: removed, older than the version kept\n  \
+             write@20: Put, start_ts: 10: removed, older than the version kept\n  \
+             default@10: removed with it\n"
+        );
+        assert_eq!(result.modifies().len(), 4);
+    }
+
+    #[test]
+    fn test_gc_delete() {
+        let history = MvccHistory::from_writes(&[
+            (30, Write::new(WriteType::Delete, TimeStamp(25), None)),
+            (
+                20,
+                Write::new(WriteType::Put, TimeStamp(10), Some(b"v".to_vec())),
+            ),
+        ]);
+        let result = simulate_gc(&history, TimeStamp(30), GcMode::GcWorker);
+        assert_eq!(removed(&result), vec![30, 20]);
+        let result = simulate_gc(&history, TimeStamp(30), GcMode::CompactionFilter);
+        assert_eq!(removed(&result), vec![20]);
+        let result = simulate_gc(&history, TimeStamp(30), GcMode::BottommostCompactionFilter);
+        assert_eq!(removed(&result), vec![30, 20]);
+        let result = simulate_gc(&history, TimeStamp(29), GcMode::GcWorker);
+        assert!(removed(&result).is_empty());
+    }
+}
@@ -0,0 +1,259 @@
+//! Render some bytes as a hexdump, with the fields of a `ParsingTrace` underlined and labelled.
+//!
+//! ```text
+//! 00000000  50 0a 76 01 78                                   |P.v.x|
+//!           ^^                                               write_type: EnumFlag = Put
+//!              ^^                                            start_ts: VarInt = 10
+//!                 ^^                                         short_value.flag: EnumFlag
+//! ```
+use crate::trace::{EncodeMethod, ParsingTrace};
+use wasm_bindgen::prelude::*;
+
+const BYTES_PER_LINE: usize = 16;
+const OFFSET_WIDTH: usize = 10;
+const LABEL_COLUMN: usize = OFFSET_WIDTH + BYTES_PER_LINE * 3 + 1;
+const ANSI_COLORS: [u8; 6] = [31, 32, 33, 34, 35, 36];
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HexdumpStyle {
+    Plain,
+    Ansi,
+    Html,
+}
+
+struct Field {
+    start: usize,
+    end: usize,
+    label: String,
+}
+
+fn label(path: &str, trace: &ParsingTrace) -> String {
+    match &trace.value {
+        Some(value) => format!("{}: {:?} = {}", path, trace.encoded_in, value),
+        None => format!("{}: {:?}", path, trace.encoded_in),
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+fn paint(style: HexdumpStyle, text: &str, color: Option<usize>, title: &str) -> String {
+    match (style, color) {
+        (HexdumpStyle::Plain, _) => text.to_string(),
+        (HexdumpStyle::Ansi, None) => text.to_string(),
+        (HexdumpStyle::Ansi, Some(color)) => format!(
+            "\x1b[{}m{}\x1b[0m",
+            ANSI_COLORS[color % ANSI_COLORS.len()],
+            text
+        ),
+        (HexdumpStyle::Html, None) => escape_html(text),
+        (HexdumpStyle::Html, Some(color)) => format!(
+            "<span class=\"trace-field-{}\" title=\"{}\">{}</span>",
+            color % ANSI_COLORS.len(),
+            escape_html(title),
+            escape_html(text)
+        ),
+    }
+}
+
+/// The bytes a memcomparable encoded node decodes into, which are the data parts of its groups.
+/// Only memcomparable encoded fields carry an `inner` trace so far.
+fn decoded_bytes(trace: &ParsingTrace, b: &[u8]) -> Option<Vec<u8>> {
+    if trace.encoded_in != EncodeMethod::Memcomparable {
+        return None;
+    }
+    let mut result = Vec::new();
+    for (path, leaf) in trace.leaves() {
+        if path.ends_with("data") {
+            result.extend_from_slice(b.get(leaf.start..leaf.end())?);
+        }
+    }
+    Some(result)
+}
+
+/// The nodes having an `inner` trace, not including the ones inside `inner` traces.
+fn collect_encoded<'a>(trace: &'a ParsingTrace, result: &mut Vec<&'a ParsingTrace>) {
+    if trace.inner.is_some() {
+        result.push(trace);
+    }
+    for child in &trace.children {
+        collect_encoded(child, result);
+    }
+}
+
+fn render_into(out: &mut String, b: &[u8], trace: &ParsingTrace, style: HexdumpStyle) {
+    let leaves = trace.leaves();
+    let fields: Vec<Field> = if leaves.is_empty() {
+        vec![Field {
+            start: trace.start,
+            end: trace.end(),
+            label: label(&trace.field, trace),
+        }]
+    } else {
+        leaves
+            .into_iter()
+            .map(|(path, leaf)| Field {
+                start: leaf.start,
+                end: leaf.end(),
+                label: label(&path, leaf),
+            })
+            .filter(|field| field.start < field.end)
+            .collect()
+    };
+    let color_of = |offset: usize| {
+        fields
+            .iter()
+            .position(|f| (f.start..f.end).contains(&offset))
+    };
+
+    for line_start in (0..b.len().max(1)).step_by(BYTES_PER_LINE) {
+        let line_end = (line_start + BYTES_PER_LINE).min(b.len());
+        out.push_str(&format!("{:08x}  ", line_start));
+        for offset in line_start..line_start + BYTES_PER_LINE {
+            match b.get(offset).filter(|_| offset < line_end) {
+                Some(byte) => {
+                    let color = color_of(offset);
+                    let title = color.map_or("", |i| fields[i].label.as_str());
+                    out.push_str(&paint(style, &format!("{:02x}", byte), color, title));
+                    out.push(' ');
+                }
+                None => out.push_str("   "),
+            }
+        }
+        let ascii: String = b[line_start..line_end]
+            .iter()
+            .map(|&c| {
+                if c.is_ascii_graphic() || c == b' ' {
+                    c as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        out.push_str(&paint(style, &format!(" |{}|", ascii), None, ""));
+        out.push('\n');
+
+        for (i, field) in fields.iter().enumerate() {
+            let (start, end) = (field.start.max(line_start), field.end.min(line_end));
+            if start >= end {
+                continue;
+            }
+            let column = OFFSET_WIDTH + (start - line_start) * 3;
+            let underline = "^".repeat((end - start) * 3 - 1);
+            out.push_str(&" ".repeat(column));
+            out.push_str(&paint(style, &underline, Some(i), &field.label));
+            out.push_str(&" ".repeat(LABEL_COLUMN.saturating_sub(column + underline.len())));
+            let text = if start == field.start {
+                field.label.clone()
+            } else {
+                format!("(cont.) {}", field.label)
+            };
+            out.push_str(&paint(style, &text, Some(i), &field.label));
+            out.push('\n');
+        }
+    }
+
+    // Fields encoding some other bytes are followed by the dump of the decoded bytes.
+    let mut encoded = Vec::new();
+    collect_encoded(trace, &mut encoded);
+    for node in encoded {
+        if let Some(decoded) = decoded_bytes(node, b) {
+            let title = format!(
+                "{} at {:#x}..{:#x} decodes to:",
+                node.field,
+                node.start,
+                node.end()
+            );
+            out.push('\n');
+            out.push_str(&paint(style, &title, None, ""));
+            out.push('\n');
+            if let Some(inner) = &node.inner {
+                render_into(out, &decoded, inner, style);
+            }
+        }
+    }
+}
+
+/// Render `b` as a hexdump annotated with the fields in `trace`.
+pub fn render(b: &[u8], trace: &ParsingTrace, style: HexdumpStyle) -> String {
+    let mut out = String::new();
+    render_into(&mut out, b, trace, style);
+    match style {
+        HexdumpStyle::Html => format!("<pre class=\"hexdump\">{}</pre>", out),
+        _ => out,
+    }
+}
+
+#[wasm_bindgen]
+pub fn hexdump(b: &[u8], trace: &ParsingTrace, style: HexdumpStyle) -> String {
+    render(b, trace, style)
+}
+
+/// Explain `key` and render the result as a hexdump.
+#[wasm_bindgen]
+pub fn hexdump_key(key: &[u8], style: HexdumpStyle) -> String {
+    render(key, &crate::explain::trace_key(key), style)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk;
+    use crate::db_to_kv;
+    use crate::mvcc::Write;
+
+    #[test]
+    fn test_render_write() {
+        let code = [b'P', 10, b'v', 1, b'<'];
+        let write = Write::parse_rust(&code).unwrap();
+        let expected = "\
+00000000  50 0a 76 01 3c                                   |P.v.<|
+          ^^                                               write_type: EnumFlag = Put
+             ^^                                            start_ts: VarInt = 10
+                ^^                                         short_value.flag: EnumFlag
+                   ^^                                      short_value.length: SingleByte = 1
+                      ^^                                   short_value.value: Bytes
+";
+        assert_eq!(render(&code, write.trace(), HexdumpStyle::Plain), expected);
+
+        let ansi = render(&code, write.trace(), HexdumpStyle::Ansi);
+        assert!(ansi.contains("\x1b[31m50\x1b[0m"));
+        let html = render(&code, write.trace(), HexdumpStyle::Html);
+        assert!(html.starts_with("<pre class=\"hexdump\">"));
+        assert!(html.contains("|P.v.&lt;|"));
+        assert!(html.contains(
+            "<span class=\"trace-field-0\" title=\"write_type: EnumFlag = Put\">50</span>"
+        ));
+    }
+
+    #[test]
+    fn test_render_nested_key() {
+        let mut key = vec![b'z'];
+        key.extend_from_slice(&chunk::encode_bytes(
+            &db_to_kv::encode_record_key_rust(None, 53, 1).unwrap(),
+        ));
+        key.extend_from_slice(&(!10u64).to_be_bytes());
+        let text = hexdump_key(&key, HexdumpStyle::Plain);
+        let lines: Vec<_> = text.lines().collect();
+        assert!(lines[0].starts_with("00000000  7a 74 80 00 00 00 00 00 00 ff"));
+        assert!(text.contains("(cont.) mvcc_key.encoded_key.group.data: Bytes"));
+        assert!(text.contains("mvcc_key.ts: DescendingU64 = 10"));
+        assert!(text.contains("encoded_key at 0x1..0x1c decodes to:"));
+        assert!(text.contains("row_id: ComparableI64 = 1"));
+        // The decoded table key is dumped from offset 0 again.
+        let decoded = text.split("decodes to:\n").nth(1).unwrap();
+        assert!(decoded.starts_with("00000000  74 80 00 00 00 00 00 00 35 5f"));
+    }
+}
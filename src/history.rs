@@ -0,0 +1,242 @@
+//! Render the write CF records of a key as a timeline, newest first as TiKV stores them.
+use crate::explain::{self, ExplainedKey};
+use crate::local::DATA_PREFIX;
+use crate::mvcc::{Key, TimeStamp, Write, WriteType};
+use anyhow::{anyhow, bail};
+use serde::Serialize;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WriteRow {
+    pub commit_ts: TimeStamp,
+    pub commit_time: String,
+    pub start_ts: TimeStamp,
+    pub start_time: String,
+    pub write_type: WriteType,
+    pub short_value: Option<String>,
+    /// `protected`, `overlapped_rollback` and `gc_fence: N`.
+    pub flags: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WriteHistory {
+    pub key: ExplainedKey,
+    pub rows: Vec<WriteRow>,
+}
+
+impl WriteRow {
+    fn new(commit_ts: TimeStamp, write: &Write) -> WriteRow {
+        let mut flags = Vec::new();
+        if write.is_protected() {
+            flags.push("protected".to_string());
+        }
+        if write.has_overlapped_rollback {
+            flags.push("overlapped_rollback".to_string());
+        }
+        if let Some(fence) = write.gc_fence {
+            flags.push(format!("gc_fence: {}", fence));
+        }
+        WriteRow {
+            commit_ts,
+            commit_time: commit_ts.wall_clock(),
+            start_ts: write.start_ts,
+            start_time: write.start_ts.wall_clock(),
+            write_type: write.write_type,
+            // The short value of a protected rollback is only a marker.
+            short_value: write
+                .short_value_ref()
+                .filter(|_| !write.is_protected())
+                .map(|value| explain::escape(value)),
+            flags,
+        }
+    }
+}
+
+impl WriteHistory {
+    /// Build the history from the pairs of MVCC keys, with or without the `z` prefix,
+    /// and values in the write CF, all of which must be of the same user key.
+    pub fn from_entries(entries: &[(Vec<u8>, Vec<u8>)]) -> anyhow::Result<WriteHistory> {
+        let mut user_key: Option<Vec<u8>> = None;
+        let mut rows = Vec::new();
+        for (key, value) in entries {
+            let key = key.strip_prefix(&[DATA_PREFIX]).unwrap_or(key);
+            let key = Key::from_encoded(key.to_vec());
+            let (encoded, commit_ts) = key.split_on_ts()?;
+            let raw = Key::from_encoded(encoded.to_vec()).to_raw()?;
+            match &user_key {
+                Some(user_key) if *user_key != raw => bail!(
+                    "{} and {} are different keys",
+                    explain::explain_key(user_key),
+                    explain::explain_key(&raw)
+                ),
+                Some(_) => {}
+                None => user_key = Some(raw),
+            }
+            let write =
+                Write::parse_rust(value).map_err(|e| anyhow!("write@{}: {}", commit_ts, e))?;
+            rows.push(WriteRow::new(commit_ts, &write));
+        }
+        rows.sort_by_key(|row| std::cmp::Reverse(row.commit_ts));
+        let key = user_key.ok_or_else(|| anyhow!("no write record"))?;
+        Ok(WriteHistory {
+            key: ExplainedKey::new(&key),
+            rows,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+impl fmt::Display for WriteHistory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "key: {}", self.key.explanation)?;
+        let header = [
+            "commit_ts",
+            "commit time",
+            "start_ts",
+            "start time",
+            "type",
+            "short value",
+            "flags",
+        ];
+        let table: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| {
+                vec![
+                    row.commit_ts.to_string(),
+                    row.commit_time.clone(),
+                    row.start_ts.to_string(),
+                    row.start_time.clone(),
+                    format!("{:?}", row.write_type),
+                    row.short_value.clone().unwrap_or_default(),
+                    row.flags.join(", "),
+                ]
+            })
+            .collect();
+        let widths: Vec<usize> = header
+            .iter()
+            .enumerate()
+            .map(|(i, title)| {
+                table
+                    .iter()
+                    .map(|cells| cells[i].chars().count())
+                    .fold(title.len(), usize::max)
+            })
+            .collect();
+        let header: Vec<String> = header.iter().map(ToString::to_string).collect();
+        for cells in std::iter::once(&header).chain(&table) {
+            let line: Vec<String> = cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect();
+            writeln!(f, "{}", line.join(" | ").trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// Render the write CF records in `text`, a line of hex key and hex value for each.
+#[wasm_bindgen]
+pub fn render_write_history(text: &str, json: bool) -> Result<String, JsValue> {
+    let parse = || -> anyhow::Result<WriteHistory> {
+        let entries = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let mut parts = line.split_whitespace();
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some(key), Some(value), None) => Ok((hex::decode(key)?, hex::decode(value)?)),
+                    _ => bail!("expect `key value` in hex: {}", line),
+                }
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        WriteHistory::from_entries(&entries)
+    };
+    parse()
+        .map(|history| {
+            if json {
+                history.to_json()
+            } else {
+                history.to_string()
+            }
+        })
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::NOW;
+
+    fn entry(key: &[u8], commit_ts: TimeStamp, write: &Write) -> (Vec<u8>, Vec<u8>) {
+        let key = Key::from_raw(key).append_ts(commit_ts).into_encoded();
+        (key, write.to_bytes())
+    }
+
+    #[test]
+    fn test_write_history() {
+        let ts = |ms| TimeStamp::compose(NOW + ms, 0);
+        let mut fenced = Write::new(WriteType::Put, ts(0), Some(b"v1".to_vec()));
+        fenced.gc_fence = Some(ts(20));
+        let entries = vec![
+            entry(b"k", ts(1), &fenced),
+            entry(b"k", ts(10), &Write::new_rollback(ts(10), true)),
+            entry(b"k", ts(21), &Write::new(WriteType::Delete, ts(20), None)),
+        ];
+        let history = WriteHistory::from_entries(&entries).unwrap();
+        assert_eq!(
+            history
+                .rows
+                .iter()
+                .map(|row| row.write_type)
+                .collect::<Vec<_>>(),
+            vec![WriteType::Delete, WriteType::Rollback, WriteType::Put]
+        );
+        let text = history.to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "key: \"k\"");
+        assert_eq!(
+            lines[1],
+            "commit_ts          | commit time                 | start_ts           \
+             | start time                  | type     | short value | flags"
+        );
+        assert_eq!(
+            lines[3],
+            format!(
+                "{} | 2021-06-02 09:25:01.133 UTC | {} | 2021-06-02 09:25:01.133 UTC \
+                 | Rollback |             | protected",
+                ts(10),
+                ts(10)
+            )
+        );
+        assert!(lines[4].ends_with(&format!(
+            "| Put      | \"v1\"        | gc_fence: {}",
+            ts(20)
+        )));
+
+        let json: serde_json::Value = serde_json::from_str(&history.to_json()).unwrap();
+        assert_eq!(json["rows"][2]["short_value"], "\"v1\"");
+        assert_eq!(json["rows"][0]["write_type"], "Delete");
+    }
+
+    #[test]
+    fn test_write_history_errors() {
+        let write = Write::new(WriteType::Put, TimeStamp(1), None);
+        let entries = vec![
+            entry(b"a", TimeStamp(2), &write),
+            entry(b"b", TimeStamp(2), &write),
+        ];
+        assert_eq!(
+            WriteHistory::from_entries(&entries)
+                .unwrap_err()
+                .to_string(),
+            "\"a\" and \"b\" are different keys"
+        );
+        assert!(WriteHistory::from_entries(&[]).is_err());
+    }
+}
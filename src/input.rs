@@ -1,24 +1,36 @@
+use crate::notation;
+use anyhow::{anyhow, bail};
 use wasm_bindgen::JsValue;
 
 use wasm_bindgen::prelude::*;
 
-#[wasm_bindgen]
-pub fn parse_golang_fmt_print(mut code: &str) -> Result<Vec<u8>, JsValue> {
+fn trim_brackets(mut code: &str) -> &str {
     if code.starts_with('[') {
         code = &code[1..];
     }
     if code.ends_with(']') {
         code = &code[..code.len() - 1];
     }
-    let str_forms = code.split(' ');
-    let mut result = Vec::with_capacity(str_forms.clone().count());
-    for str_form in str_forms {
-        let byte_form = str_form
-            .parse::<u8>()
-            .map_err(|_| JsValue::from("Parse golang fmt.print failed"))?;
-        result.push(byte_form);
-    }
-    Ok(result)
+    code
+}
+
+fn golang_fmt_print(code: &str) -> Option<Vec<u8>> {
+    trim_brackets(code)
+        .split(' ')
+        .map(|it| it.parse::<u8>().ok())
+        .collect()
+}
+
+fn rust_print(code: &str) -> Option<Vec<u8>> {
+    trim_brackets(code)
+        .split(',')
+        .map(|it| it.trim().parse::<u8>().ok())
+        .collect()
+}
+
+#[wasm_bindgen]
+pub fn parse_golang_fmt_print(code: &str) -> Result<Vec<u8>, JsValue> {
+    golang_fmt_print(code).ok_or_else(|| JsValue::from("Parse golang fmt.print failed"))
 }
 
 #[wasm_bindgen]
@@ -27,41 +39,64 @@ pub fn parse_hex_encoded(code: &str) -> Result<Vec<u8>, JsValue> {
 }
 
 #[wasm_bindgen]
-pub fn parse_rust_print(mut code: &str) -> Result<Vec<u8>, JsValue> {
-    if code.starts_with('[') {
-        code = &code[1..];
-    }
-    if code.ends_with(']') {
-        code = &code[..code.len() - 1];
+pub fn parse_rust_print(code: &str) -> Result<Vec<u8>, JsValue> {
+    rust_print(code).ok_or_else(|| JsValue::from("Invalid rust print encoded"))
+}
+
+/// Decode standard base64, the padding is optional.
+pub fn decode_base64(code: &str) -> anyhow::Result<Vec<u8>> {
+    let code = code.trim().trim_end_matches('=');
+    let mut result = Vec::with_capacity(code.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in code.bytes() {
+        let n = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => bail!("invalid base64 character {:?}", c as char),
+        };
+        buffer = buffer << 6 | n as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+        }
     }
-    let str_forms = code.split(',').map(|it| it.trim());
-    let mut result = Vec::with_capacity(str_forms.clone().count());
-    for str_form in str_forms {
-        let byte_form = str_form
-            .parse::<u8>()
-            .map_err(|_| JsValue::from("Invalid rust print encoded"))?;
-        result.push(byte_form);
+    if bits >= 6 {
+        bail!("invalid base64 length");
     }
     Ok(result)
 }
 
 #[wasm_bindgen]
-pub fn parse_input(code: &str) -> Result<Vec<u8>, JsValue> {
-    if let Ok(result) = parse_rust_print(code) {
-        Ok(result)
-    } else if let Ok(result) = parse_golang_fmt_print(code) {
-        Ok(result)
-    } else if let Ok(result) = parse_hex_encoded(code) {
-        Ok(result)
-    } else {
-        Err(JsValue::from_str("Cannot parse input"))
+pub fn parse_base64_encoded(code: &str) -> Result<Vec<u8>, JsValue> {
+    decode_base64(code).map_err(|_| JsValue::from("Invalid base64 encoded"))
+}
+
+/// Read `code` in any form we know, ie. rust and golang byte arrays, hex,
+/// an escaped string in quotes or the key notation.
+pub fn parse_input_rust(code: &str) -> anyhow::Result<Vec<u8>> {
+    let code = code.trim();
+    if let Some(result) = rust_print(code).or_else(|| golang_fmt_print(code)) {
+        return Ok(result);
     }
+    if let Ok(result) = hex::decode(code) {
+        return Ok(result);
+    }
+    notation::parse_notation(code).map_err(|_| anyhow!("Cannot parse input {:?}", code))
+}
+
+#[wasm_bindgen]
+pub fn parse_input(code: &str) -> Result<Vec<u8>, JsValue> {
+    parse_input_rust(code).map_err(|e| JsValue::from_str(&e.to_string()))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::db_to_kv::parse_record;
 
     #[test]
     fn test_parse_golang_fmt_print() {
@@ -86,4 +121,32 @@ mod test {
             expected
         )
     }
+
+    #[test]
+    fn test_parse_input() {
+        let expected = crate::db_to_kv::encode_record_key_rust(None, 53, 1).unwrap();
+        let cases = vec![
+            "[116, 128, 0, 0, 0, 0, 0, 0, 53, 95, 114, 128, 0, 0, 0, 0, 0, 0, 1]",
+            "[116 128 0 0 0 0 0 0 53 95 114 128 0 0 0 0 0 0 1]",
+            "7480000000000000355f728000000000000001",
+            "\"t\\x80\\x00\\x00\\x00\\x00\\x00\\x005_r\\x80\\x00\\x00\\x00\\x00\\x00\\x00\\x01\"",
+            " t53_r1 ",
+        ];
+        for code in cases {
+            assert_eq!(parse_input_rust(code).unwrap(), expected, "{}", code);
+        }
+        assert_eq!(parse_input_rust("").unwrap(), b"");
+        assert!(parse_input_rust("t53_x").is_err());
+    }
+
+    #[test]
+    fn test_decode_base64() {
+        for b in [&b""[..], b"a", b"ab", b"abc", b"t\x80\x00\xff\n"] {
+            let encoded = crate::output::base64(b);
+            assert_eq!(decode_base64(&encoded).unwrap(), b);
+            assert_eq!(decode_base64(encoded.trim_end_matches('=')).unwrap(), b);
+        }
+        assert!(decode_base64("a").is_err());
+        assert!(decode_base64("a*").is_err());
+    }
 }
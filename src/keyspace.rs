@@ -0,0 +1,110 @@
+//! Keyspace prefixes of API v2, which split a cluster into several tenants.
+//!
+//! Each key starts with a mode byte (`r` for RawKV, `x` for TxnKV) followed
+//! by the keyspace ID in 3 big-endian bytes.
+use crate::trace::{EncodeMethod, ParsingTrace};
+use anyhow::bail;
+use serde::Serialize;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+pub const RAW_KEY_PREFIX: u8 = b'r';
+pub const TXN_KEY_PREFIX: u8 = b'x';
+pub const KEYSPACE_PREFIX_LEN: usize = 4;
+pub const MAX_KEYSPACE_ID: u32 = 0xff_ffff;
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum KeyMode {
+    Raw,
+    Txn,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct Keyspace {
+    pub mode: KeyMode,
+    pub id: u32,
+}
+
+impl Keyspace {
+    pub fn new(mode: KeyMode, id: u32) -> anyhow::Result<Keyspace> {
+        if id > MAX_KEYSPACE_ID {
+            bail!("keyspace id {} is larger than 3 bytes", id);
+        }
+        Ok(Keyspace { mode, id })
+    }
+
+    /// Split the keyspace prefix from `key`.
+    pub fn split(key: &[u8]) -> Option<(Keyspace, &[u8])> {
+        if key.len() < KEYSPACE_PREFIX_LEN {
+            return None;
+        }
+        let mode = match key[0] {
+            RAW_KEY_PREFIX => KeyMode::Raw,
+            TXN_KEY_PREFIX => KeyMode::Txn,
+            _ => return None,
+        };
+        let id = u32::from_be_bytes([0, key[1], key[2], key[3]]);
+        Some((Keyspace { mode, id }, &key[KEYSPACE_PREFIX_LEN..]))
+    }
+
+    pub fn prefix(&self) -> [u8; KEYSPACE_PREFIX_LEN] {
+        let id = self.id.to_be_bytes();
+        let mode = match self.mode {
+            KeyMode::Raw => RAW_KEY_PREFIX,
+            KeyMode::Txn => TXN_KEY_PREFIX,
+        };
+        [mode, id[1], id[2], id[3]]
+    }
+
+    /// How the prefix is laid out at the beginning of a key.
+    pub fn trace(&self) -> ParsingTrace {
+        ParsingTrace::node(
+            "keyspace",
+            vec![
+                ParsingTrace::leaf(0, 1, "key_mode", EncodeMethod::EnumFlag)
+                    .with_value(format!("{:?}", self.mode)),
+                ParsingTrace::leaf(
+                    1,
+                    KEYSPACE_PREFIX_LEN - 1,
+                    "keyspace_id",
+                    EncodeMethod::BigEndian,
+                )
+                .with_value(self.id),
+            ],
+        )
+        .with_value(self)
+    }
+
+    /// Put the keyspace prefix before `key`.
+    pub fn apply(&self, key: &[u8]) -> Vec<u8> {
+        let mut result = self.prefix().to_vec();
+        result.extend_from_slice(key);
+        result
+    }
+}
+
+impl fmt::Display for Keyspace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            KeyMode::Raw => write!(f, "raw keyspace {}", self.id),
+            KeyMode::Txn => write!(f, "txn keyspace {}", self.id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_keyspace() {
+        let (keyspace, rest) = Keyspace::split(b"r\x00\x01\x02key").unwrap();
+        assert_eq!(keyspace, Keyspace::new(KeyMode::Raw, 258).unwrap());
+        assert_eq!(rest, b"key");
+        assert_eq!(keyspace.apply(rest), b"r\x00\x01\x02key");
+        assert!(Keyspace::split(b"t\x00\x01\x02").is_none());
+        assert!(Keyspace::new(KeyMode::Txn, 1 << 24).is_err());
+    }
+}
@@ -1,10 +1,39 @@
-mod chunk;
-mod db_to_kv;
-mod endian;
-mod input;
-mod mvcc;
-mod utils;
-mod varint;
+pub mod async_commit;
+pub mod check;
+pub mod chunk;
+pub mod datum;
+pub mod db_to_kv;
+pub mod edit;
+pub mod endian;
+pub mod explain;
+pub mod flashback;
+pub mod gc;
+pub mod hexdump;
+pub mod history;
+pub mod input;
+pub mod keyspace;
+pub mod local;
+pub mod lock;
+pub mod lock_status;
+pub mod meta;
+pub mod mvcc;
+pub mod notation;
+pub mod output;
+pub mod pd;
+pub mod protobuf;
+pub mod raft_log;
+pub mod raft_serverpb;
+pub mod range;
+pub mod rawkv;
+pub mod region;
+pub mod resolved_ts;
+pub mod store;
+pub mod tidb_http;
+pub mod tikv_ctl;
+pub mod trace;
+pub mod txn;
+pub mod utils;
+pub mod varint;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -0,0 +1,267 @@
+//! Keys TiKV's raftstore stores outside of the data range (prefixed with `0x01`).
+use crate::endian;
+use crate::raft_log::Entry;
+use crate::raft_serverpb::{RaftApplyState, RaftLocalState, RegionLocalState, StoreIdent};
+use crate::trace::{EncodeMethod, ParsingTrace};
+use crate::utils;
+use anyhow::bail;
+use serde::Serialize;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+pub const LOCAL_PREFIX: u8 = 0x01;
+pub const DATA_PREFIX: u8 = b'z';
+
+const STORE_IDENT_KEY: &[u8] = &[LOCAL_PREFIX, 0x01];
+const PREPARE_BOOTSTRAP_KEY: &[u8] = &[LOCAL_PREFIX, 0x02];
+const RECOVER_STATE_KEY: &[u8] = &[LOCAL_PREFIX, 0x03];
+
+const REGION_RAFT_PREFIX: u8 = 0x02;
+const REGION_META_PREFIX: u8 = 0x03;
+
+const RAFT_LOG_SUFFIX: u8 = 0x01;
+const RAFT_STATE_SUFFIX: u8 = 0x02;
+const APPLY_STATE_SUFFIX: u8 = 0x03;
+const SNAPSHOT_RAFT_STATE_SUFFIX: u8 = 0x04;
+const REGION_STATE_SUFFIX: u8 = 0x01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum LocalKey {
+    StoreIdent,
+    PrepareBootstrap,
+    RecoverState,
+    RaftLog { region_id: u64, index: u64 },
+    RaftState { region_id: u64 },
+    ApplyState { region_id: u64 },
+    SnapshotRaftState { region_id: u64 },
+    RegionState { region_id: u64 },
+}
+
+impl LocalKey {
+    pub fn parse(b: &[u8]) -> anyhow::Result<LocalKey> {
+        match b {
+            STORE_IDENT_KEY => return Ok(LocalKey::StoreIdent),
+            PREPARE_BOOTSTRAP_KEY => return Ok(LocalKey::PrepareBootstrap),
+            RECOVER_STATE_KEY => return Ok(LocalKey::RecoverState),
+            _ => {}
+        }
+        if b.len() < 11 || b[0] != LOCAL_PREFIX {
+            bail!("not a local key");
+        }
+        let region_id = endian::big::decode_u64(&b[2..10]);
+        let suffix = b[10];
+        let rest = &b[11..];
+        let key = match (b[1], suffix, rest.len()) {
+            (REGION_RAFT_PREFIX, RAFT_LOG_SUFFIX, 8) => LocalKey::RaftLog {
+                region_id,
+                index: endian::big::decode_u64(rest),
+            },
+            (REGION_RAFT_PREFIX, RAFT_STATE_SUFFIX, 0) => LocalKey::RaftState { region_id },
+            (REGION_RAFT_PREFIX, APPLY_STATE_SUFFIX, 0) => LocalKey::ApplyState { region_id },
+            (REGION_RAFT_PREFIX, SNAPSHOT_RAFT_STATE_SUFFIX, 0) => {
+                LocalKey::SnapshotRaftState { region_id }
+            }
+            (REGION_META_PREFIX, REGION_STATE_SUFFIX, 0) => LocalKey::RegionState { region_id },
+            _ => bail!("unknown local key"),
+        };
+        Ok(key)
+    }
+
+    /// How the bytes of this key are laid out.
+    pub fn trace(&self) -> ParsingTrace {
+        let mut children = vec![ParsingTrace::leaf(
+            0,
+            1,
+            "local_prefix",
+            EncodeMethod::Literal,
+        )];
+        let (region_prefix, region_id, suffix) = match *self {
+            LocalKey::StoreIdent | LocalKey::PrepareBootstrap | LocalKey::RecoverState => {
+                children
+                    .push(ParsingTrace::leaf(1, 1, "key", EncodeMethod::EnumFlag).with_value(self));
+                return ParsingTrace::node("local_key", children).with_value(self);
+            }
+            LocalKey::RaftLog { region_id, .. } => ("raft", region_id, "raft log"),
+            LocalKey::RaftState { region_id } => ("raft", region_id, "raft state"),
+            LocalKey::ApplyState { region_id } => ("raft", region_id, "apply state"),
+            LocalKey::SnapshotRaftState { region_id } => ("raft", region_id, "snapshot raft state"),
+            LocalKey::RegionState { region_id } => ("meta", region_id, "region state"),
+        };
+        children.push(
+            ParsingTrace::leaf(1, 1, "region_prefix", EncodeMethod::EnumFlag)
+                .with_value(region_prefix),
+        );
+        children.push(
+            ParsingTrace::leaf(2, 8, "region_id", EncodeMethod::BigEndian).with_value(region_id),
+        );
+        children
+            .push(ParsingTrace::leaf(10, 1, "suffix", EncodeMethod::EnumFlag).with_value(suffix));
+        if let LocalKey::RaftLog { index, .. } = *self {
+            children.push(
+                ParsingTrace::leaf(11, 8, "index", EncodeMethod::BigEndian).with_value(index),
+            );
+        }
+        ParsingTrace::node("local_key", children).with_value(self)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let region_key = |prefix: u8, region_id: u64, suffix: u8| {
+            let mut b = vec![LOCAL_PREFIX, prefix];
+            b.extend_from_slice(&region_id.to_be_bytes());
+            b.push(suffix);
+            b
+        };
+        match *self {
+            LocalKey::StoreIdent => STORE_IDENT_KEY.to_vec(),
+            LocalKey::PrepareBootstrap => PREPARE_BOOTSTRAP_KEY.to_vec(),
+            LocalKey::RecoverState => RECOVER_STATE_KEY.to_vec(),
+            LocalKey::RaftLog { region_id, index } => {
+                let mut b = region_key(REGION_RAFT_PREFIX, region_id, RAFT_LOG_SUFFIX);
+                b.extend_from_slice(&index.to_be_bytes());
+                b
+            }
+            LocalKey::RaftState { region_id } => {
+                region_key(REGION_RAFT_PREFIX, region_id, RAFT_STATE_SUFFIX)
+            }
+            LocalKey::ApplyState { region_id } => {
+                region_key(REGION_RAFT_PREFIX, region_id, APPLY_STATE_SUFFIX)
+            }
+            LocalKey::SnapshotRaftState { region_id } => {
+                region_key(REGION_RAFT_PREFIX, region_id, SNAPSHOT_RAFT_STATE_SUFFIX)
+            }
+            LocalKey::RegionState { region_id } => {
+                region_key(REGION_META_PREFIX, region_id, REGION_STATE_SUFFIX)
+            }
+        }
+    }
+}
+
+impl fmt::Display for LocalKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LocalKey::StoreIdent => write!(f, "store ident"),
+            LocalKey::PrepareBootstrap => write!(f, "prepare bootstrap"),
+            LocalKey::RecoverState => write!(f, "recover state"),
+            LocalKey::RaftLog { region_id, index } => {
+                write!(f, "region {} raft log {}", region_id, index)
+            }
+            LocalKey::RaftState { region_id } => write!(f, "region {} raft state", region_id),
+            LocalKey::ApplyState { region_id } => write!(f, "region {} apply state", region_id),
+            LocalKey::SnapshotRaftState { region_id } => {
+                write!(f, "region {} snapshot raft state", region_id)
+            }
+            LocalKey::RegionState { region_id } => write!(f, "region {} region state", region_id),
+        }
+    }
+}
+
+/// The decoded value of a local key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum LocalValue {
+    RaftLocalState(RaftLocalState),
+    RaftApplyState(RaftApplyState),
+    RegionLocalState(Box<RegionLocalState>),
+    StoreIdent(StoreIdent),
+    RaftLogEntry(Box<Entry>),
+}
+
+impl fmt::Display for LocalValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LocalValue::RaftLocalState(state) => write!(f, "{}", state),
+            LocalValue::RaftApplyState(state) => write!(f, "{}", state),
+            LocalValue::RegionLocalState(state) => write!(f, "{}", state),
+            LocalValue::StoreIdent(ident) => write!(f, "{}", ident),
+            LocalValue::RaftLogEntry(entry) => write!(f, "{}", entry),
+        }
+    }
+}
+
+/// Decode `value` according to what kind of local key `key` is.
+pub fn decode_local_value(key: &[u8], value: &[u8]) -> anyhow::Result<(LocalKey, LocalValue)> {
+    let local_key = LocalKey::parse(key)?;
+    let local_value = match local_key {
+        LocalKey::RaftState { .. } | LocalKey::SnapshotRaftState { .. } => {
+            LocalValue::RaftLocalState(RaftLocalState::decode(value)?)
+        }
+        LocalKey::ApplyState { .. } => LocalValue::RaftApplyState(RaftApplyState::decode(value)?),
+        LocalKey::RegionState { .. } => {
+            LocalValue::RegionLocalState(Box::new(RegionLocalState::decode(value)?))
+        }
+        LocalKey::StoreIdent => LocalValue::StoreIdent(StoreIdent::decode(value)?),
+        LocalKey::RaftLog { .. } => LocalValue::RaftLogEntry(Box::new(Entry::decode(value)?)),
+        _ => bail!("don't know how to decode the value of {}", local_key),
+    };
+    Ok((local_key, local_value))
+}
+
+#[wasm_bindgen]
+pub fn parse_local_value(key: &[u8], value: &[u8]) -> Result<JsValue, JsValue> {
+    decode_local_value(key, value)
+        .map(|(_, value)| utils::to_js_value(&value))
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[wasm_bindgen]
+pub fn explain_local_value(key: &[u8], value: &[u8]) -> Result<String, JsValue> {
+    decode_local_value(key, value)
+        .map(|(key, value)| format!("{}: {}", key, value))
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_local_key() {
+        let cases = vec![
+            (vec![1, 1], LocalKey::StoreIdent),
+            (
+                vec![1, 2, 0, 0, 0, 0, 0, 0, 0, 42, 1, 0, 0, 0, 0, 0, 0, 0, 5],
+                LocalKey::RaftLog {
+                    region_id: 42,
+                    index: 5,
+                },
+            ),
+            (
+                vec![1, 2, 0, 0, 0, 0, 0, 0, 0, 42, 3],
+                LocalKey::ApplyState { region_id: 42 },
+            ),
+            (
+                vec![1, 3, 0, 0, 0, 0, 0, 0, 0, 42, 1],
+                LocalKey::RegionState { region_id: 42 },
+            ),
+        ];
+        for (code, expected) in cases {
+            let key = LocalKey::parse(&code).unwrap();
+            assert_eq!(key, expected);
+            assert_eq!(key.to_bytes(), code);
+            assert_eq!(key.trace().width, code.len());
+        }
+        assert_eq!(
+            LocalKey::ApplyState { region_id: 42 }.to_string(),
+            "region 42 apply state"
+        );
+        assert!(LocalKey::parse(&[1, 2, 0, 0, 0, 0, 0, 0, 0, 42, 9]).is_err());
+    }
+
+    #[test]
+    fn test_decode_local_value() {
+        let key = LocalKey::ApplyState { region_id: 42 }.to_bytes();
+        // applied_index: 10, truncated_state: {index: 5, term: 5}
+        let value = vec![0x08, 10, 0x12, 4, 0x08, 5, 0x10, 5];
+        let (_, value) = decode_local_value(&key, &value).unwrap();
+        assert_eq!(
+            value.to_string(),
+            "applied_index: 10, truncated_state: {index: 5, term: 5}, last_commit_index: 0, \
+             commit_index: 0, commit_term: 0"
+        );
+        let key = LocalKey::StoreIdent.to_bytes();
+        let (_, value) = decode_local_value(&key, &[0x08, 1, 0x10, 4]).unwrap();
+        assert_eq!(
+            value.to_string(),
+            "cluster_id: 1, store_id: 4, api_version: V1"
+        );
+    }
+}
@@ -0,0 +1,471 @@
+//! Values in the lock CF.
+use crate::explain;
+use crate::mvcc::{TimeStamp, Value, SHORT_VALUE_PREFIX};
+use crate::trace::{EncodeMethod, ParsingTrace, TracingReader};
+use crate::utils;
+use crate::varint;
+use anyhow::anyhow;
+use serde::Serialize;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+const FLAG_PUT: u8 = b'P';
+const FLAG_DELETE: u8 = b'D';
+const FLAG_LOCK: u8 = b'L';
+const FLAG_PESSIMISTIC: u8 = b'S';
+
+const FOR_UPDATE_TS_PREFIX: u8 = b'f';
+const TXN_SIZE_PREFIX: u8 = b't';
+const MIN_COMMIT_TS_PREFIX: u8 = b'c';
+const ASYNC_COMMIT_PREFIX: u8 = b'a';
+const ROLLBACK_TS_PREFIX: u8 = b'r';
+const LAST_CHANGE_PREFIX: u8 = b'l';
+const TXN_SOURCE_PREFIX: u8 = b's';
+const PESSIMISTIC_LOCK_WITH_CONFLICT_PREFIX: u8 = b'F';
+const GENERATION_PREFIX: u8 = b'g';
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum LockType {
+    Put,
+    Delete,
+    Lock,
+    Pessimistic,
+}
+
+#[wasm_bindgen]
+pub fn lock_type_from_u8(b: u8) -> Option<LockType> {
+    match b {
+        FLAG_PUT => Some(LockType::Put),
+        FLAG_DELETE => Some(LockType::Delete),
+        FLAG_LOCK => Some(LockType::Lock),
+        FLAG_PESSIMISTIC => Some(LockType::Pessimistic),
+        _ => None,
+    }
+}
+
+#[wasm_bindgen]
+pub fn lock_type_to_u8(l: LockType) -> u8 {
+    match l {
+        LockType::Put => FLAG_PUT,
+        LockType::Delete => FLAG_DELETE,
+        LockType::Lock => FLAG_LOCK,
+        LockType::Pessimistic => FLAG_PESSIMISTIC,
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lock {
+    pub lock_type: LockType,
+    primary: Vec<u8>,
+    pub ts: TimeStamp,
+    pub ttl: u64,
+    short_value: Option<Value>,
+    pub for_update_ts: TimeStamp,
+    pub txn_size: u64,
+    pub min_commit_ts: TimeStamp,
+    pub use_async_commit: bool,
+    secondaries: Vec<Vec<u8>>,
+    rollback_ts: Vec<TimeStamp>,
+    pub last_change_ts: TimeStamp,
+    pub versions_to_last_change: u64,
+    pub txn_source: u64,
+    pub is_locked_with_conflict: bool,
+    pub generation: u64,
+    parsing_trace: ParsingTrace,
+}
+
+impl Lock {
+    pub fn new(lock_type: LockType, primary: Vec<u8>, ts: TimeStamp, ttl: u64) -> Lock {
+        Lock {
+            lock_type,
+            primary,
+            ts,
+            ttl,
+            short_value: None,
+            for_update_ts: TimeStamp::zero(),
+            txn_size: 0,
+            min_commit_ts: TimeStamp::zero(),
+            use_async_commit: false,
+            secondaries: Vec::new(),
+            rollback_ts: Vec::new(),
+            last_change_ts: TimeStamp::zero(),
+            versions_to_last_change: 0,
+            txn_source: 0,
+            is_locked_with_conflict: false,
+            generation: 0,
+            parsing_trace: ParsingTrace::default(),
+        }
+    }
+
+    pub fn with_short_value(mut self, short_value: Value) -> Lock {
+        self.short_value = Some(short_value);
+        self
+    }
+
+    pub fn with_async_commit(mut self, secondaries: Vec<Vec<u8>>) -> Lock {
+        self.use_async_commit = true;
+        self.secondaries = secondaries;
+        self
+    }
+
+    pub fn with_rollback_ts(mut self, rollback_ts: Vec<TimeStamp>) -> Lock {
+        self.rollback_ts = rollback_ts;
+        self
+    }
+
+    pub fn primary_key(&self) -> &[u8] {
+        &self.primary
+    }
+
+    pub fn short_value_ref(&self) -> Option<&Value> {
+        self.short_value.as_ref()
+    }
+
+    pub fn secondary_keys(&self) -> &[Vec<u8>] {
+        &self.secondaries
+    }
+
+    pub fn rollback_ts_list(&self) -> &[TimeStamp] {
+        &self.rollback_ts
+    }
+
+    /// The physical time in milliseconds when the lock expires.
+    pub fn expire_physical(&self) -> u64 {
+        self.ts.physical() + self.ttl
+    }
+
+    /// Whether the lock has expired at `current_ts`, so others may resolve it.
+    pub fn is_expired(&self, current_ts: TimeStamp) -> bool {
+        self.expire_physical() < current_ts.physical()
+    }
+
+    /// Whether this is the lock `FLASHBACK CLUSTER` puts on the first key of a region
+    /// when preparing, which has no ttl and the memcomparable `encoded_key` itself as the
+    /// primary, while the primary of a transaction is a raw key. It's a `Delete` lock if
+    /// there is no version to restore.
+    pub fn is_flashback_lock(&self, encoded_key: &[u8]) -> bool {
+        matches!(self.lock_type, LockType::Put | LockType::Delete)
+            && self.ttl == 0
+            && self.for_update_ts.is_zero()
+            && self.primary == encoded_key
+    }
+
+    fn rollback_ts_values(&self) -> Vec<u64> {
+        self.rollback_ts.iter().map(|ts| ts.0).collect()
+    }
+
+    pub fn trace(&self) -> &ParsingTrace {
+        &self.parsing_trace
+    }
+
+    pub fn parse_rust(b: &[u8]) -> anyhow::Result<Lock> {
+        let mut reader = TracingReader::new(b);
+        let lock_type_byte = reader.read_u8("lock_type", EncodeMethod::EnumFlag)?;
+        let lock_type = lock_type_from_u8(lock_type_byte)
+            .ok_or_else(|| anyhow!("invalid lock type {}", lock_type_byte))?;
+        reader.annotate(format!("{:?}", lock_type));
+        let primary = reader.read_compact_bytes("primary")?.to_vec();
+        let ts = reader.read_var_u64("start_ts")?.into();
+        let ttl = reader.read_var_u64("ttl")?;
+        let mut lock = Lock::new(lock_type, primary, ts, ttl);
+
+        while let Some(prefix) = reader.peek() {
+            match prefix {
+                SHORT_VALUE_PREFIX => {
+                    reader.begin("short_value");
+                    reader.read_u8("flag", EncodeMethod::EnumFlag)?;
+                    let len = reader.read_u8("length", EncodeMethod::SingleByte)?;
+                    reader.annotate(len);
+                    let value = reader.read_bytes(len as _, "value")?;
+                    reader.end(explain::escape(value));
+                    lock.short_value = Some(value.to_vec());
+                }
+                FOR_UPDATE_TS_PREFIX => {
+                    reader.begin("for_update_ts");
+                    reader.read_u8("flag", EncodeMethod::EnumFlag)?;
+                    lock.for_update_ts = reader.read_u64("for_update_ts")?.into();
+                    reader.end(lock.for_update_ts);
+                }
+                TXN_SIZE_PREFIX => {
+                    reader.begin("txn_size");
+                    reader.read_u8("flag", EncodeMethod::EnumFlag)?;
+                    lock.txn_size = reader.read_u64("txn_size")?;
+                    reader.end(lock.txn_size);
+                }
+                MIN_COMMIT_TS_PREFIX => {
+                    reader.begin("min_commit_ts");
+                    reader.read_u8("flag", EncodeMethod::EnumFlag)?;
+                    lock.min_commit_ts = reader.read_u64("min_commit_ts")?.into();
+                    reader.end(lock.min_commit_ts);
+                }
+                ASYNC_COMMIT_PREFIX => {
+                    reader.begin("secondaries");
+                    reader.read_u8("flag", EncodeMethod::EnumFlag)?;
+                    lock.use_async_commit = true;
+                    let count = reader.read_var_u64("count")?;
+                    for _ in 0..count {
+                        let secondary = reader.read_compact_bytes("secondary")?;
+                        lock.secondaries.push(secondary.to_vec());
+                    }
+                    reader.end(format!("{} keys", count));
+                }
+                ROLLBACK_TS_PREFIX => {
+                    reader.begin("rollback_ts");
+                    reader.read_u8("flag", EncodeMethod::EnumFlag)?;
+                    let count = reader.read_var_u64("count")?;
+                    for _ in 0..count {
+                        lock.rollback_ts
+                            .push(reader.read_u64("rollback_ts")?.into());
+                    }
+                    reader.end(format!("{:?}", lock.rollback_ts_values()));
+                }
+                LAST_CHANGE_PREFIX => {
+                    reader.begin("last_change");
+                    reader.read_u8("flag", EncodeMethod::EnumFlag)?;
+                    lock.last_change_ts = reader.read_u64("last_change_ts")?.into();
+                    lock.versions_to_last_change =
+                        reader.read_var_u64("versions_to_last_change")?;
+                    reader.end(lock.last_change_ts);
+                }
+                TXN_SOURCE_PREFIX => {
+                    reader.begin("txn_source");
+                    reader.read_u8("flag", EncodeMethod::EnumFlag)?;
+                    lock.txn_source = reader.read_var_u64("txn_source")?;
+                    reader.end(lock.txn_source);
+                }
+                PESSIMISTIC_LOCK_WITH_CONFLICT_PREFIX => {
+                    reader.read_u8("locked_with_conflict", EncodeMethod::EnumFlag)?;
+                    reader.annotate(true);
+                    lock.is_locked_with_conflict = true;
+                }
+                GENERATION_PREFIX => {
+                    reader.begin("generation");
+                    reader.read_u8("flag", EncodeMethod::EnumFlag)?;
+                    lock.generation = reader.read_u64("generation")?;
+                    reader.end(lock.generation);
+                }
+                _ => {
+                    // Same as `Write`, stop parsing at an unknown byte for forward compatibility.
+                    reader.read_rest("unknown");
+                    break;
+                }
+            }
+        }
+        lock.parsing_trace = reader.finish("lock", &lock);
+        Ok(lock)
+    }
+}
+
+fn encode_compact_bytes(b: &mut Vec<u8>, data: &[u8]) {
+    b.extend_from_slice(&varint::encode_i64(data.len() as i64));
+    b.extend_from_slice(data);
+}
+
+#[wasm_bindgen]
+impl Lock {
+    pub fn parse(b: &[u8]) -> Result<Lock, JsValue> {
+        Self::parse_rust(b).map_err(|_| JsValue::from_str("Cannot parse Lock!"))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut b = vec![lock_type_to_u8(self.lock_type)];
+        encode_compact_bytes(&mut b, &self.primary);
+        b.extend_from_slice(&varint::encode_u64(self.ts.0));
+        b.extend_from_slice(&varint::encode_u64(self.ttl));
+        if let Some(v) = &self.short_value {
+            b.push(SHORT_VALUE_PREFIX);
+            b.push(v.len() as u8);
+            b.extend_from_slice(v);
+        }
+        if !self.for_update_ts.is_zero() {
+            b.push(FOR_UPDATE_TS_PREFIX);
+            b.extend_from_slice(&self.for_update_ts.0.to_be_bytes());
+        }
+        if self.txn_size > 0 {
+            b.push(TXN_SIZE_PREFIX);
+            b.extend_from_slice(&self.txn_size.to_be_bytes());
+        }
+        if !self.min_commit_ts.is_zero() {
+            b.push(MIN_COMMIT_TS_PREFIX);
+            b.extend_from_slice(&self.min_commit_ts.0.to_be_bytes());
+        }
+        if self.use_async_commit {
+            b.push(ASYNC_COMMIT_PREFIX);
+            b.extend_from_slice(&varint::encode_u64(self.secondaries.len() as u64));
+            for k in &self.secondaries {
+                encode_compact_bytes(&mut b, k);
+            }
+        }
+        if !self.rollback_ts.is_empty() {
+            b.push(ROLLBACK_TS_PREFIX);
+            b.extend_from_slice(&varint::encode_u64(self.rollback_ts.len() as u64));
+            for ts in &self.rollback_ts {
+                b.extend_from_slice(&ts.0.to_be_bytes());
+            }
+        }
+        if !self.last_change_ts.is_zero() || self.versions_to_last_change != 0 {
+            b.push(LAST_CHANGE_PREFIX);
+            b.extend_from_slice(&self.last_change_ts.0.to_be_bytes());
+            b.extend_from_slice(&varint::encode_u64(self.versions_to_last_change));
+        }
+        if self.txn_source != 0 {
+            b.push(TXN_SOURCE_PREFIX);
+            b.extend_from_slice(&varint::encode_u64(self.txn_source));
+        }
+        if self.is_locked_with_conflict {
+            b.push(PESSIMISTIC_LOCK_WITH_CONFLICT_PREFIX);
+        }
+        if self.generation != 0 {
+            b.push(GENERATION_PREFIX);
+            b.extend_from_slice(&self.generation.to_be_bytes());
+        }
+        b
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn primary(&self) -> Vec<u8> {
+        self.primary.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn short_value(&self) -> Option<Value> {
+        self.short_value.clone()
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_primary(&mut self, primary: Vec<u8>) {
+        self.primary = primary;
+    }
+
+    #[wasm_bindgen(setter)]
+    pub fn set_short_value(&mut self, value: Option<Value>) {
+        self.short_value = value;
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn secondaries(&self) -> JsValue {
+        utils::to_js_value(&self.secondaries)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn rollback_ts(&self) -> JsValue {
+        utils::to_js_value(&self.rollback_ts)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn parsing_trace(&self) -> JsValue {
+        utils::to_js_value(&self.parsing_trace)
+    }
+}
+
+impl fmt::Display for Lock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?}, primary: {}, start_ts: {}, ttl: {}",
+            self.lock_type,
+            explain::explain_key(&self.primary),
+            self.ts,
+            self.ttl
+        )?;
+        if let Some(v) = &self.short_value {
+            write!(f, ", short_value: {}", explain::escape(v))?;
+        }
+        if !self.for_update_ts.is_zero() {
+            write!(f, ", for_update_ts: {}", self.for_update_ts)?;
+        }
+        if self.txn_size != 0 {
+            write!(f, ", txn_size: {}", self.txn_size)?;
+        }
+        if !self.min_commit_ts.is_zero() {
+            write!(f, ", min_commit_ts: {}", self.min_commit_ts)?;
+        }
+        if self.use_async_commit {
+            write!(f, ", async_commit secondaries: [")?;
+            for (i, k) in self.secondaries.iter().enumerate() {
+                if i != 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", explain::explain_key(k))?;
+            }
+            write!(f, "]")?;
+        }
+        if !self.rollback_ts.is_empty() {
+            write!(
+                f,
+                ", rollback_ts: {:?}",
+                self.rollback_ts.iter().map(|ts| ts.0).collect::<Vec<_>>()
+            )?;
+        }
+        if !self.last_change_ts.is_zero() || self.versions_to_last_change != 0 {
+            write!(
+                f,
+                ", last_change_ts: {}, versions_to_last_change: {}",
+                self.last_change_ts, self.versions_to_last_change
+            )?;
+        }
+        if self.txn_source != 0 {
+            write!(f, ", txn_source: {}", self.txn_source)?;
+        }
+        if self.is_locked_with_conflict {
+            write!(f, ", locked_with_conflict")?;
+        }
+        if self.generation != 0 {
+            write!(f, ", generation: {}", self.generation)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lock() {
+        let mut lock = Lock::new(LockType::Put, b"pk".to_vec(), TimeStamp(100), 3000)
+            .with_short_value(b"v".to_vec())
+            .with_async_commit(vec![b"k1".to_vec(), b"k2".to_vec()]);
+        lock.min_commit_ts = TimeStamp(101);
+        lock.for_update_ts = TimeStamp(99);
+        lock.txn_size = 2;
+        lock.rollback_ts = vec![TimeStamp(98)];
+        lock.txn_source = 1;
+        let code = lock.to_bytes();
+        let parsed = Lock::parse_rust(&code).unwrap();
+        let trace = parsed.trace();
+        assert_eq!(trace.width, code.len());
+        assert_eq!(trace.leaves().len(), 25);
+        let secondaries = &trace.children[8];
+        assert_eq!(secondaries.field, "secondaries");
+        assert_eq!(secondaries.value.as_deref(), Some("2 keys"));
+        assert_eq!(secondaries.children[2].value.as_deref(), Some("\"k1\""));
+        lock.parsing_trace = parsed.parsing_trace.clone();
+        assert_eq!(parsed, lock);
+        assert_eq!(
+            parsed.to_string(),
+            "Put, primary: \"pk\", start_ts: 100, ttl: 3000, short_value: \"v\", \
+             for_update_ts: 99, txn_size: 2, min_commit_ts: 101, \
+             async_commit secondaries: [\"k1\", \"k2\"], rollback_ts: [98], txn_source: 1"
+        );
+    }
+
+    #[test]
+    fn test_parse_lock_bytes() {
+        // A pessimistic lock on `a`, with ttl 5000 and for_update_ts 200.
+        let code = vec![
+            83, 2, 97, 128, 128, 128, 128, 128, 128, 128, 128, 1, 136, 39, 102, 0, 0, 0, 0, 0, 0,
+            0, 200,
+        ];
+        let lock = Lock::parse_rust(&code).unwrap();
+        assert_eq!(lock.lock_type, LockType::Pessimistic);
+        assert_eq!(lock.primary, b"a");
+        assert_eq!(lock.ts, TimeStamp(1 << 56));
+        assert_eq!(lock.ttl, 5000);
+        assert_eq!(lock.for_update_ts, TimeStamp(200));
+        assert_eq!(lock.to_bytes(), code);
+        assert!(Lock::parse_rust(&[b'X', 0]).is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! Tell whether a lock has expired, and what a reader resolving it would do.
+//!
+//! The time of a lock is the physical part of its start ts, and it expires `ttl`
+//! milliseconds later. 1PC transactions write their commit records directly without
+//! leaving any lock, so a lock found is always of a pessimistic, 2PC or async-commit
+//! transaction.
+use crate::explain;
+use crate::lock::{Lock, LockType};
+use crate::mvcc::TimeStamp;
+use crate::utils;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LockKind {
+    /// Acquired by a pessimistic transaction, but not prewritten yet.
+    Pessimistic,
+    /// Prewritten by an optimistic 2PC transaction.
+    Optimistic,
+    /// Prewritten by a pessimistic 2PC transaction, which has a for_update_ts.
+    PessimisticPrewrite,
+    AsyncCommit,
+}
+
+/// What the lock resolver of a reader does with the lock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// The lock is alive, the reader waits, or pushes its min_commit_ts.
+    Wait { remaining_ms: u64 },
+    /// A read at the current ts ignores the lock, as the transaction will commit later.
+    Ignore { min_commit_ts: TimeStamp },
+    /// The expired pessimistic lock is removed, it never blocks reads.
+    RemovePessimisticLock,
+    /// The primary lock has expired, the transaction is rolled back.
+    RollBack,
+    /// The secondary lock is committed or rolled back as the primary is.
+    FollowPrimary(Vec<u8>),
+    /// The transaction is committed at the max min_commit_ts if all the secondaries are
+    /// prewritten, or rolled back otherwise.
+    CheckSecondaries {
+        secondaries: usize,
+        min_commit_ts: TimeStamp,
+    },
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resolution::Wait { remaining_ms } => {
+                write!(f, "wait for {}ms, or push the min_commit_ts", remaining_ms)
+            }
+            Resolution::Ignore { min_commit_ts } => write!(
+                f,
+                "ignore it when reading at the current ts, the min_commit_ts {} is newer",
+                min_commit_ts
+            ),
+            Resolution::RemovePessimisticLock => write!(f, "remove the pessimistic lock"),
+            Resolution::RollBack => write!(f, "roll back the transaction"),
+            Resolution::FollowPrimary(primary) => write!(
+                f,
+                "check the primary {}, commit or roll back as it is",
+                explain::explain_key(primary)
+            ),
+            Resolution::CheckSecondaries {
+                secondaries,
+                min_commit_ts,
+            } => write!(
+                f,
+                "check the {} secondaries, commit at the max min_commit_ts (at least {}) \
+                 if all are prewritten, otherwise roll back",
+                secondaries, min_commit_ts
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockStatus {
+    pub kind: LockKind,
+    pub is_primary: bool,
+    /// The physical time of the start ts and the expiry, in milliseconds since unix epoch.
+    pub start_physical: u64,
+    pub expire_physical: u64,
+    pub expired: bool,
+    pub resolution: Resolution,
+}
+
+impl fmt::Display for LockStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:?} lock of the {} key",
+            self.kind,
+            if self.is_primary {
+                "primary"
+            } else {
+                "secondary"
+            }
+        )?;
+        writeln!(
+            f,
+            "started at {}",
+            utils::format_unix_millis(self.start_physical)
+        )?;
+        writeln!(
+            f,
+            "{} at {}",
+            if self.expired { "expired" } else { "expires" },
+            utils::format_unix_millis(self.expire_physical)
+        )?;
+        write!(f, "=> {}", self.resolution)
+    }
+}
+
+/// Analyze the lock on `key` at `current_ts`.
+pub fn analyze_lock(key: &[u8], lock: &Lock, current_ts: TimeStamp) -> LockStatus {
+    let kind = if lock.lock_type == LockType::Pessimistic {
+        LockKind::Pessimistic
+    } else if lock.use_async_commit {
+        LockKind::AsyncCommit
+    } else if !lock.for_update_ts.is_zero() {
+        LockKind::PessimisticPrewrite
+    } else {
+        LockKind::Optimistic
+    };
+    // The lock of a transaction having a single key may not record the primary.
+    let is_primary = lock.primary_key() == key || lock.primary_key().is_empty();
+    let expired = lock.is_expired(current_ts);
+    let resolution = if !expired && lock.min_commit_ts > current_ts {
+        Resolution::Ignore {
+            min_commit_ts: lock.min_commit_ts,
+        }
+    } else if !expired {
+        Resolution::Wait {
+            remaining_ms: lock.expire_physical() + 1 - current_ts.physical(),
+        }
+    } else {
+        match kind {
+            LockKind::Pessimistic => Resolution::RemovePessimisticLock,
+            LockKind::AsyncCommit => {
+                // Only the primary lock records the secondaries.
+                if is_primary {
+                    Resolution::CheckSecondaries {
+                        secondaries: lock.secondary_keys().len(),
+                        min_commit_ts: lock.min_commit_ts,
+                    }
+                } else {
+                    Resolution::FollowPrimary(lock.primary_key().to_vec())
+                }
+            }
+            LockKind::Optimistic | LockKind::PessimisticPrewrite if is_primary => {
+                Resolution::RollBack
+            }
+            LockKind::Optimistic | LockKind::PessimisticPrewrite => {
+                Resolution::FollowPrimary(lock.primary_key().to_vec())
+            }
+        }
+    };
+    LockStatus {
+        kind,
+        is_primary,
+        start_physical: lock.ts.physical(),
+        expire_physical: lock.expire_physical(),
+        expired,
+        resolution,
+    }
+}
+
+/// Explain the lock in the lock CF value at the TSO `current_ts`.
+#[wasm_bindgen]
+pub fn explain_lock_status(key: &[u8], value: &[u8], current_ts: u64) -> Result<String, JsValue> {
+    Lock::parse_rust(value)
+        .map(|lock| analyze_lock(key, &lock, TimeStamp(current_ts)).to_string())
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::NOW;
+
+    fn ts(physical: u64) -> TimeStamp {
+        TimeStamp::compose(physical, 1)
+    }
+
+    #[test]
+    fn test_analyze_lock() {
+        let lock = Lock::new(LockType::Put, b"pk".to_vec(), ts(NOW - 3000), 3000);
+        let status = analyze_lock(b"pk", &lock, ts(NOW));
+        assert_eq!(status.kind, LockKind::Optimistic);
+        assert_eq!(status.resolution, Resolution::Wait { remaining_ms: 1 });
+        assert_eq!(
+            status.to_string(),
+            "Optimistic lock of the primary key\n\
+             started at 2021-06-02 09:24:58.123 UTC\n\
+             expires at 2021-06-02 09:25:01.123 UTC\n\
+             => wait for 1ms, or push the min_commit_ts"
+        );
+        let status = analyze_lock(b"pk", &lock, ts(NOW + 1));
+        assert!(status.expired);
+        assert_eq!(status.resolution, Resolution::RollBack);
+        assert_eq!(
+            analyze_lock(b"sk", &lock, ts(NOW + 1)).resolution,
+            Resolution::FollowPrimary(b"pk".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_analyze_lock_kinds() {
+        let lock = Lock::new(LockType::Pessimistic, b"pk".to_vec(), ts(NOW), 20000);
+        let status = analyze_lock(b"sk", &lock, ts(NOW + 20001));
+        assert_eq!(status.kind, LockKind::Pessimistic);
+        assert_eq!(status.resolution, Resolution::RemovePessimisticLock);
+        let mut lock = Lock::new(LockType::Put, b"pk".to_vec(), ts(NOW), 3000);
+        lock.for_update_ts = ts(NOW + 1);
+        let status = analyze_lock(b"pk", &lock, ts(NOW + 3001));
+        assert_eq!(status.kind, LockKind::PessimisticPrewrite);
+        assert_eq!(status.resolution, Resolution::RollBack);
+
+        let mut lock = Lock::new(LockType::Put, b"pk".to_vec(), ts(NOW), 3000)
+            .with_async_commit(vec![b"s1".to_vec(), b"s2".to_vec()]);
+        lock.min_commit_ts = ts(NOW + 10);
+        assert_eq!(
+            analyze_lock(b"pk", &lock, ts(NOW + 1)).resolution,
+            Resolution::Ignore {
+                min_commit_ts: ts(NOW + 10)
+            }
+        );
+        let status = analyze_lock(b"pk", &lock, ts(NOW + 5000));
+        assert_eq!(status.kind, LockKind::AsyncCommit);
+        assert_eq!(
+            status.resolution.to_string(),
+            format!(
+                "check the 2 secondaries, commit at the max min_commit_ts (at least {}) \
+                 if all are prewritten, otherwise roll back",
+                ts(NOW + 10)
+            )
+        );
+    }
+}
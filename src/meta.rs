@@ -0,0 +1,156 @@
+//! Keys of TiDB's meta structure (the `m` prefixed keys), where schemas and global ids are saved.
+//!
+//! A meta key is `m` + encoded(key) + the type flag as an u64, followed by the
+//! encoded hash field or the list index for data keys of hashes and lists.
+//! For example the info of table 53 in database 1 is at `meta("DB:1").h("Table:53")`.
+use crate::chunk;
+use crate::db_to_kv;
+use crate::explain;
+use crate::trace::{EncodeMethod, ParsingTrace, TracingReader};
+use anyhow::{anyhow, bail};
+use std::convert::TryFrom;
+use std::fmt;
+
+pub const META_PREFIX: u8 = b'm';
+
+const STRING_META: u8 = b'S';
+const STRING_DATA: u8 = b's';
+const HASH_META: u8 = b'H';
+const HASH_DATA: u8 = b'h';
+const LIST_META: u8 = b'L';
+const LIST_DATA: u8 = b'l';
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetaField {
+    StringMeta,
+    StringData,
+    HashMeta,
+    HashData(Vec<u8>),
+    ListMeta,
+    ListData(i64),
+}
+
+impl MetaField {
+    pub fn flag(&self) -> u8 {
+        match self {
+            MetaField::StringMeta => STRING_META,
+            MetaField::StringData => STRING_DATA,
+            MetaField::HashMeta => HASH_META,
+            MetaField::HashData(_) => HASH_DATA,
+            MetaField::ListMeta => LIST_META,
+            MetaField::ListData(_) => LIST_DATA,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetaKey {
+    pub key: Vec<u8>,
+    pub field: MetaField,
+}
+
+impl MetaKey {
+    pub fn parse(b: &[u8]) -> anyhow::Result<MetaKey> {
+        Self::parse_with_trace(b).map(|(key, _)| key)
+    }
+
+    pub fn parse_with_trace(b: &[u8]) -> anyhow::Result<(MetaKey, ParsingTrace)> {
+        if b.first() != Some(&META_PREFIX) {
+            bail!("meta key should start with 'm'");
+        }
+        let mut reader = TracingReader::new(b);
+        reader.read_fixed(1, "meta_prefix", EncodeMethod::Literal)?;
+        reader.annotate("m");
+        let key = read_encoded(&mut reader, "key")?;
+        let flag = reader.read_u64("type")?;
+        let flag = u8::try_from(flag).map_err(|_| anyhow!("invalid meta type {}", flag))?;
+        reader.annotate(format!("{:?}", flag as char));
+        let field = match flag {
+            STRING_META => MetaField::StringMeta,
+            STRING_DATA => MetaField::StringData,
+            HASH_META => MetaField::HashMeta,
+            HASH_DATA => MetaField::HashData(read_encoded(&mut reader, "field")?),
+            LIST_META => MetaField::ListMeta,
+            LIST_DATA => {
+                let index = reader.read_fixed(8, "index", EncodeMethod::ComparableI64)?;
+                let index = db_to_kv::decode_i64(index)?;
+                reader.annotate(index);
+                MetaField::ListData(index)
+            }
+            _ => bail!("invalid meta type {}", flag),
+        };
+        if reader.peek().is_some() {
+            bail!("unexpected bytes after meta key");
+        }
+        let meta_key = MetaKey { key, field };
+        let trace = reader.finish("meta_key", &meta_key);
+        Ok((meta_key, trace))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut b = vec![META_PREFIX];
+        b.extend_from_slice(&chunk::encode_bytes(&self.key));
+        b.extend_from_slice(&(self.field.flag() as u64).to_be_bytes());
+        match &self.field {
+            MetaField::HashData(field) => b.extend_from_slice(&chunk::encode_bytes(field)),
+            MetaField::ListData(index) => b.extend_from_slice(&db_to_kv::encode_i64(*index)),
+            _ => {}
+        }
+        b
+    }
+}
+
+fn read_encoded(reader: &mut TracingReader, field: &str) -> anyhow::Result<Vec<u8>> {
+    let (raw, mut trace) = chunk::trace_bytes_prefix(reader.rest())
+        .ok_or_else(|| anyhow!("{} of meta key is not memcomparable encoded", field))?;
+    trace.field = field.to_string();
+    reader.push(trace);
+    Ok(raw)
+}
+
+impl fmt::Display for MetaKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "meta({}).{}",
+            explain::escape(&self.key),
+            self.field.flag() as char
+        )?;
+        match &self.field {
+            MetaField::HashData(field) => write!(f, "({})", explain::escape(field)),
+            MetaField::ListData(index) => write!(f, "({})", index),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meta_key() {
+        // The key of `DB:1`'s `Table:53` field, as TiDB writes it.
+        let mut code = b"m".to_vec();
+        code.extend_from_slice(b"DB:1\x00\x00\x00\x00\xfb");
+        code.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, b'h']);
+        code.extend_from_slice(b"Table:53\xff\x00\x00\x00\x00\x00\x00\x00\x00\xf7");
+        let (key, trace) = MetaKey::parse_with_trace(&code).unwrap();
+        assert_eq!(key.to_string(), "meta(\"DB:1\").h(\"Table:53\")");
+        assert_eq!(key.to_bytes(), code);
+        assert_eq!(trace.children[2].value.as_deref(), Some("'h'"));
+        assert_eq!(trace.width, code.len());
+
+        let key = MetaKey {
+            key: b"NextGlobalID".to_vec(),
+            field: MetaField::StringData,
+        };
+        assert_eq!(key.to_string(), "meta(\"NextGlobalID\").s");
+        let key = MetaKey {
+            key: b"q".to_vec(),
+            field: MetaField::ListData(-1),
+        };
+        assert_eq!(MetaKey::parse(&key.to_bytes()).unwrap(), key);
+        assert!(MetaKey::parse(b"mDBs").is_err());
+    }
+}
@@ -1,14 +1,75 @@
+use crate::chunk;
 use crate::endian;
+use crate::explain;
+use crate::lock::Lock;
+use crate::trace::TracingReader;
+pub use crate::trace::{EncodeMethod, ParsingTrace};
+use crate::utils;
 use crate::varint;
+use anyhow::{anyhow, bail};
 use serde::Serialize;
+use std::fmt;
 use wasm_bindgen::prelude::*;
 pub type CfName = &'static str;
 
 pub type Value = Vec<u8>;
 
+/// A key in the format TiKV's storage layer uses, ie. memcomparable encoded,
+/// with an optional timestamp appended.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Key(Vec<u8>);
 
+impl Key {
+    pub fn from_raw(key: &[u8]) -> Key {
+        Key(chunk::encode_bytes(key))
+    }
+
+    pub fn from_encoded(encoded_key: Vec<u8>) -> Key {
+        Key(encoded_key)
+    }
+
+    pub fn as_encoded(&self) -> &Vec<u8> {
+        &self.0
+    }
+
+    pub fn into_encoded(self) -> Vec<u8> {
+        self.0
+    }
+
+    /// Decode the user key, the timestamp (if any) is ignored.
+    pub fn to_raw(&self) -> anyhow::Result<Vec<u8>> {
+        chunk::decode_bytes_prefix(&self.0)
+            .map(|(raw, _)| raw)
+            .ok_or_else(|| anyhow!("key is not memcomparable encoded"))
+    }
+
+    /// Append the timestamp in descending order, so newer versions come first.
+    pub fn append_ts(mut self, ts: TimeStamp) -> Key {
+        self.0.extend_from_slice(&(!ts.0).to_be_bytes());
+        self
+    }
+
+    /// Split the key into the encoded user key and the timestamp appended to it.
+    pub fn split_on_ts(&self) -> anyhow::Result<(&[u8], TimeStamp)> {
+        if self.0.len() < 8 {
+            bail!("key is too short to have a timestamp");
+        }
+        let (key, ts) = self.0.split_at(self.0.len() - 8);
+        Ok((key, TimeStamp(!endian::big::decode_u64(ts))))
+    }
+
+    pub fn decode_ts(&self) -> anyhow::Result<TimeStamp> {
+        self.split_on_ts().map(|(_, ts)| ts)
+    }
+
+    pub fn truncate_ts(self) -> anyhow::Result<Key> {
+        let len = self.split_on_ts()?.0.len();
+        let mut encoded = self.0;
+        encoded.truncate(len);
+        Ok(Key(encoded))
+    }
+}
+
 const FLAG_PUT: u8 = b'P';
 const FLAG_DELETE: u8 = b'D';
 const FLAG_LOCK: u8 = b'L';
@@ -16,11 +77,16 @@ const FLAG_ROLLBACK: u8 = b'R';
 
 const FLAG_OVERLAPPED_ROLLBACK: u8 = b'R';
 const GC_FENCE_PREFIX: u8 = b'F';
+const LAST_CHANGE_PREFIX: u8 = b'l';
+const TXN_SOURCE_PREFIX: u8 = b'S';
 pub const CF_DEFAULT: CfName = "default";
 pub const CF_LOCK: CfName = "lock";
 pub const CF_WRITE: CfName = "write";
+pub const TSO_PHYSICAL_SHIFT_BITS: u64 = 18;
 pub const SHORT_VALUE_MAX_LEN: usize = 255;
 pub const SHORT_VALUE_PREFIX: u8 = b'v';
+/// The short value marking a rollback record as protected, which must not be collapsed.
+pub const PROTECTED_ROLLBACK_SHORT_VALUE: &[u8] = b"p";
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Modify {
@@ -28,10 +94,85 @@ pub enum Modify {
     Put(CfName, Key, Value),
 }
 
+impl fmt::Display for Modify {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Modify::Delete(cf, key) => {
+                write!(
+                    f,
+                    "delete {} {}",
+                    cf,
+                    explain::explain_key(key.as_encoded())
+                )
+            }
+            Modify::Put(cf, key, value) => {
+                write!(
+                    f,
+                    "put {} {} = ",
+                    cf,
+                    explain::explain_key(key.as_encoded())
+                )?;
+                let explained = match *cf {
+                    CF_LOCK => Lock::parse_rust(value).map(|lock| lock.to_string()),
+                    CF_WRITE => Write::parse_rust(value).map(|write| write.to_string()),
+                    _ => Ok(explain::escape(value)),
+                };
+                write!(
+                    f,
+                    "{}",
+                    explained.unwrap_or_else(|_| explain::escape(value))
+                )
+            }
+        }
+    }
+}
+
 #[wasm_bindgen]
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub struct TimeStamp(pub u64);
 
+impl TimeStamp {
+    pub fn zero() -> TimeStamp {
+        TimeStamp(0)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn max() -> TimeStamp {
+        TimeStamp(u64::MAX)
+    }
+
+    pub fn is_max(&self) -> bool {
+        self.0 == u64::MAX
+    }
+
+    pub fn compose(physical: u64, logical: u64) -> TimeStamp {
+        TimeStamp((physical << TSO_PHYSICAL_SHIFT_BITS) + logical)
+    }
+
+    /// The milliseconds since the unix epoch of a TSO.
+    pub fn physical(&self) -> u64 {
+        self.0 >> TSO_PHYSICAL_SHIFT_BITS
+    }
+
+    pub fn logical(&self) -> u64 {
+        self.0 & ((1 << TSO_PHYSICAL_SHIFT_BITS) - 1)
+    }
+
+    /// The physical time of a TSO in UTC.
+    pub fn wall_clock(&self) -> String {
+        utils::format_unix_millis(self.physical())
+    }
+}
+
+impl fmt::Display for TimeStamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 impl From<u64> for TimeStamp {
     fn from(t: u64) -> Self {
         TimeStamp(t)
@@ -39,7 +180,7 @@ impl From<u64> for TimeStamp {
 }
 
 #[wasm_bindgen]
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub enum WriteType {
     Put,
     Delete,
@@ -76,147 +217,166 @@ pub struct Write {
     short_value: Option<Value>,
     pub has_overlapped_rollback: bool,
     pub gc_fence: Option<TimeStamp>,
-    parsing_trace: Vec<ParsingTrace>,
+    /// The commit ts of the last version which is a `Put` or `Delete`,
+    /// only set on `Lock` and `Rollback` records.
+    pub last_change_ts: TimeStamp,
+    /// How many versions to skip to reach the last `Put` or `Delete`.
+    pub versions_to_last_change: u64,
+    pub txn_source: u64,
+    parsing_trace: ParsingTrace,
 }
 
-#[wasm_bindgen]
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
-pub enum EncodeMethod {
-    EnumFlag,
-    SingleByte,
-    Bytes,
-    BigEndian,
-    LittleEndian,
-    VarInt,
-}
+impl Write {
+    pub fn new(write_type: WriteType, start_ts: TimeStamp, short_value: Option<Value>) -> Write {
+        Write {
+            write_type,
+            start_ts,
+            short_value,
+            has_overlapped_rollback: false,
+            gc_fence: None,
+            last_change_ts: TimeStamp::zero(),
+            versions_to_last_change: 0,
+            txn_source: 0,
+            parsing_trace: ParsingTrace::default(),
+        }
+    }
 
-#[wasm_bindgen]
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
-pub struct ParsingTrace {
-    pub start: usize,
-    pub width: usize,
-    description: String,
-    encoded_in: EncodeMethod,
-}
+    pub fn new_rollback(start_ts: TimeStamp, protected: bool) -> Write {
+        let short_value = protected.then(|| PROTECTED_ROLLBACK_SHORT_VALUE.to_vec());
+        Write::new(WriteType::Rollback, start_ts, short_value)
+    }
 
-#[wasm_bindgen]
-impl ParsingTrace {
-    #[wasm_bindgen(getter)]
-    pub fn description(&self) -> String {
-        self.description.clone()
+    /// Whether this is a rollback record which must not be collapsed or removed early.
+    pub fn is_protected(&self) -> bool {
+        self.write_type == WriteType::Rollback
+            && self.short_value.as_deref() == Some(PROTECTED_ROLLBACK_SHORT_VALUE)
     }
-}
 
-#[wasm_bindgen]
-impl Write {
-    fn parse_rust(mut b: &[u8]) -> anyhow::Result<Write> {
-        let mut parsing_trace = vec![ParsingTrace {
-            start: 0,
-            width: 1,
-            description: "write_type".to_string(),
-            encoded_in: EncodeMethod::EnumFlag,
-        }];
-        let write_type_bytes = b[0];
-        b = &b[1..];
-        let write_type = write_type_from_u8(write_type_bytes).unwrap();
-        let (start_ts_u64, ts_width) = varint::decode_u64(b);
-        parsing_trace.push(ParsingTrace {
-            start: 1,
-            width: ts_width,
-            description: "start_ts".to_string(),
-            encoded_in: EncodeMethod::VarInt,
-        });
-        b = &b[ts_width..];
-        let start_ts = start_ts_u64.into();
+    pub fn short_value_ref(&self) -> Option<&Value> {
+        self.short_value.as_ref()
+    }
+
+    /// Whether this version, found as the latest one no newer than `read_ts`, can be
+    /// returned. It can't when GC has removed a newer version the read should have seen,
+    /// which the GC fence tells.
+    pub fn check_gc_fence_as_latest_version(&self, read_ts: TimeStamp) -> bool {
+        match self.gc_fence {
+            Some(fence) => fence.is_zero() || fence > read_ts,
+            None => true,
+        }
+    }
+
+    /// Explain the result of `check_gc_fence_as_latest_version`. `next_commit_ts` is the
+    /// commit ts of the next newer `Put` or `Delete` of the key, if it's known.
+    pub fn explain_gc_fence(
+        &self,
+        next_commit_ts: Option<TimeStamp>,
+        read_ts: TimeStamp,
+    ) -> (bool, String) {
+        let valid = self.check_gc_fence_as_latest_version(read_ts);
+        let mut reason = match self.gc_fence {
+            None => "no GC fence, the version is valid".to_string(),
+            Some(fence) if fence.is_zero() => {
+                "the GC fence is 0, there was no newer version when it was set".to_string()
+            }
+            Some(fence) if valid => format!(
+                "the newer version committed at {} is newer than the read at {}",
+                fence, read_ts
+            ),
+            Some(fence) => format!("a newer version committed at {} was removed by GC", fence),
+        };
+        match (self.gc_fence, next_commit_ts) {
+            (Some(fence), Some(next)) if next != fence => {
+                reason += &format!(", but the next version is committed at {}", next);
+            }
+            _ => {}
+        }
+        (valid, reason)
+    }
+
+    pub fn parse_rust(b: &[u8]) -> anyhow::Result<Write> {
+        let mut reader = TracingReader::new(b);
+        let write_type_byte = reader.read_u8("write_type", EncodeMethod::EnumFlag)?;
+        let write_type = write_type_from_u8(write_type_byte)
+            .ok_or_else(|| anyhow!("invalid write type {}", write_type_byte))?;
+        reader.annotate(format!("{:?}", write_type));
+        let start_ts = reader.read_var_u64("start_ts")?.into();
 
         let mut short_value = None;
         let mut has_overlapped_rollback = false;
         let mut gc_fence = None;
+        let mut last_change_ts = TimeStamp::zero();
+        let mut versions_to_last_change = 0;
+        let mut txn_source = 0;
 
-        let mut current_start = 1 + ts_width;
-        while !b.is_empty() {
-            let prefix = b[0];
-            b = &b[1..];
+        while let Some(prefix) = reader.peek() {
             match prefix {
                 SHORT_VALUE_PREFIX => {
-                    parsing_trace.push(ParsingTrace {
-                        start: current_start,
-                        width: 1,
-                        description: "flag:short_value".to_string(),
-                        encoded_in: EncodeMethod::EnumFlag,
-                    });
-                    current_start += 1;
-                    let len = b[0];
-                    parsing_trace.push(ParsingTrace {
-                        start: current_start,
-                        width: 1,
-                        description: "length:short_value".to_string(),
-                        encoded_in: EncodeMethod::SingleByte,
-                    });
-                    current_start += 1;
-                    b = &b[1..];
-                    if b.len() < len as usize {
-                        panic!(
-                            "content len [{}] shorter than short value len [{}]",
-                            b.len(),
-                            len,
-                        );
-                    }
-                    short_value = Some(b[..len as usize].to_vec());
-                    b = &b[len as usize..];
-                    parsing_trace.push(ParsingTrace {
-                        start: current_start,
-                        width: len as _,
-                        description: "short_value".to_string(),
-                        encoded_in: EncodeMethod::Bytes,
-                    });
-                    current_start += len as usize;
+                    reader.begin("short_value");
+                    reader.read_u8("flag", EncodeMethod::EnumFlag)?;
+                    let len = reader.read_u8("length", EncodeMethod::SingleByte)?;
+                    reader.annotate(len);
+                    let value = reader.read_bytes(len as _, "value")?;
+                    reader.end(explain::escape(value));
+                    short_value = Some(value.to_vec());
                 }
                 FLAG_OVERLAPPED_ROLLBACK => {
+                    reader.read_u8("overlapped_rollback", EncodeMethod::EnumFlag)?;
+                    reader.annotate(true);
                     has_overlapped_rollback = true;
-                    parsing_trace.push(ParsingTrace {
-                        start: current_start,
-                        width: 1,
-                        description: "flag:overlapped_rollback".to_string(),
-                        encoded_in: EncodeMethod::EnumFlag,
-                    });
-                    current_start += 1;
                 }
                 GC_FENCE_PREFIX => {
-                    parsing_trace.push(ParsingTrace {
-                        start: current_start,
-                        width: 1,
-                        description: "flag:gc_fence".to_string(),
-                        encoded_in: EncodeMethod::EnumFlag,
-                    });
-                    current_start += 1;
-                    gc_fence = Some(endian::big::decode_u64(&b).into());
-                    parsing_trace.push(ParsingTrace {
-                        start: current_start,
-                        width: 1,
-                        description: "gc_fence".to_string(),
-                        encoded_in: EncodeMethod::BigEndian,
-                    });
-                    current_start += 8;
+                    reader.begin("gc_fence");
+                    reader.read_u8("flag", EncodeMethod::EnumFlag)?;
+                    let ts = reader.read_u64("gc_fence")?;
+                    reader.end(ts);
+                    gc_fence = Some(ts.into());
+                }
+                LAST_CHANGE_PREFIX => {
+                    reader.begin("last_change");
+                    reader.read_u8("flag", EncodeMethod::EnumFlag)?;
+                    last_change_ts = reader.read_u64("last_change_ts")?.into();
+                    versions_to_last_change = reader.read_var_u64("versions_to_last_change")?;
+                    reader.end(last_change_ts);
+                }
+                TXN_SOURCE_PREFIX => {
+                    reader.begin("txn_source");
+                    reader.read_u8("flag", EncodeMethod::EnumFlag)?;
+                    txn_source = reader.read_var_u64("txn_source")?;
+                    reader.end(txn_source);
                 }
                 _ => {
                     // To support forward compatibility, all fields should be serialized in order
                     // and stop parsing if meets an unknown byte.
+                    // We still record what we cannot understand, instead of dropping it silently.
+                    reader.read_rest("unknown");
                     break;
                 }
             }
         }
 
-        Ok(Write {
+        let mut write = Write {
             write_type,
             start_ts,
             short_value,
             has_overlapped_rollback,
             gc_fence,
-            parsing_trace,
-        })
+            last_change_ts,
+            versions_to_last_change,
+            txn_source,
+            parsing_trace: ParsingTrace::default(),
+        };
+        write.parsing_trace = reader.finish("write", &write);
+        Ok(write)
     }
 
+    pub fn trace(&self) -> &ParsingTrace {
+        &self.parsing_trace
+    }
+}
+
+#[wasm_bindgen]
+impl Write {
     pub fn parse(b: &[u8]) -> Result<Write, JsValue> {
         Self::parse_rust(b).map_err(|_| JsValue::from_str("Cannot parse Write!"))
     }
@@ -234,10 +394,19 @@ impl Write {
         }
         if let Some(ts) = &self.gc_fence {
             b.push(GC_FENCE_PREFIX);
-            let mut v = Vec::with_capacity(8);
+            let mut v = [0; 8];
             endian::big::encode_u64(&mut v, ts.0);
             b.extend_from_slice(&v);
         }
+        if !self.last_change_ts.is_zero() || self.versions_to_last_change != 0 {
+            b.push(LAST_CHANGE_PREFIX);
+            b.extend_from_slice(&self.last_change_ts.0.to_be_bytes());
+            b.extend_from_slice(&varint::encode_u64(self.versions_to_last_change));
+        }
+        if self.txn_source != 0 {
+            b.push(TXN_SOURCE_PREFIX);
+            b.extend_from_slice(&varint::encode_u64(self.txn_source));
+        }
         b
     }
 
@@ -251,15 +420,58 @@ impl Write {
         self.short_value = value;
     }
 
+    /// Explain whether the version is valid for a read at `read_ts` with its GC fence.
+    #[wasm_bindgen(js_name = explainGcFence)]
+    pub fn explain_gc_fence_js(&self, next_commit_ts: Option<u64>, read_ts: u64) -> String {
+        let (valid, reason) =
+            self.explain_gc_fence(next_commit_ts.map(TimeStamp), TimeStamp(read_ts));
+        format!("{}: {}", if valid { "valid" } else { "invalid" }, reason)
+    }
+
     #[wasm_bindgen(getter)]
     pub fn parsing_trace(&self) -> JsValue {
-        JsValue::from_serde(&self.parsing_trace).unwrap()
+        utils::to_js_value(&self.parsing_trace)
+    }
+}
+
+impl fmt::Display for Write {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}, start_ts: {}", self.write_type, self.start_ts)?;
+        if let Some(v) = &self.short_value {
+            write!(f, ", short_value: {}", explain::escape(v))?;
+        }
+        if self.has_overlapped_rollback {
+            write!(f, ", overlapped_rollback")?;
+        }
+        if let Some(ts) = self.gc_fence {
+            write!(f, ", gc_fence: {}", ts)?;
+        }
+        if !self.last_change_ts.is_zero() || self.versions_to_last_change != 0 {
+            write!(
+                f,
+                ", last_change_ts: {}, versions_to_last_change: {}",
+                self.last_change_ts, self.versions_to_last_change
+            )?;
+        }
+        if self.txn_source != 0 {
+            write!(f, ", txn_source: {}", self.txn_source)?;
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[test]
+    fn test_key() {
+        let raw = b"t\x80\x00\x00\x00\x00\x00\x00\x35_r\x80\x00\x00\x00\x00\x00\x00\x01";
+        let key = Key::from_raw(raw).append_ts(TimeStamp(424659320104550401));
+        assert_eq!(key.decode_ts().unwrap(), TimeStamp(424659320104550401));
+        assert_eq!(key.to_raw().unwrap(), raw.to_vec());
+        assert_eq!(key.truncate_ts().unwrap(), Key::from_raw(raw));
+    }
+
     #[test]
     fn test_parse_write() {
         let cases = vec![
@@ -271,13 +483,10 @@ mod tests {
                     short_value: Some(vec![0]),
                     has_overlapped_rollback: false,
                     gc_fence: None,
-                    parsing_trace: vec![
-                        ParsingTrace { start: 0, width: 1, description: "write_type".to_string(), encoded_in: EncodeMethod::EnumFlag }, 
-                        ParsingTrace { start: 1, width: 1, description: "start_ts".to_string(), encoded_in: EncodeMethod::VarInt },
-                        ParsingTrace { start: 2, width: 1, description: "flag:short_value".to_string(), encoded_in: EncodeMethod::EnumFlag }, 
-                        ParsingTrace { start: 3, width: 1, description: "length:short_value".to_string(), encoded_in: EncodeMethod::SingleByte }, 
-                        ParsingTrace { start: 4, width: 1, description: "short_value".to_string(), encoded_in: EncodeMethod::Bytes }
-                    ]
+                    last_change_ts: TimeStamp::zero(),
+                    versions_to_last_change: 0,
+                    txn_source: 0,
+                    parsing_trace: ParsingTrace::default(),
                 },
             ),
             (
@@ -288,10 +497,10 @@ mod tests {
                     short_value: None,
                     has_overlapped_rollback: false,
                     gc_fence: None,
-                    parsing_trace: vec![
-                        ParsingTrace { start: 0, width: 1, description: "write_type".to_string(), encoded_in: EncodeMethod::EnumFlag }, 
-                        ParsingTrace { start: 1, width: 9, description: "start_ts".to_string(), encoded_in: EncodeMethod::VarInt }
-                    ]
+                    last_change_ts: TimeStamp::zero(),
+                    versions_to_last_change: 0,
+                    txn_source: 0,
+                    parsing_trace: ParsingTrace::default(),
                 }
             ),
             (
@@ -311,19 +520,140 @@ mod tests {
                     short_value: Some(b"{\"version\":8,\"type\":3,\"schema_id\":3,\"table_id\":15,\"old_table_id\":0,\"old_schema_id\":0,\"affected_options\":null}".to_vec()),
                     has_overlapped_rollback: false,
                     gc_fence: None,
-                    parsing_trace: vec![
-                        ParsingTrace { start: 0, width: 1, description: "write_type".to_string(), encoded_in: EncodeMethod::EnumFlag }, 
-                        ParsingTrace { start: 1, width: 9, description: "start_ts".to_string(), encoded_in: EncodeMethod::VarInt }, 
-                        ParsingTrace { start: 10, width: 1, description: "flag:short_value".to_string(), encoded_in: EncodeMethod::EnumFlag }, 
-                        ParsingTrace { start: 11, width: 1, description: "length:short_value".to_string(), encoded_in: EncodeMethod::SingleByte }, 
-                        ParsingTrace { start: 12, width: 109, description: "short_value".to_string(), encoded_in: EncodeMethod::Bytes }
-                    ]
+                    last_change_ts: TimeStamp::zero(),
+                    versions_to_last_change: 0,
+                    txn_source: 0,
+                    parsing_trace: ParsingTrace::default(),
                 },
             ),
         ];
-        for (source, expected) in cases {
-            let result = Write::parse(&source).unwrap();
+        let expected_leaves = vec![
+            vec![
+                ("write_type", 0, 1, EncodeMethod::EnumFlag),
+                ("start_ts", 1, 1, EncodeMethod::VarInt),
+                ("short_value.flag", 2, 1, EncodeMethod::EnumFlag),
+                ("short_value.length", 3, 1, EncodeMethod::SingleByte),
+                ("short_value.value", 4, 1, EncodeMethod::Bytes),
+            ],
+            vec![
+                ("write_type", 0, 1, EncodeMethod::EnumFlag),
+                ("start_ts", 1, 9, EncodeMethod::VarInt),
+            ],
+            vec![
+                ("write_type", 0, 1, EncodeMethod::EnumFlag),
+                ("start_ts", 1, 9, EncodeMethod::VarInt),
+                ("short_value.flag", 10, 1, EncodeMethod::EnumFlag),
+                ("short_value.length", 11, 1, EncodeMethod::SingleByte),
+                ("short_value.value", 12, 109, EncodeMethod::Bytes),
+            ],
+        ];
+        for ((source, expected), leaves) in cases.into_iter().zip(expected_leaves) {
+            let mut result = Write::parse(&source).unwrap();
+            let trace = std::mem::take(&mut result.parsing_trace);
             assert_eq!(result, expected);
+            assert_eq!((trace.start, trace.width), (0, source.len()));
+            assert_eq!(trace.value, Some(result.to_string()));
+            let result_leaves: Vec<_> = trace
+                .leaves()
+                .into_iter()
+                .map(|(path, t)| (path, t.start, t.width, t.encoded_in))
+                .collect();
+            let leaves: Vec<_> = leaves
+                .into_iter()
+                .map(|(path, start, width, method)| (path.to_string(), start, width, method))
+                .collect();
+            assert_eq!(result_leaves, leaves);
         }
     }
+
+    #[test]
+    fn test_write_new_fields() {
+        let mut write = Write::parse_rust(&[b'L', 10]).unwrap();
+        write.has_overlapped_rollback = true;
+        write.gc_fence = Some(TimeStamp(20));
+        write.last_change_ts = TimeStamp(8);
+        write.versions_to_last_change = 2;
+        write.txn_source = 1;
+        let code = write.to_bytes();
+        assert_eq!(
+            code,
+            vec![
+                b'L', 10, b'R', b'F', 0, 0, 0, 0, 0, 0, 0, 20, b'l', 0, 0, 0, 0, 0, 0, 0, 8, 2,
+                b'S', 1
+            ]
+        );
+        let result = Write::parse_rust(&code).unwrap();
+        assert_eq!(result.last_change_ts, TimeStamp(8));
+        assert_eq!(result.versions_to_last_change, 2);
+        assert_eq!(result.txn_source, 1);
+        assert_eq!(result.gc_fence, Some(TimeStamp(20)));
+        let trace = result.trace();
+        let fields: Vec<_> = trace
+            .children
+            .iter()
+            .map(|t| (t.field.as_str(), t.width, t.value.as_deref().unwrap_or("")))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("write_type", 1, "Lock"),
+                ("start_ts", 1, "10"),
+                ("overlapped_rollback", 1, "true"),
+                ("gc_fence", 9, "20"),
+                ("last_change", 10, "8"),
+                ("txn_source", 2, "1"),
+            ]
+        );
+        let paths: Vec<_> = trace.leaves().into_iter().map(|(path, _)| path).collect();
+        assert_eq!(
+            paths[5..8],
+            [
+                "last_change.flag",
+                "last_change.last_change_ts",
+                "last_change.versions_to_last_change"
+            ]
+        );
+        assert_eq!(
+            result.to_string(),
+            "Lock, start_ts: 10, overlapped_rollback, gc_fence: 20, \
+             last_change_ts: 8, versions_to_last_change: 2, txn_source: 1"
+        );
+    }
+
+    #[test]
+    fn test_gc_fence() {
+        let mut write = Write::new(WriteType::Put, TimeStamp(10), None);
+        assert!(write.check_gc_fence_as_latest_version(TimeStamp(100)));
+        write.gc_fence = Some(TimeStamp::zero());
+        assert!(write.check_gc_fence_as_latest_version(TimeStamp(100)));
+        write.gc_fence = Some(TimeStamp(30));
+        assert!(write.check_gc_fence_as_latest_version(TimeStamp(29)));
+        assert!(!write.check_gc_fence_as_latest_version(TimeStamp(30)));
+        assert_eq!(
+            write.explain_gc_fence(Some(TimeStamp(30)), TimeStamp(20)),
+            (
+                true,
+                "the newer version committed at 30 is newer than the read at 20".to_string()
+            )
+        );
+        assert_eq!(
+            write.explain_gc_fence(Some(TimeStamp(25)), TimeStamp(40)),
+            (
+                false,
+                "a newer version committed at 30 was removed by GC, \
+                 but the next version is committed at 25"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_write_unknown_bytes() {
+        let result = Write::parse_rust(&[b'P', 10, b'?', 1, 2]).unwrap();
+        let last = result.trace().children.last().unwrap();
+        assert_eq!((last.start, last.width), (2, 3));
+        assert_eq!(last.field, "unknown");
+        assert!(Write::parse_rust(&[b'P', 10, b'v', 5, 1]).is_err());
+        assert!(Write::parse_rust(&[b'X', 10]).is_err());
+    }
 }
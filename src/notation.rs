@@ -0,0 +1,388 @@
+//! A textual notation for keys, which is what `explain` prints, and can be parsed back into bytes.
+//!
+//! ```text
+//! t53                             table prefix
+//! t53_r1                          record key with an int handle
+//! t53_r("pk",1)                   record key with a common handle
+//! t53_i2("abc",7)                 index key with the column values
+//! meta("DB:1").h("Table:53")      meta key, `.s`/`.S`/`.h(field)`/`.H`/`.l(index)`/`.L`
+//! keyspace 7: t53_r1              with the TxnKV keyspace prefix of API v2
+//! encoded(t53_r1)@42              memcomparable encoded, with a timestamp
+//! data(encoded(t53_r1)@42)        with the `z` prefix TiKV puts on data keys
+//! region 42 apply state           raftstore local keys
+//! "raw\x00bytes"                  anything else, escaped
+//! ```
+//!
+//! Datums in handles and index values are `NULL`, `MAX`, integers, unsigned integers
+//! suffixed with `u`, floats, durations like `01:02:03.5` and escaped strings.
+use crate::chunk;
+use crate::datum::{self, Datum};
+use crate::db_to_kv;
+use crate::explain;
+use crate::keyspace::{KeyMode, Keyspace};
+use crate::local::{LocalKey, DATA_PREFIX};
+use crate::meta::{MetaField, MetaKey};
+use anyhow::{anyhow, bail};
+use std::str::FromStr;
+use wasm_bindgen::prelude::*;
+
+struct Parser<'a> {
+    s: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn eat(&mut self, prefix: &str) -> bool {
+        match self.s.strip_prefix(prefix) {
+            Some(rest) => {
+                self.s = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, prefix: &str) -> anyhow::Result<()> {
+        if !self.eat(prefix) {
+            bail!("expect {:?} at {:?}", prefix, self.s);
+        }
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.s.chars().next()
+    }
+
+    /// Take the characters until one of `end` or the end of input.
+    fn take_until(&mut self, end: &[char]) -> &'a str {
+        let len = self.s.find(|c| end.contains(&c)).unwrap_or(self.s.len());
+        let (token, rest) = self.s.split_at(len);
+        self.s = rest;
+        token
+    }
+
+    fn number<T: FromStr>(&mut self) -> anyhow::Result<T> {
+        let len = self
+            .s
+            .char_indices()
+            .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && c == '-')))
+            .map_or(self.s.len(), |(i, _)| i);
+        let (token, rest) = self.s.split_at(len);
+        let n = token
+            .parse()
+            .map_err(|_| anyhow!("expect a number at {:?}", self.s))?;
+        self.s = rest;
+        Ok(n)
+    }
+
+    fn string(&mut self) -> anyhow::Result<Vec<u8>> {
+        if !self.s.starts_with('"') {
+            bail!("expect a string at {:?}", self.s);
+        }
+        let mut escaped = false;
+        for (i, c) in self.s.char_indices().skip(1) {
+            match c {
+                '\\' if !escaped => escaped = true,
+                '"' if !escaped => {
+                    let (token, rest) = self.s.split_at(i + 1);
+                    self.s = rest;
+                    return explain::unescape(token);
+                }
+                _ => escaped = false,
+            }
+        }
+        bail!("unterminated string {:?}", self.s)
+    }
+
+    fn datum(&mut self) -> anyhow::Result<Datum> {
+        if self.peek() == Some('"') {
+            return Ok(Datum::Bytes(self.string()?));
+        }
+        let token = self.take_until(&[',', ')']).trim();
+        Ok(match token {
+            "NULL" => Datum::Null,
+            "MAX" => Datum::Max,
+            _ if token.contains(':') => Datum::Duration(parse_duration(token)?),
+            _ => {
+                if let Some(n) = token.strip_suffix('u') {
+                    Datum::Uint(n.parse()?)
+                } else if let Ok(n) = token.parse() {
+                    Datum::Int(n)
+                } else {
+                    Datum::Float(
+                        token
+                            .parse()
+                            .map_err(|_| anyhow!("invalid datum {:?}", token))?,
+                    )
+                }
+            }
+        })
+    }
+
+    /// `(datum,datum,...)`
+    fn datums(&mut self) -> anyhow::Result<Vec<Datum>> {
+        self.expect("(")?;
+        let mut result = Vec::new();
+        if self.eat(")") {
+            return Ok(result);
+        }
+        loop {
+            result.push(self.datum()?);
+            if self.eat(")") {
+                return Ok(result);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn table_key(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.expect("t")?;
+        let mut result = db_to_kv::encode_table_prefix(self.number()?);
+        if self.eat("_r") {
+            result.extend_from_slice(db_to_kv::RECORD_PREFIX_SEP);
+            match self.peek() {
+                Some('(') => result.extend(datum::encode_datums(&self.datums()?)),
+                Some(c) if c == '-' || c.is_ascii_digit() => {
+                    result.extend_from_slice(&db_to_kv::encode_i64(self.number()?))
+                }
+                _ => {}
+            }
+        } else if self.eat("_i") {
+            result.extend_from_slice(db_to_kv::INDEX_PREFIX_SEP);
+            if matches!(self.peek(), Some(c) if c == '-' || c.is_ascii_digit()) {
+                result.extend_from_slice(&db_to_kv::encode_i64(self.number()?));
+                if self.peek() == Some('(') {
+                    result.extend(datum::encode_datums(&self.datums()?));
+                } else if self.eat(" values: ") {
+                    result.extend(hex::decode(self.take_until(&[')']))?);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn meta_key(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.expect("meta(")?;
+        let key = self.string()?;
+        self.expect(").")?;
+        let field = match self.take_until(&['(', ')']) {
+            "S" => MetaField::StringMeta,
+            "s" => MetaField::StringData,
+            "H" => MetaField::HashMeta,
+            "L" => MetaField::ListMeta,
+            "h" => {
+                self.expect("(")?;
+                let field = self.string()?;
+                self.expect(")")?;
+                MetaField::HashData(field)
+            }
+            "l" => {
+                self.expect("(")?;
+                let index = self.number()?;
+                self.expect(")")?;
+                MetaField::ListData(index)
+            }
+            flag => bail!("invalid meta type {:?}", flag),
+        };
+        Ok(MetaKey { key, field }.to_bytes())
+    }
+
+    fn local_key(&mut self) -> anyhow::Result<Vec<u8>> {
+        let key = if self.eat("store ident") {
+            LocalKey::StoreIdent
+        } else if self.eat("prepare bootstrap") {
+            LocalKey::PrepareBootstrap
+        } else if self.eat("recover state") {
+            LocalKey::RecoverState
+        } else {
+            self.expect("region ")?;
+            let region_id = self.number()?;
+            if self.eat(" raft log ") {
+                LocalKey::RaftLog {
+                    region_id,
+                    index: self.number()?,
+                }
+            } else if self.eat(" raft state") {
+                LocalKey::RaftState { region_id }
+            } else if self.eat(" apply state") {
+                LocalKey::ApplyState { region_id }
+            } else if self.eat(" snapshot raft state") {
+                LocalKey::SnapshotRaftState { region_id }
+            } else {
+                self.expect(" region state")?;
+                LocalKey::RegionState { region_id }
+            }
+        };
+        Ok(key.to_bytes())
+    }
+
+    fn key(&mut self) -> anyhow::Result<Vec<u8>> {
+        if self.eat("data(") {
+            let mut result = vec![DATA_PREFIX];
+            result.extend(self.key()?);
+            self.expect(")")?;
+            return Ok(result);
+        }
+        if self.eat("encoded(") {
+            let mut result = chunk::encode_bytes(&self.key()?);
+            self.expect(")")?;
+            if self.eat("@") {
+                result.extend_from_slice(&(!self.number::<u64>()?).to_be_bytes());
+            }
+            return Ok(result);
+        }
+        if self.eat("keyspace ") {
+            let keyspace = Keyspace::new(KeyMode::Txn, self.number()?)?;
+            self.expect(": ")?;
+            return Ok(keyspace.apply(&self.key()?));
+        }
+        match self.peek() {
+            Some('t') => self.table_key(),
+            Some('m') => self.meta_key(),
+            Some('"') => self.string(),
+            Some('s') | Some('p') | Some('r') => self.local_key(),
+            _ => bail!("unknown key notation {:?}", self.s),
+        }
+    }
+}
+
+/// `[-]HH:MM:SS[.fraction]` into nanoseconds.
+fn parse_duration(s: &str) -> anyhow::Result<i64> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+    let (hms, fraction) = s.split_once('.').unwrap_or((s, ""));
+    let parts = hms
+        .split(':')
+        .map(|part| part.parse::<i64>())
+        .collect::<Result<Vec<_>, _>>()?;
+    if parts.len() != 3 || fraction.len() > 9 {
+        bail!("invalid duration {:?}", s);
+    }
+    let mut nanos = ((parts[0] * 60 + parts[1]) * 60 + parts[2]) * 1_000_000_000;
+    if !fraction.is_empty() {
+        nanos += format!("{:0<9}", fraction).parse::<i64>()?;
+    }
+    Ok(if negative { -nanos } else { nanos })
+}
+
+/// Parse a key in the notation into its bytes.
+pub fn parse_notation(s: &str) -> anyhow::Result<Vec<u8>> {
+    let mut parser = Parser { s: s.trim() };
+    let result = parser.key()?;
+    if !parser.s.is_empty() {
+        bail!("unexpected {:?} after the key", parser.s);
+    }
+    Ok(result)
+}
+
+/// Print `key` in the notation, which is the same as the explanation of it.
+pub fn print_notation(key: &[u8]) -> String {
+    explain::explain_key(key)
+}
+
+#[wasm_bindgen]
+pub fn parse_key_notation(s: &str) -> Result<Vec<u8>, JsValue> {
+    parse_notation(s).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mvcc::{Key, TimeStamp};
+
+    #[test]
+    fn test_parse_notation() {
+        let values = datum::encode_datums(&[Datum::Bytes(b"abc".to_vec()), Datum::Int(7)]);
+        let record = db_to_kv::encode_record_key_rust(None, 53, 1).unwrap();
+        let cases = vec![
+            ("t53_r1", record.clone()),
+            ("t53", db_to_kv::encode_table_prefix(53)),
+            (
+                "t53_i2(\"abc\",7)",
+                db_to_kv::encode_index_key_rust(None, 53, 2, &values).unwrap(),
+            ),
+            (
+                "keyspace 7: t53_r1",
+                db_to_kv::encode_record_key_rust(Some(7), 53, 1).unwrap(),
+            ),
+            (
+                "data(encoded(t53_r1)@42)",
+                [
+                    vec![DATA_PREFIX],
+                    Key::from_raw(&record)
+                        .append_ts(TimeStamp(42))
+                        .into_encoded(),
+                ]
+                .concat(),
+            ),
+            (
+                "meta(\"DB:1\").h(\"Table:53\")",
+                MetaKey {
+                    key: b"DB:1".to_vec(),
+                    field: MetaField::HashData(b"Table:53".to_vec()),
+                }
+                .to_bytes(),
+            ),
+            (
+                "region 42 raft log 5",
+                LocalKey::RaftLog {
+                    region_id: 42,
+                    index: 5,
+                }
+                .to_bytes(),
+            ),
+            ("\"a\\\"b\\x00\"", b"a\"b\x00".to_vec()),
+        ];
+        for (notation, expected) in cases {
+            assert_eq!(parse_notation(notation).unwrap(), expected, "{}", notation);
+            assert_eq!(print_notation(&expected), notation);
+        }
+        assert!(parse_notation("t53_r1 extra").is_err());
+        assert!(parse_notation("t53_i2(\"abc\"").is_err());
+        assert!(parse_notation("hello").is_err());
+    }
+
+    #[test]
+    fn test_notation_round_trip() {
+        let datums = vec![
+            Datum::Null,
+            Datum::Int(-3),
+            Datum::Uint(3),
+            Datum::Float(2.5),
+            Datum::Duration(-3_723_500_000_000),
+            Datum::Bytes(b"x,y)".to_vec()),
+            Datum::Max,
+        ];
+        let keys = vec![
+            db_to_kv::encode_index_key_rust(None, 53, 2, &datum::encode_datums(&datums)).unwrap(),
+            db_to_kv::encode_index_key_rust(Some(1), 53, 2, &[42]).unwrap(),
+            [
+                db_to_kv::encode_table_prefix(-1),
+                db_to_kv::RECORD_PREFIX_SEP.to_vec(),
+                datum::encode_datums(&datums[5..6]),
+            ]
+            .concat(),
+            Key::from_raw(b"hello")
+                .append_ts(TimeStamp(1))
+                .into_encoded(),
+            MetaKey {
+                key: b"q".to_vec(),
+                field: MetaField::ListData(3),
+            }
+            .to_bytes(),
+            chunk::encode_bytes(
+                &MetaKey {
+                    key: b"NextGlobalID".to_vec(),
+                    field: MetaField::StringData,
+                }
+                .to_bytes(),
+            ),
+            LocalKey::StoreIdent.to_bytes(),
+        ];
+        for key in keys {
+            let notation = print_notation(&key);
+            assert_eq!(parse_notation(&notation).unwrap(), key, "{}", notation);
+        }
+    }
+}
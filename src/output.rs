@@ -0,0 +1,129 @@
+//! Print bytes in the forms other tools take, the counterpart of `input`.
+use crate::chunk;
+use crate::explain;
+use wasm_bindgen::prelude::*;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// `[116, 128, 0]`, what `{:?}` prints for a `Vec<u8>`.
+    RustDebug,
+    /// `[116 128 0]`, what `fmt.Print` prints for a `[]byte`.
+    GolangFmtPrint,
+    HexUpper,
+    HexLower,
+    /// `"t\x80\x00"`, a rust byte string literal.
+    Escaped,
+    /// `"t\200\000"`, non printable bytes in octal, as `tikv-ctl` prints keys.
+    OctalEscaped,
+    Base64,
+    /// `x'748000'`
+    SqlHex,
+    /// The upper hex of the memcomparable encoded key, as TiKV logs a `Key`.
+    TikvLog,
+}
+
+pub fn rust_debug(b: &[u8]) -> String {
+    format!("{:?}", b)
+}
+
+pub fn golang_fmt_print(b: &[u8]) -> String {
+    let bytes: Vec<String> = b.iter().map(|c| c.to_string()).collect();
+    format!("[{}]", bytes.join(" "))
+}
+
+/// Escape `b` like `tikv_util::escape` does, in quotes.
+pub fn octal_escaped(b: &[u8]) -> String {
+    let mut result = String::with_capacity(b.len() * 4 + 2);
+    result.push('"');
+    for &c in b {
+        match c {
+            b'\n' => result.push_str("\\n"),
+            b'\r' => result.push_str("\\r"),
+            b'\t' => result.push_str("\\t"),
+            b'"' => result.push_str("\\\""),
+            b'\\' => result.push_str("\\\\"),
+            0x20..=0x7e => result.push(c as char),
+            _ => result.push_str(&format!("\\{:03o}", c)),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Standard base64 with padding.
+pub fn base64(b: &[u8]) -> String {
+    let mut result = String::with_capacity(b.len().div_ceil(3) * 4);
+    for chunk in b.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &c)| n | (c as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+pub fn format_rust(b: &[u8], format: OutputFormat) -> String {
+    match format {
+        OutputFormat::RustDebug => rust_debug(b),
+        OutputFormat::GolangFmtPrint => golang_fmt_print(b),
+        OutputFormat::HexUpper => hex::encode_upper(b),
+        OutputFormat::HexLower => hex::encode(b),
+        OutputFormat::Escaped => explain::escape(b),
+        OutputFormat::OctalEscaped => octal_escaped(b),
+        OutputFormat::Base64 => base64(b),
+        OutputFormat::SqlHex => format!("x'{}'", hex::encode(b)),
+        OutputFormat::TikvLog => hex::encode_upper(chunk::encode_bytes(b)),
+    }
+}
+
+#[wasm_bindgen]
+pub fn format_output(b: &[u8], format: OutputFormat) -> String {
+    format_rust(b, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input;
+
+    #[test]
+    fn test_format_output() {
+        let key = b"t\x80\x00\"\n";
+        let cases = vec![
+            (OutputFormat::RustDebug, "[116, 128, 0, 34, 10]"),
+            (OutputFormat::GolangFmtPrint, "[116 128 0 34 10]"),
+            (OutputFormat::HexUpper, "748000220A"),
+            (OutputFormat::HexLower, "748000220a"),
+            (OutputFormat::Escaped, "\"t\\x80\\x00\\\"\\n\""),
+            (OutputFormat::OctalEscaped, "\"t\\200\\000\\\"\\n\""),
+            (OutputFormat::Base64, "dIAAIgo="),
+            (OutputFormat::SqlHex, "x'748000220a'"),
+            (OutputFormat::TikvLog, "748000220A000000FC"),
+        ];
+        for (format, expected) in cases {
+            assert_eq!(format_rust(key, format), expected, "{:?}", format);
+        }
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"abc"), "YWJj");
+
+        // What is printed can be read back.
+        let printed = format_rust(key, OutputFormat::RustDebug);
+        assert_eq!(input::parse_rust_print(&printed).unwrap(), key);
+        let printed = format_rust(key, OutputFormat::GolangFmtPrint);
+        assert_eq!(input::parse_golang_fmt_print(&printed).unwrap(), key);
+        let printed = format_rust(key, OutputFormat::Escaped);
+        assert_eq!(explain::unescape(&printed).unwrap(), key);
+    }
+}
@@ -0,0 +1,237 @@
+//! Read the regions `pd-ctl region` and `pd-ctl region key` print.
+//!
+//! The boundaries are upper hex in recent versions of pd-ctl, and strings with
+//! octal escapes in the older ones, both are memcomparable encoded for TxnKV. As an
+//! escaped key like `7480` is also valid hex, the form is decided once for the whole
+//! output: hex only if every boundary in it is.
+use crate::explain::{self, ExplainedKey};
+use crate::region::RegionMap;
+use crate::utils;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct RegionEpoch {
+    #[serde(default)]
+    pub conf_ver: u64,
+    #[serde(default)]
+    pub version: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Peer {
+    pub id: u64,
+    pub store_id: u64,
+    #[serde(default)]
+    pub role_name: Option<String>,
+}
+
+/// A region as pd-ctl prints it, the fields we don't care are ignored.
+#[derive(Debug, Clone, Deserialize)]
+struct PdRegion {
+    id: u64,
+    #[serde(default)]
+    start_key: String,
+    #[serde(default)]
+    end_key: String,
+    #[serde(default)]
+    epoch: RegionEpoch,
+    #[serde(default)]
+    peers: Vec<Peer>,
+    #[serde(default)]
+    leader: Option<Peer>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum PdOutput {
+    Regions { regions: Vec<PdRegion> },
+    Region(PdRegion),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RegionInfo {
+    pub id: u64,
+    pub start_key: ExplainedKey,
+    pub end_key: ExplainedKey,
+    pub epoch: RegionEpoch,
+    pub peers: Vec<Peer>,
+    pub leader: Option<Peer>,
+}
+
+impl fmt::Display for RegionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bound = |key: &ExplainedKey, unbounded| {
+            if key.key.is_empty() {
+                unbounded
+            } else {
+                key.explanation.clone()
+            }
+        };
+        write!(
+            f,
+            "region {} (conf_ver {}, version {}): {} .. {}",
+            self.id,
+            self.epoch.conf_ver,
+            self.epoch.version,
+            bound(&self.start_key, "-inf".to_string()),
+            bound(&self.end_key, "+inf".to_string())
+        )?;
+        let stores: Vec<String> = self
+            .peers
+            .iter()
+            .map(|peer| {
+                let leader = self.leader.as_ref().map(|leader| leader.id) == Some(peer.id);
+                format!("{}{}", peer.store_id, if leader { "*" } else { "" })
+            })
+            .collect();
+        if !stores.is_empty() {
+            write!(f, " on stores {}", stores.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `key` is in the upper hex form recent versions of pd-ctl print.
+fn is_hex_boundary(key: &str) -> bool {
+    key.len().is_multiple_of(2)
+        && key
+            .bytes()
+            .all(|c| c.is_ascii_digit() || (b'A'..=b'F').contains(&c))
+}
+
+/// A boundary in hex or escaped form.
+fn decode_boundary(key: &str, hex: bool) -> anyhow::Result<Vec<u8>> {
+    let decoded = if hex {
+        hex::decode(key).map_err(anyhow::Error::from)
+    } else {
+        explain::unescape(key)
+    };
+    decoded.map_err(|_| anyhow!("cannot decode region boundary {:?}", key))
+}
+
+/// Parse the output of `pd-ctl region`, which is either a single region or
+/// `{"count": N, "regions": [...]}`.
+pub fn parse_pd_regions(json: &str) -> anyhow::Result<Vec<RegionInfo>> {
+    let regions = match serde_json::from_str(json)? {
+        PdOutput::Regions { regions } => regions,
+        PdOutput::Region(region) => vec![region],
+    };
+    let hex = regions
+        .iter()
+        .flat_map(|region| [&region.start_key, &region.end_key])
+        .all(|key| is_hex_boundary(key));
+    regions
+        .into_iter()
+        .map(|region| {
+            Ok(RegionInfo {
+                id: region.id,
+                start_key: ExplainedKey::new(&decode_boundary(&region.start_key, hex)?),
+                end_key: ExplainedKey::new(&decode_boundary(&region.end_key, hex)?),
+                epoch: region.epoch,
+                peers: region.peers,
+                leader: region.leader,
+            })
+        })
+        .collect()
+}
+
+/// Build a `RegionMap` from the output of `pd-ctl region`.
+pub fn region_map_from_pd(json: &str) -> anyhow::Result<RegionMap> {
+    let regions = parse_pd_regions(json)?;
+    RegionMap::from_boundaries(
+        regions
+            .into_iter()
+            .map(|region| (region.id, region.start_key.key, region.end_key.key)),
+    )
+}
+
+#[wasm_bindgen]
+pub fn parse_pd_ctl_regions(json: &str) -> Result<JsValue, JsValue> {
+    parse_pd_regions(json)
+        .map(|regions| utils::to_js_value(&regions))
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[wasm_bindgen]
+pub fn region_map_from_pd_ctl(json: &str) -> Result<RegionMap, JsValue> {
+    region_map_from_pd(json).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db_to_kv;
+
+    const REGION: &str = include_str!("../tests/fixtures/pd-ctl-region.json");
+    const REGIONS: &str = include_str!("../tests/fixtures/pd-ctl-regions.json");
+
+    #[test]
+    fn test_parse_pd_region() {
+        let regions = parse_pd_regions(REGION).unwrap();
+        assert_eq!(regions.len(), 1);
+        let region = &regions[0];
+        assert_eq!(region.id, 12);
+        assert_eq!(
+            region.epoch,
+            RegionEpoch {
+                conf_ver: 5,
+                version: 54
+            }
+        );
+        assert_eq!(region.start_key.explanation, "encoded(t53_r1000)");
+        assert_eq!(region.peers[2].role_name.as_deref(), Some("Learner"));
+        assert_eq!(
+            region.to_string(),
+            "region 12 (conf_ver 5, version 54): encoded(t53_r1000) .. encoded(t54_i1) \
+             on stores 1*,4,5"
+        );
+    }
+
+    #[test]
+    fn test_parse_pd_regions() {
+        let regions = parse_pd_regions(REGIONS).unwrap();
+        assert_eq!(
+            regions.iter().map(|region| region.id).collect::<Vec<_>>(),
+            vec![2, 12, 30]
+        );
+        // The hex boundaries decode to the same keys as the escaped ones.
+        let region = parse_pd_regions(REGION).unwrap().remove(0);
+        assert_eq!(regions[1].start_key, region.start_key);
+        assert_eq!(regions[1].end_key, region.end_key);
+
+        let map = region_map_from_pd(REGIONS).unwrap();
+        assert!(map.encoded);
+        assert_eq!(
+            map.to_string(),
+            "region 2: -inf .. t53_r1000\n\
+             region 12: t53_r1000 .. t54_i1\n\
+             region 30: t54_i1 .. +inf\n"
+        );
+        let key = db_to_kv::encode_record_key_rust(None, 53, 1).unwrap();
+        assert_eq!(map.locate(&key).unwrap().id, 2);
+        assert!(parse_pd_regions("{\"count\": 0}").is_err());
+    }
+
+    #[test]
+    fn test_ambiguous_boundaries() {
+        // `7480` is a valid hex, but the other boundary is escaped, so both are.
+        let escaped = r#"{"count": 2, "regions": [
+            {"id": 2, "start_key": "", "end_key": "7480"},
+            {"id": 3, "start_key": "7480", "end_key": "t\\200"}
+        ]}"#;
+        let regions = parse_pd_regions(escaped).unwrap();
+        assert_eq!(regions[0].end_key.key, b"7480");
+        assert_eq!(regions[1].end_key.key, b"t\x80");
+        // Recent pd-ctl prints upper hex, `abcd` can only be escaped.
+        let region = r#"{"id": 2, "start_key": "abcd", "end_key": ""}"#;
+        assert_eq!(parse_pd_regions(region).unwrap()[0].start_key.key, b"abcd");
+        let hex = r#"{"count": 2, "regions": [
+            {"id": 2, "start_key": "", "end_key": "7480"},
+            {"id": 3, "start_key": "7480", "end_key": ""}
+        ]}"#;
+        assert_eq!(parse_pd_regions(hex).unwrap()[0].end_key.key, b"\x74\x80");
+    }
+}
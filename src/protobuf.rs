@@ -0,0 +1,140 @@
+//! A tiny reader of the protobuf wire format.
+//!
+//! We only need to decode a handful of kvproto/raft messages, so instead of
+//! depending on generated code (which needs the `.proto` files and `protoc`
+//! at build time) we walk the fields by hand.
+use crate::varint;
+use anyhow::{anyhow, bail};
+
+const WIRE_TYPE_VARINT: u64 = 0;
+const WIRE_TYPE_FIXED64: u64 = 1;
+const WIRE_TYPE_LENGTH_DELIMITED: u64 = 2;
+const WIRE_TYPE_FIXED32: u64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    LengthDelimited(&'a [u8]),
+    Fixed32(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field<'a> {
+    pub number: u32,
+    /// Offset of the field's tag in the message.
+    pub start: usize,
+    /// Offset of the field's value in the message, for length delimited fields
+    /// this is where the payload starts, i.e. after the length.
+    pub value_start: usize,
+    /// Offset after the end of the field.
+    pub end: usize,
+    pub value: WireValue<'a>,
+}
+
+impl<'a> Field<'a> {
+    pub fn as_u64(&self) -> anyhow::Result<u64> {
+        match self.value {
+            WireValue::Varint(v) | WireValue::Fixed64(v) => Ok(v),
+            WireValue::Fixed32(v) => Ok(v as u64),
+            WireValue::LengthDelimited(_) => {
+                bail!("field {} is not an integer", self.number)
+            }
+        }
+    }
+
+    pub fn as_bool(&self) -> anyhow::Result<bool> {
+        Ok(self.as_u64()? != 0)
+    }
+
+    pub fn as_bytes(&self) -> anyhow::Result<&'a [u8]> {
+        match self.value {
+            WireValue::LengthDelimited(b) => Ok(b),
+            _ => bail!("field {} is not length delimited", self.number),
+        }
+    }
+
+    pub fn as_string(&self) -> anyhow::Result<String> {
+        Ok(String::from_utf8_lossy(self.as_bytes()?).into_owned())
+    }
+}
+
+/// Split an encoded message into its fields, in the order they appear.
+pub fn fields(b: &[u8]) -> anyhow::Result<Vec<Field<'_>>> {
+    let mut result = Vec::new();
+    let mut offset = 0;
+    while offset < b.len() {
+        let start = offset;
+        let (tag, width) =
+            varint::try_decode_u64(&b[offset..]).ok_or_else(|| anyhow!("bad tag at {}", offset))?;
+        offset += width;
+        let number = (tag >> 3) as u32;
+        let mut value_start = offset;
+        let value = match tag & 0x7 {
+            WIRE_TYPE_VARINT => {
+                let (v, width) = varint::try_decode_u64(&b[offset..])
+                    .ok_or_else(|| anyhow!("bad varint at {}", offset))?;
+                offset += width;
+                WireValue::Varint(v)
+            }
+            WIRE_TYPE_FIXED64 => {
+                let bytes = b
+                    .get(offset..offset + 8)
+                    .ok_or_else(|| anyhow!("truncated fixed64 at {}", offset))?;
+                offset += 8;
+                WireValue::Fixed64(crate::endian::little::decode_u64(bytes))
+            }
+            WIRE_TYPE_LENGTH_DELIMITED => {
+                let (len, width) = varint::try_decode_u64(&b[offset..])
+                    .ok_or_else(|| anyhow!("bad length at {}", offset))?;
+                offset += width;
+                value_start = offset;
+                let bytes = b
+                    .get(offset..offset.saturating_add(len as usize))
+                    .ok_or_else(|| anyhow!("truncated field {} at {}", number, offset))?;
+                offset += len as usize;
+                WireValue::LengthDelimited(bytes)
+            }
+            WIRE_TYPE_FIXED32 => {
+                let bytes = b
+                    .get(offset..offset + 4)
+                    .ok_or_else(|| anyhow!("truncated fixed32 at {}", offset))?;
+                offset += 4;
+                WireValue::Fixed32(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            }
+            wire_type => bail!("unsupported wire type {} at {}", wire_type, start),
+        };
+        result.push(Field {
+            number,
+            start,
+            value_start,
+            end: offset,
+            value,
+        });
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields() {
+        // field 1: varint 150, field 2: "abc", field 3: fixed64 1
+        let code = vec![
+            0x08, 0x96, 0x01, 0x12, 0x03, b'a', b'b', b'c', 0x19, 1, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        let result = fields(&code).unwrap();
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].number, 1);
+        assert_eq!(result[0].as_u64().unwrap(), 150);
+        assert_eq!(result[1].as_bytes().unwrap(), b"abc");
+        assert_eq!(
+            (result[1].start, result[1].value_start, result[1].end),
+            (3, 5, 8)
+        );
+        assert_eq!(result[2].as_u64().unwrap(), 1);
+        assert!(fields(&[0x12, 0x05, b'a']).is_err());
+    }
+}
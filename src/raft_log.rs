@@ -0,0 +1,619 @@
+//! Raft log entries (`eraftpb::Entry`) and the `raft_cmdpb::RaftCmdRequest`s embedded in them.
+use crate::explain::{self, ExplainedKey};
+use crate::lock::Lock;
+use crate::mvcc::{Write, CF_DEFAULT, CF_LOCK, CF_WRITE};
+use crate::protobuf;
+use crate::raft_serverpb::{Peer, RegionEpoch};
+use crate::utils;
+use serde::Serialize;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum EntryType {
+    #[default]
+    EntryNormal,
+    EntryConfChange,
+    EntryConfChangeV2,
+    Unknown(u64),
+}
+
+impl From<u64> for EntryType {
+    fn from(n: u64) -> Self {
+        match n {
+            0 => EntryType::EntryNormal,
+            1 => EntryType::EntryConfChange,
+            2 => EntryType::EntryConfChangeV2,
+            n => EntryType::Unknown(n),
+        }
+    }
+}
+
+/// A key-value pair embedded in a raft command, with both of them explained.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ExplainedKv {
+    pub cf: String,
+    pub key: ExplainedKey,
+    pub value: Vec<u8>,
+    pub value_explanation: String,
+}
+
+/// Explain `value` according to which column family it is written to.
+pub fn explain_cf_value(cf: &str, value: &[u8]) -> String {
+    match cf {
+        CF_WRITE => Write::parse_rust(value)
+            .map(|w| w.to_string())
+            .unwrap_or_else(|_| explain::escape(value)),
+        CF_LOCK => Lock::parse_rust(value)
+            .map(|l| l.to_string())
+            .unwrap_or_else(|_| explain::escape(value)),
+        _ => explain::escape(value),
+    }
+}
+
+fn cf_name(cf: String) -> String {
+    if cf.is_empty() {
+        CF_DEFAULT.to_string()
+    } else {
+        cf
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Request {
+    Put(ExplainedKv),
+    Delete {
+        cf: String,
+        key: ExplainedKey,
+    },
+    DeleteRange {
+        cf: String,
+        start_key: ExplainedKey,
+        end_key: ExplainedKey,
+        notify_only: bool,
+    },
+    IngestSst {
+        cf: String,
+        region_id: u64,
+        uuid: String,
+        start_key: ExplainedKey,
+        end_key: ExplainedKey,
+        length: u64,
+    },
+    Other {
+        cmd_type: u64,
+    },
+}
+
+const CMD_TYPE_PUT: u64 = 3;
+const CMD_TYPE_DELETE: u64 = 4;
+const CMD_TYPE_DELETE_RANGE: u64 = 7;
+const CMD_TYPE_INGEST_SST: u64 = 8;
+
+/// The field of `Request` carrying the request body for each `CmdType`.
+fn request_field(cmd_type: u64) -> u32 {
+    match cmd_type {
+        CMD_TYPE_PUT => 4,
+        CMD_TYPE_DELETE => 5,
+        CMD_TYPE_DELETE_RANGE => 8,
+        CMD_TYPE_INGEST_SST => 9,
+        _ => 0,
+    }
+}
+
+fn decode_put(b: &[u8]) -> anyhow::Result<ExplainedKv> {
+    let mut cf = String::new();
+    let mut key = ExplainedKey::default();
+    let mut value = Vec::new();
+    for field in protobuf::fields(b)? {
+        match field.number {
+            1 => cf = field.as_string()?,
+            2 => key = ExplainedKey::new(field.as_bytes()?),
+            3 => value = field.as_bytes()?.to_vec(),
+            _ => {}
+        }
+    }
+    let cf = cf_name(cf);
+    let value_explanation = explain_cf_value(&cf, &value);
+    Ok(ExplainedKv {
+        cf,
+        key,
+        value,
+        value_explanation,
+    })
+}
+
+fn decode_delete(b: &[u8]) -> anyhow::Result<Request> {
+    let mut cf = String::new();
+    let mut key = ExplainedKey::default();
+    for field in protobuf::fields(b)? {
+        match field.number {
+            1 => cf = field.as_string()?,
+            2 => key = ExplainedKey::new(field.as_bytes()?),
+            _ => {}
+        }
+    }
+    Ok(Request::Delete {
+        cf: cf_name(cf),
+        key,
+    })
+}
+
+fn decode_delete_range(b: &[u8]) -> anyhow::Result<Request> {
+    let mut cf = String::new();
+    let mut start_key = ExplainedKey::default();
+    let mut end_key = ExplainedKey::default();
+    let mut notify_only = false;
+    for field in protobuf::fields(b)? {
+        match field.number {
+            1 => cf = field.as_string()?,
+            2 => start_key = ExplainedKey::new(field.as_bytes()?),
+            3 => end_key = ExplainedKey::new(field.as_bytes()?),
+            4 => notify_only = field.as_bool()?,
+            _ => {}
+        }
+    }
+    Ok(Request::DeleteRange {
+        cf: cf_name(cf),
+        start_key,
+        end_key,
+        notify_only,
+    })
+}
+
+fn decode_ingest_sst(b: &[u8]) -> anyhow::Result<Request> {
+    let mut cf = String::new();
+    let mut region_id = 0;
+    let mut uuid = String::new();
+    let mut start_key = ExplainedKey::default();
+    let mut end_key = ExplainedKey::default();
+    let mut length = 0;
+    for field in protobuf::fields(b)? {
+        // IngestSSTRequest only has the `sst` field
+        if field.number != 1 {
+            continue;
+        }
+        for field in protobuf::fields(field.as_bytes()?)? {
+            match field.number {
+                1 => uuid = hex::encode(field.as_bytes()?),
+                2 => {
+                    for field in protobuf::fields(field.as_bytes()?)? {
+                        match field.number {
+                            1 => start_key = ExplainedKey::new(field.as_bytes()?),
+                            2 => end_key = ExplainedKey::new(field.as_bytes()?),
+                            _ => {}
+                        }
+                    }
+                }
+                4 => length = field.as_u64()?,
+                5 => cf = field.as_string()?,
+                6 => region_id = field.as_u64()?,
+                _ => {}
+            }
+        }
+    }
+    Ok(Request::IngestSst {
+        cf: cf_name(cf),
+        region_id,
+        uuid,
+        start_key,
+        end_key,
+        length,
+    })
+}
+
+impl Request {
+    pub fn decode(b: &[u8]) -> anyhow::Result<Request> {
+        let fields = protobuf::fields(b)?;
+        let mut cmd_type = 0;
+        for field in &fields {
+            if field.number == 1 {
+                cmd_type = field.as_u64()?;
+            }
+        }
+        let body = |number| fields.iter().find(|field| field.number == number);
+        let request = match (cmd_type, body(request_field(cmd_type))) {
+            (CMD_TYPE_PUT, Some(field)) => Request::Put(decode_put(field.as_bytes()?)?),
+            (CMD_TYPE_DELETE, Some(field)) => decode_delete(field.as_bytes()?)?,
+            (CMD_TYPE_DELETE_RANGE, Some(field)) => decode_delete_range(field.as_bytes()?)?,
+            (CMD_TYPE_INGEST_SST, Some(field)) => decode_ingest_sst(field.as_bytes()?)?,
+            _ => Request::Other { cmd_type },
+        };
+        Ok(request)
+    }
+}
+
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Request::Put(kv) => write!(f, "put {} {} => {}", kv.cf, kv.key, kv.value_explanation),
+            Request::Delete { cf, key } => write!(f, "delete {} {}", cf, key),
+            Request::DeleteRange {
+                cf,
+                start_key,
+                end_key,
+                notify_only,
+            } => {
+                write!(f, "delete_range {} [{}, {})", cf, start_key, end_key)?;
+                if *notify_only {
+                    write!(f, " notify_only")?;
+                }
+                Ok(())
+            }
+            Request::IngestSst {
+                cf,
+                region_id,
+                uuid,
+                start_key,
+                end_key,
+                length,
+            } => write!(
+                f,
+                "ingest_sst {} region {} uuid {} [{}, {}] length {}",
+                cf, region_id, uuid, start_key, end_key, length
+            ),
+            Request::Other { cmd_type } => write!(f, "cmd_type {}", cmd_type),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RaftRequestHeader {
+    pub region_id: u64,
+    pub peer: Peer,
+    pub uuid: String,
+    pub region_epoch: RegionEpoch,
+    pub term: u64,
+    pub flags: u64,
+}
+
+impl RaftRequestHeader {
+    pub fn decode(b: &[u8]) -> anyhow::Result<RaftRequestHeader> {
+        let mut result = RaftRequestHeader::default();
+        for field in protobuf::fields(b)? {
+            match field.number {
+                1 => result.region_id = field.as_u64()?,
+                2 => result.peer = Peer::decode(field.as_bytes()?)?,
+                4 => result.uuid = hex::encode(field.as_bytes()?),
+                5 => result.region_epoch = RegionEpoch::decode(field.as_bytes()?)?,
+                6 => result.term = field.as_u64()?,
+                9 => result.flags = field.as_u64()?,
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl fmt::Display for RaftRequestHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "region_id: {}, peer: {}, region_epoch: {}, term: {}",
+            self.region_id, self.peer, self.region_epoch, self.term
+        )?;
+        if self.flags != 0 {
+            write!(f, ", flags: {:#x}", self.flags)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum AdminRequest {
+    CompactLog {
+        compact_index: u64,
+        compact_term: u64,
+    },
+    TransferLeader {
+        peer: Peer,
+    },
+    BatchSplit {
+        split_keys: Vec<ExplainedKey>,
+        new_region_ids: Vec<u64>,
+    },
+    Other {
+        cmd_type: u64,
+    },
+}
+
+const ADMIN_CMD_TYPE_COMPACT_LOG: u64 = 3;
+const ADMIN_CMD_TYPE_TRANSFER_LEADER: u64 = 4;
+const ADMIN_CMD_TYPE_BATCH_SPLIT: u64 = 10;
+
+/// The field of `AdminRequest` carrying the request body for each `AdminCmdType`.
+fn admin_request_field(cmd_type: u64) -> u32 {
+    match cmd_type {
+        ADMIN_CMD_TYPE_COMPACT_LOG => 4,
+        ADMIN_CMD_TYPE_TRANSFER_LEADER => 5,
+        ADMIN_CMD_TYPE_BATCH_SPLIT => 10,
+        _ => 0,
+    }
+}
+
+impl AdminRequest {
+    pub fn decode(b: &[u8]) -> anyhow::Result<AdminRequest> {
+        let fields = protobuf::fields(b)?;
+        let mut cmd_type = 0;
+        for field in &fields {
+            if field.number == 1 {
+                cmd_type = field.as_u64()?;
+            }
+        }
+        let body = |number| fields.iter().find(|field| field.number == number);
+        let request = match (cmd_type, body(admin_request_field(cmd_type))) {
+            (ADMIN_CMD_TYPE_COMPACT_LOG, Some(field)) => {
+                let mut compact_index = 0;
+                let mut compact_term = 0;
+                for field in protobuf::fields(field.as_bytes()?)? {
+                    match field.number {
+                        1 => compact_index = field.as_u64()?,
+                        2 => compact_term = field.as_u64()?,
+                        _ => {}
+                    }
+                }
+                AdminRequest::CompactLog {
+                    compact_index,
+                    compact_term,
+                }
+            }
+            (ADMIN_CMD_TYPE_TRANSFER_LEADER, Some(field)) => {
+                let mut peer = Peer::default();
+                for field in protobuf::fields(field.as_bytes()?)? {
+                    if field.number == 1 {
+                        peer = Peer::decode(field.as_bytes()?)?;
+                    }
+                }
+                AdminRequest::TransferLeader { peer }
+            }
+            (ADMIN_CMD_TYPE_BATCH_SPLIT, Some(field)) => {
+                let mut split_keys = Vec::new();
+                let mut new_region_ids = Vec::new();
+                for field in protobuf::fields(field.as_bytes()?)? {
+                    if field.number != 1 {
+                        continue;
+                    }
+                    for field in protobuf::fields(field.as_bytes()?)? {
+                        match field.number {
+                            1 => split_keys.push(ExplainedKey::new(field.as_bytes()?)),
+                            2 => new_region_ids.push(field.as_u64()?),
+                            _ => {}
+                        }
+                    }
+                }
+                AdminRequest::BatchSplit {
+                    split_keys,
+                    new_region_ids,
+                }
+            }
+            _ => AdminRequest::Other { cmd_type },
+        };
+        Ok(request)
+    }
+}
+
+impl fmt::Display for AdminRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdminRequest::CompactLog {
+                compact_index,
+                compact_term,
+            } => write!(
+                f,
+                "compact_log index: {}, term: {}",
+                compact_index, compact_term
+            ),
+            AdminRequest::TransferLeader { peer } => write!(f, "transfer_leader to {}", peer),
+            AdminRequest::BatchSplit {
+                split_keys,
+                new_region_ids,
+            } => {
+                write!(f, "batch_split")?;
+                for (key, region_id) in split_keys.iter().zip(new_region_ids) {
+                    write!(f, " {} (new region {})", key, region_id)?;
+                }
+                Ok(())
+            }
+            AdminRequest::Other { cmd_type } => write!(f, "admin cmd_type {}", cmd_type),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RaftCmdRequest {
+    pub header: RaftRequestHeader,
+    pub requests: Vec<Request>,
+    pub admin_request: Option<AdminRequest>,
+}
+
+impl RaftCmdRequest {
+    pub fn decode(b: &[u8]) -> anyhow::Result<RaftCmdRequest> {
+        let mut result = RaftCmdRequest::default();
+        for field in protobuf::fields(b)? {
+            match field.number {
+                1 => result.header = RaftRequestHeader::decode(field.as_bytes()?)?,
+                2 => result.requests.push(Request::decode(field.as_bytes()?)?),
+                3 => result.admin_request = Some(AdminRequest::decode(field.as_bytes()?)?),
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl fmt::Display for RaftCmdRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "header: {}", self.header)?;
+        if let Some(admin_request) = &self.admin_request {
+            write!(f, "\n{}", admin_request)?;
+        }
+        for request in &self.requests {
+            write!(f, "\n{}", request)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Entry {
+    pub entry_type: EntryType,
+    pub term: u64,
+    pub index: u64,
+    /// The command carried by a normal entry, `None` for an empty entry
+    /// (eg. the one a new leader proposes) or a conf change.
+    pub command: Option<RaftCmdRequest>,
+    pub data: Vec<u8>,
+    pub context: Vec<u8>,
+}
+
+impl Entry {
+    pub fn decode(b: &[u8]) -> anyhow::Result<Entry> {
+        let mut result = Entry::default();
+        for field in protobuf::fields(b)? {
+            match field.number {
+                1 => result.entry_type = field.as_u64()?.into(),
+                2 => result.term = field.as_u64()?,
+                3 => result.index = field.as_u64()?,
+                4 => result.data = field.as_bytes()?.to_vec(),
+                6 => result.context = field.as_bytes()?.to_vec(),
+                _ => {}
+            }
+        }
+        if result.entry_type == EntryType::EntryNormal && !result.data.is_empty() {
+            result.command = Some(RaftCmdRequest::decode(&result.data)?);
+        }
+        Ok(result)
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} term: {}, index: {}",
+            self.entry_type, self.term, self.index
+        )?;
+        match &self.command {
+            Some(command) => write!(f, "\n{}", command),
+            None if self.data.is_empty() => write!(f, " (empty)"),
+            None => write!(f, " data: {}", hex::encode(&self.data)),
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub fn parse_raft_log_entry(value: &[u8]) -> Result<JsValue, JsValue> {
+    Entry::decode(value)
+        .map(|entry| utils::to_js_value(&entry))
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[wasm_bindgen]
+pub fn explain_raft_log_entry(value: &[u8]) -> Result<String, JsValue> {
+    Entry::decode(value)
+        .map(|entry| entry.to_string())
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk;
+    use crate::mvcc::{Key, TimeStamp};
+    use crate::varint;
+
+    fn varint_field(number: u64, v: u64) -> Vec<u8> {
+        let mut b = varint::encode_u64(number << 3);
+        b.extend(varint::encode_u64(v));
+        b
+    }
+
+    fn bytes_field(number: u64, v: &[u8]) -> Vec<u8> {
+        let mut b = varint::encode_u64(number << 3 | 2);
+        b.extend(varint::encode_u64(v.len() as u64));
+        b.extend_from_slice(v);
+        b
+    }
+
+    #[test]
+    fn test_decode_entry() {
+        let row = vec![
+            116, 128, 0, 0, 0, 0, 0, 0, 53, 95, 114, 128, 0, 0, 0, 0, 0, 0, 1,
+        ];
+        let write_key = Key::from_raw(&row).append_ts(TimeStamp(101)).into_encoded();
+        let write = vec![b'P', 100, b'v', 1, b'x'];
+        let put = [
+            bytes_field(1, b"write"),
+            bytes_field(2, &write_key),
+            bytes_field(3, &write),
+        ]
+        .concat();
+        let delete = [
+            bytes_field(1, b"lock"),
+            bytes_field(2, &chunk::encode_bytes(&row)),
+        ]
+        .concat();
+        let header = [varint_field(1, 2), varint_field(6, 7)].concat();
+        let command = [
+            bytes_field(1, &header),
+            bytes_field(2, &[varint_field(1, 3), bytes_field(4, &put)].concat()),
+            bytes_field(2, &[varint_field(1, 4), bytes_field(5, &delete)].concat()),
+        ]
+        .concat();
+        let entry = [
+            varint_field(2, 7),
+            varint_field(3, 10),
+            bytes_field(4, &command),
+        ]
+        .concat();
+        let entry = Entry::decode(&entry).unwrap();
+        assert_eq!(entry.command.as_ref().unwrap().requests.len(), 2);
+        assert_eq!(
+            entry.to_string(),
+            "EntryNormal term: 7, index: 10\n\
+             header: region_id: 2, peer: {id: 0, store_id: 0}, region_epoch: {conf_ver: 0, version: 0}, term: 7\n\
+             put write encoded(t53_r1)@101 => Put, start_ts: 100, short_value: \"x\"\n\
+             delete lock encoded(t53_r1)"
+        );
+    }
+
+    #[test]
+    fn test_decode_admin_entry() {
+        let compact_log = [varint_field(1, 5), varint_field(2, 6)].concat();
+        let admin = [varint_field(1, 3), bytes_field(4, &compact_log)].concat();
+        let command = bytes_field(3, &admin);
+        let entry = [
+            varint_field(2, 6),
+            varint_field(3, 9),
+            bytes_field(4, &command),
+        ]
+        .concat();
+        let entry = Entry::decode(&entry).unwrap();
+        assert_eq!(
+            entry.command.unwrap().admin_request,
+            Some(AdminRequest::CompactLog {
+                compact_index: 5,
+                compact_term: 6
+            })
+        );
+        let empty = Entry::decode(&[varint_field(2, 6), varint_field(3, 9)].concat()).unwrap();
+        assert_eq!(empty.to_string(), "EntryNormal term: 6, index: 9 (empty)");
+    }
+
+    #[test]
+    fn test_decode_malformed_cmd_type() {
+        for cmd_type in [u32::MAX as u64, u64::MAX, 0] {
+            let request = [varint_field(1, cmd_type), bytes_field(2, b"")].concat();
+            assert_eq!(
+                Request::decode(&request).unwrap(),
+                Request::Other { cmd_type }
+            );
+        }
+        // A put request without its body is not a put.
+        let request = [varint_field(1, 3), bytes_field(5, b"")].concat();
+        assert_eq!(
+            Request::decode(&request).unwrap(),
+            Request::Other { cmd_type: 3 }
+        );
+    }
+}
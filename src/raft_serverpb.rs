@@ -0,0 +1,465 @@
+//! Messages from kvproto's `raft_serverpb` (and the `metapb`/`eraftpb` messages they embed)
+//! which raftstore saves as the values of local keys.
+use crate::explain::ExplainedKey;
+use crate::protobuf;
+use crate::rawkv::ApiVersion;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct HardState {
+    pub term: u64,
+    pub vote: u64,
+    pub commit: u64,
+}
+
+impl HardState {
+    pub fn decode(b: &[u8]) -> anyhow::Result<HardState> {
+        let mut result = HardState::default();
+        for field in protobuf::fields(b)? {
+            match field.number {
+                1 => result.term = field.as_u64()?,
+                2 => result.vote = field.as_u64()?,
+                3 => result.commit = field.as_u64()?,
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RaftLocalState {
+    pub hard_state: HardState,
+    pub last_index: u64,
+}
+
+impl RaftLocalState {
+    pub fn decode(b: &[u8]) -> anyhow::Result<RaftLocalState> {
+        let mut result = RaftLocalState::default();
+        for field in protobuf::fields(b)? {
+            match field.number {
+                1 => result.hard_state = HardState::decode(field.as_bytes()?)?,
+                2 => result.last_index = field.as_u64()?,
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl fmt::Display for RaftLocalState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "hard_state: {{term: {}, vote: {}, commit: {}}}, last_index: {}",
+            self.hard_state.term, self.hard_state.vote, self.hard_state.commit, self.last_index
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RaftTruncatedState {
+    pub index: u64,
+    pub term: u64,
+}
+
+impl RaftTruncatedState {
+    pub fn decode(b: &[u8]) -> anyhow::Result<RaftTruncatedState> {
+        let mut result = RaftTruncatedState::default();
+        for field in protobuf::fields(b)? {
+            match field.number {
+                1 => result.index = field.as_u64()?,
+                2 => result.term = field.as_u64()?,
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RaftApplyState {
+    pub applied_index: u64,
+    pub truncated_state: RaftTruncatedState,
+    pub last_commit_index: u64,
+    pub commit_index: u64,
+    pub commit_term: u64,
+}
+
+impl RaftApplyState {
+    pub fn decode(b: &[u8]) -> anyhow::Result<RaftApplyState> {
+        let mut result = RaftApplyState::default();
+        for field in protobuf::fields(b)? {
+            match field.number {
+                1 => result.applied_index = field.as_u64()?,
+                2 => result.truncated_state = RaftTruncatedState::decode(field.as_bytes()?)?,
+                3 => result.last_commit_index = field.as_u64()?,
+                4 => result.commit_index = field.as_u64()?,
+                5 => result.commit_term = field.as_u64()?,
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl fmt::Display for RaftApplyState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "applied_index: {}, truncated_state: {{index: {}, term: {}}}, last_commit_index: {}, \
+             commit_index: {}, commit_term: {}",
+            self.applied_index,
+            self.truncated_state.index,
+            self.truncated_state.term,
+            self.last_commit_index,
+            self.commit_index,
+            self.commit_term
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RegionEpoch {
+    pub conf_ver: u64,
+    pub version: u64,
+}
+
+impl RegionEpoch {
+    pub fn decode(b: &[u8]) -> anyhow::Result<RegionEpoch> {
+        let mut result = RegionEpoch::default();
+        for field in protobuf::fields(b)? {
+            match field.number {
+                1 => result.conf_ver = field.as_u64()?,
+                2 => result.version = field.as_u64()?,
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl fmt::Display for RegionEpoch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{{conf_ver: {}, version: {}}}",
+            self.conf_ver, self.version
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum PeerRole {
+    #[default]
+    Voter,
+    Learner,
+    IncomingVoter,
+    DemotingVoter,
+    Unknown(u64),
+}
+
+impl From<u64> for PeerRole {
+    fn from(n: u64) -> Self {
+        match n {
+            0 => PeerRole::Voter,
+            1 => PeerRole::Learner,
+            2 => PeerRole::IncomingVoter,
+            3 => PeerRole::DemotingVoter,
+            n => PeerRole::Unknown(n),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Peer {
+    pub id: u64,
+    pub store_id: u64,
+    pub role: PeerRole,
+    pub is_witness: bool,
+}
+
+impl Peer {
+    pub fn decode(b: &[u8]) -> anyhow::Result<Peer> {
+        let mut result = Peer::default();
+        for field in protobuf::fields(b)? {
+            match field.number {
+                1 => result.id = field.as_u64()?,
+                2 => result.store_id = field.as_u64()?,
+                3 => result.role = field.as_u64()?.into(),
+                4 => result.is_witness = field.as_bool()?,
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{id: {}, store_id: {}", self.id, self.store_id)?;
+        if self.role != PeerRole::Voter {
+            write!(f, ", role: {:?}", self.role)?;
+        }
+        if self.is_witness {
+            write!(f, ", witness")?;
+        }
+        write!(f, "}}")
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Region {
+    pub id: u64,
+    pub start_key: ExplainedKey,
+    pub end_key: ExplainedKey,
+    pub region_epoch: RegionEpoch,
+    pub peers: Vec<Peer>,
+}
+
+impl Region {
+    pub fn decode(b: &[u8]) -> anyhow::Result<Region> {
+        let mut result = Region::default();
+        for field in protobuf::fields(b)? {
+            match field.number {
+                1 => result.id = field.as_u64()?,
+                2 => result.start_key = ExplainedKey::new(field.as_bytes()?),
+                3 => result.end_key = ExplainedKey::new(field.as_bytes()?),
+                4 => result.region_epoch = RegionEpoch::decode(field.as_bytes()?)?,
+                5 => result.peers.push(Peer::decode(field.as_bytes()?)?),
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{id: {}, start_key: ", self.id)?;
+        if self.start_key.key.is_empty() {
+            write!(f, "-inf")?;
+        } else {
+            write!(f, "{}", self.start_key)?;
+        }
+        write!(f, ", end_key: ")?;
+        if self.end_key.key.is_empty() {
+            write!(f, "+inf")?;
+        } else {
+            write!(f, "{}", self.end_key)?;
+        }
+        write!(f, ", region_epoch: {}, peers: [", self.region_epoch)?;
+        for (i, peer) in self.peers.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", peer)?;
+        }
+        write!(f, "]}}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum PeerState {
+    #[default]
+    Normal,
+    Applying,
+    Tombstone,
+    Merging,
+    Unavailable,
+    Unknown(u64),
+}
+
+impl From<u64> for PeerState {
+    fn from(n: u64) -> Self {
+        match n {
+            0 => PeerState::Normal,
+            1 => PeerState::Applying,
+            2 => PeerState::Tombstone,
+            3 => PeerState::Merging,
+            4 => PeerState::Unavailable,
+            n => PeerState::Unknown(n),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MergeState {
+    pub min_index: u64,
+    pub target: Region,
+    pub commit: u64,
+}
+
+impl MergeState {
+    pub fn decode(b: &[u8]) -> anyhow::Result<MergeState> {
+        let mut result = MergeState::default();
+        for field in protobuf::fields(b)? {
+            match field.number {
+                1 => result.min_index = field.as_u64()?,
+                2 => result.target = Region::decode(field.as_bytes()?)?,
+                3 => result.commit = field.as_u64()?,
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RegionLocalState {
+    pub state: PeerState,
+    pub region: Region,
+    pub merge_state: Option<MergeState>,
+}
+
+impl RegionLocalState {
+    pub fn decode(b: &[u8]) -> anyhow::Result<RegionLocalState> {
+        let mut result = RegionLocalState::default();
+        for field in protobuf::fields(b)? {
+            match field.number {
+                1 => result.state = field.as_u64()?.into(),
+                2 => result.region = Region::decode(field.as_bytes()?)?,
+                3 => result.merge_state = Some(MergeState::decode(field.as_bytes()?)?),
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl fmt::Display for RegionLocalState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "state: {:?}, region: {}", self.state, self.region)?;
+        if let Some(merge_state) = &self.merge_state {
+            write!(
+                f,
+                ", merge_state: {{min_index: {}, target: {}, commit: {}}}",
+                merge_state.min_index, merge_state.target, merge_state.commit
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StoreIdent {
+    pub cluster_id: u64,
+    pub store_id: u64,
+    pub api_version: u64,
+}
+
+impl StoreIdent {
+    pub fn decode(b: &[u8]) -> anyhow::Result<StoreIdent> {
+        let mut result = StoreIdent::default();
+        for field in protobuf::fields(b)? {
+            match field.number {
+                1 => result.cluster_id = field.as_u64()?,
+                2 => result.store_id = field.as_u64()?,
+                3 => result.api_version = field.as_u64()?,
+                _ => {}
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl fmt::Display for StoreIdent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cluster_id: {}, store_id: {}, api_version: ",
+            self.cluster_id, self.store_id
+        )?;
+        match ApiVersion::from_u64(self.api_version) {
+            Some(api_version) => write!(f, "{:?}", api_version),
+            None => write!(f, "{}", self.api_version),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk;
+    use crate::varint;
+
+    fn varint_field(number: u64, v: u64) -> Vec<u8> {
+        let mut b = varint::encode_u64(number << 3);
+        b.extend(varint::encode_u64(v));
+        b
+    }
+
+    fn bytes_field(number: u64, v: &[u8]) -> Vec<u8> {
+        let mut b = varint::encode_u64(number << 3 | 2);
+        b.extend(varint::encode_u64(v.len() as u64));
+        b.extend_from_slice(v);
+        b
+    }
+
+    #[test]
+    fn test_decode_raft_local_state() {
+        let hard_state = [varint_field(1, 6), varint_field(2, 3), varint_field(3, 10)].concat();
+        let code = [bytes_field(1, &hard_state), varint_field(2, 11)].concat();
+        let state = RaftLocalState::decode(&code).unwrap();
+        assert_eq!(
+            state.to_string(),
+            "hard_state: {term: 6, vote: 3, commit: 10}, last_index: 11"
+        );
+    }
+
+    #[test]
+    fn test_decode_apply_state() {
+        let truncated = [varint_field(1, 5), varint_field(2, 5)].concat();
+        let code = [
+            varint_field(1, 10),
+            bytes_field(2, &truncated),
+            varint_field(3, 9),
+            varint_field(4, 10),
+            varint_field(5, 6),
+        ]
+        .concat();
+        let state = RaftApplyState::decode(&code).unwrap();
+        assert_eq!(state.applied_index, 10);
+        assert_eq!(
+            state.truncated_state,
+            RaftTruncatedState { index: 5, term: 5 }
+        );
+        assert_eq!(
+            (
+                state.last_commit_index,
+                state.commit_index,
+                state.commit_term
+            ),
+            (9, 10, 6)
+        );
+    }
+
+    #[test]
+    fn test_decode_region_local_state() {
+        let start_key = chunk::encode_bytes(&[
+            116, 128, 0, 0, 0, 0, 0, 0, 53, 95, 114, 128, 0, 0, 0, 0, 0, 0, 1,
+        ]);
+        let epoch = [varint_field(1, 1), varint_field(2, 5)].concat();
+        let peer1 = [varint_field(1, 43), varint_field(2, 1)].concat();
+        let peer2 = [varint_field(1, 44), varint_field(2, 2), varint_field(3, 1)].concat();
+        let region = [
+            varint_field(1, 42),
+            bytes_field(2, &start_key),
+            bytes_field(4, &epoch),
+            bytes_field(5, &peer1),
+            bytes_field(5, &peer2),
+        ]
+        .concat();
+        let code = [varint_field(1, 0), bytes_field(2, &region)].concat();
+        let state = RegionLocalState::decode(&code).unwrap();
+        assert_eq!(state.region.peers.len(), 2);
+        assert_eq!(state.region.peers[1].role, PeerRole::Learner);
+        assert_eq!(
+            state.to_string(),
+            "state: Normal, region: {id: 42, start_key: encoded(t53_r1), end_key: +inf, \
+             region_epoch: {conf_ver: 1, version: 5}, \
+             peers: [{id: 43, store_id: 1}, {id: 44, store_id: 2, role: Learner}]}"
+        );
+    }
+}
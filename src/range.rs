@@ -0,0 +1,307 @@
+//! Key ranges, like the ones `tikv-ctl scan`, split commands and compactions take.
+//!
+//! A range is `[start, end)`, an empty `end` means there is no upper bound.
+//! The ranges of tables and indexes are built on raw keys, `to_encoded` turns them
+//! into ranges of memcomparable encoded keys, which the MVCC keys with a timestamp
+//! still compare correctly against.
+use crate::chunk;
+use crate::db_to_kv::{self, INDEX_PREFIX_SEP, RECORD_PREFIX_SEP};
+use crate::explain;
+use anyhow::anyhow;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+/// The smallest key greater than all the keys having `key` as the prefix,
+/// same as `kv.Key.PrefixNext` of TiDB.
+#[wasm_bindgen]
+pub fn prefix_next(key: &[u8]) -> Vec<u8> {
+    let mut result = key.to_vec();
+    for c in result.iter_mut().rev() {
+        if *c == u8::MAX {
+            *c = 0;
+        } else {
+            *c += 1;
+            return result;
+        }
+    }
+    // All bytes are 0xff, no key of the same length is greater.
+    let mut result = key.to_vec();
+    result.push(0);
+    result
+}
+
+/// The smallest key greater than `key`.
+#[wasm_bindgen]
+pub fn successor(key: &[u8]) -> Vec<u8> {
+    let mut result = key.to_vec();
+    result.push(0);
+    result
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct KeyRange {
+    #[wasm_bindgen(skip)]
+    pub start: Vec<u8>,
+    #[wasm_bindgen(skip)]
+    pub end: Vec<u8>,
+}
+
+impl KeyRange {
+    pub fn new(start: impl Into<Vec<u8>>, end: impl Into<Vec<u8>>) -> KeyRange {
+        KeyRange {
+            start: start.into(),
+            end: end.into(),
+        }
+    }
+
+    /// All the keys having `prefix` as the prefix. The range has no upper bound if
+    /// the prefix is empty or all 0xff, as no key of its length is greater.
+    pub fn of_prefix(prefix: &[u8]) -> KeyRange {
+        if prefix.iter().all(|c| *c == u8::MAX) {
+            return KeyRange::new(prefix, vec![]);
+        }
+        KeyRange::new(prefix, prefix_next(prefix))
+    }
+
+    /// The range of the memcomparable encoded keys of the keys in this range.
+    pub fn to_encoded(&self) -> KeyRange {
+        let encode = |key: &[u8]| {
+            if key.is_empty() {
+                vec![]
+            } else {
+                chunk::encode_bytes(key)
+            }
+        };
+        KeyRange::new(encode(&self.start), encode(&self.end))
+    }
+
+    /// The reverse of `to_encoded`.
+    pub fn from_encoded(range: &KeyRange) -> anyhow::Result<KeyRange> {
+        let decode = |key: &[u8]| -> anyhow::Result<Vec<u8>> {
+            if key.is_empty() {
+                return Ok(vec![]);
+            }
+            match chunk::decode_bytes_prefix(key) {
+                Some((raw, consumed)) if consumed == key.len() => Ok(raw),
+                _ => Err(anyhow!(
+                    "{} is not memcomparable encoded",
+                    explain::escape(key)
+                )),
+            }
+        };
+        Ok(KeyRange::new(decode(&range.start)?, decode(&range.end)?))
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.end.is_empty()
+    }
+
+    /// The intersection of the two ranges, `None` if they don't overlap.
+    pub fn intersection(&self, other: &KeyRange) -> Option<KeyRange> {
+        let start = self.start.as_slice().max(other.start.as_slice());
+        let end = match (self.is_unbounded(), other.is_unbounded()) {
+            (true, _) => other.end.as_slice(),
+            (_, true) => self.end.as_slice(),
+            _ => self.end.as_slice().min(other.end.as_slice()),
+        };
+        if !end.is_empty() && start >= end {
+            return None;
+        }
+        Some(KeyRange::new(start, end))
+    }
+}
+
+#[wasm_bindgen]
+impl KeyRange {
+    #[wasm_bindgen(constructor)]
+    pub fn new_js(start: Vec<u8>, end: Vec<u8>) -> KeyRange {
+        KeyRange::new(start, end)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn start(&self) -> Vec<u8> {
+        self.start.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn end(&self) -> Vec<u8> {
+        self.end.clone()
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        key >= self.start.as_slice() && (self.is_unbounded() || key < self.end.as_slice())
+    }
+
+    /// Whether all keys of `other` are in this range.
+    pub fn contains_range(&self, other: &KeyRange) -> bool {
+        other.start >= self.start
+            && (self.is_unbounded() || (!other.is_unbounded() && other.end <= self.end))
+    }
+
+    pub fn intersects(&self, other: &KeyRange) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    pub fn explain(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for KeyRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bound = |key: &[u8], unbounded: &'static str| {
+            if key.is_empty() {
+                unbounded.to_string()
+            } else {
+                explain::explain_key(key)
+            }
+        };
+        write!(
+            f,
+            "{} .. {}",
+            bound(&self.start, "-inf"),
+            bound(&self.end, "+inf")
+        )
+    }
+}
+
+fn table_prefix(keyspace_id: Option<u32>, table_id: i64) -> anyhow::Result<Vec<u8>> {
+    db_to_kv::with_keyspace(keyspace_id, db_to_kv::encode_table_prefix(table_id))
+}
+
+/// All the keys of the table, including rows and indexes.
+pub fn table_range_rust(keyspace_id: Option<u32>, table_id: i64) -> anyhow::Result<KeyRange> {
+    Ok(KeyRange::of_prefix(&table_prefix(keyspace_id, table_id)?))
+}
+
+/// All the row keys of the table.
+pub fn table_record_range_rust(
+    keyspace_id: Option<u32>,
+    table_id: i64,
+) -> anyhow::Result<KeyRange> {
+    let mut prefix = table_prefix(keyspace_id, table_id)?;
+    prefix.extend_from_slice(RECORD_PREFIX_SEP);
+    Ok(KeyRange::of_prefix(&prefix))
+}
+
+/// All the index keys of the table, or of one index if `index_id` is set.
+pub fn table_index_range_rust(
+    keyspace_id: Option<u32>,
+    table_id: i64,
+    index_id: Option<i64>,
+) -> anyhow::Result<KeyRange> {
+    let mut prefix = table_prefix(keyspace_id, table_id)?;
+    prefix.extend_from_slice(INDEX_PREFIX_SEP);
+    if let Some(index_id) = index_id {
+        prefix.extend_from_slice(&db_to_kv::encode_i64(index_id));
+    }
+    Ok(KeyRange::of_prefix(&prefix))
+}
+
+#[wasm_bindgen]
+pub fn table_range(keyspace_id: Option<u32>, table_id: i64) -> Result<KeyRange, JsValue> {
+    table_range_rust(keyspace_id, table_id).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[wasm_bindgen]
+pub fn table_record_range(keyspace_id: Option<u32>, table_id: i64) -> Result<KeyRange, JsValue> {
+    table_record_range_rust(keyspace_id, table_id).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[wasm_bindgen]
+pub fn table_index_range(
+    keyspace_id: Option<u32>,
+    table_id: i64,
+    index_id: Option<i64>,
+) -> Result<KeyRange, JsValue> {
+    table_index_range_rust(keyspace_id, table_id, index_id)
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mvcc::{Key, TimeStamp};
+
+    #[test]
+    fn test_prefix_next() {
+        assert_eq!(prefix_next(b"ab"), b"ac");
+        assert_eq!(prefix_next(b"a\xff\xff"), b"b\x00\x00");
+        assert_eq!(prefix_next(b"\xff\xff"), b"\xff\xff\x00");
+        assert_eq!(prefix_next(b""), b"\x00");
+        assert_eq!(successor(b"ab"), b"ab\x00");
+
+        assert_eq!(KeyRange::of_prefix(b"a\xff").end, b"b\x00");
+        let all_ff = KeyRange::of_prefix(b"\xff\xff");
+        assert_eq!(all_ff.end, b"");
+        assert!(all_ff.contains(b"\xff\xff\xff\x01"));
+        assert_eq!(KeyRange::of_prefix(b""), KeyRange::default());
+    }
+
+    #[test]
+    fn test_table_ranges() {
+        let rows = table_record_range_rust(None, 53).unwrap();
+        // There is no key type after `_r`, so the end is shown as bytes.
+        assert_eq!(
+            rows.to_string(),
+            "t53_r .. \"t\\x80\\x00\\x00\\x00\\x00\\x00\\x005_s\""
+        );
+        assert!(rows.contains(&db_to_kv::encode_record_key_rust(None, 53, i64::MIN).unwrap()));
+        assert!(rows.contains(&db_to_kv::encode_record_key_rust(None, 53, i64::MAX).unwrap()));
+        assert!(!rows.contains(&db_to_kv::encode_index_key_rust(None, 53, 1, &[]).unwrap()));
+
+        let index = table_index_range_rust(None, 53, Some(2)).unwrap();
+        assert_eq!(index.to_string(), "t53_i2 .. t53_i3");
+        assert!(index.contains(&db_to_kv::encode_index_key_rust(None, 53, 2, b"\x03").unwrap()));
+        assert!(!index.intersects(&rows));
+
+        let table = table_range_rust(None, 53).unwrap();
+        assert_eq!(table.to_string(), "t53 .. t54");
+        assert!(table.contains_range(&rows));
+        assert!(table.contains_range(&table_index_range_rust(None, 53, None).unwrap()));
+        assert!(!rows.contains_range(&table));
+        assert_eq!(
+            table_range_rust(None, -1).unwrap().end,
+            db_to_kv::encode_table_prefix(0)
+        );
+
+        let with_keyspace = table_record_range_rust(Some(7), 53).unwrap();
+        // The keyspace id only has 3 bytes.
+        assert!(table_range_rust(Some(1 << 24), 53).is_err());
+        assert!(with_keyspace
+            .to_string()
+            .starts_with("keyspace 7: t53_r .. "));
+        assert!(!with_keyspace.intersects(&rows));
+    }
+
+    #[test]
+    fn test_range_operations() {
+        let all = KeyRange::default();
+        let a = KeyRange::new(b"a".to_vec(), b"c".to_vec());
+        let b = KeyRange::new(b"b".to_vec(), vec![]);
+        assert_eq!(all.to_string(), "-inf .. +inf");
+        assert!(all.contains_range(&b));
+        assert!(!a.contains_range(&b));
+        assert_eq!(
+            a.intersection(&b),
+            Some(KeyRange::new(b"b".to_vec(), b"c".to_vec()))
+        );
+        assert_eq!(b.intersection(&all), Some(b.clone()));
+        assert_eq!(
+            KeyRange::new(b"c".to_vec(), b"d".to_vec()).intersection(&a),
+            None
+        );
+
+        // MVCC keys of the rows are in the encoded range of the rows.
+        let rows = table_record_range_rust(None, 53).unwrap();
+        let encoded = rows.to_encoded();
+        let key = Key::from_raw(&db_to_kv::encode_record_key_rust(None, 53, 1).unwrap())
+            .append_ts(TimeStamp(u64::MAX))
+            .into_encoded();
+        assert!(encoded.contains(&key));
+        assert!(!encoded.contains(&Key::from_raw(&rows.end).into_encoded()));
+        assert_eq!(KeyRange::from_encoded(&encoded).unwrap(), rows);
+        assert!(KeyRange::from_encoded(&rows).is_err());
+    }
+}
@@ -0,0 +1,373 @@
+//! Keys and values written by the RawKV API, whose layout depends on the API version.
+use crate::chunk;
+use crate::explain;
+use crate::keyspace::{KeyMode, Keyspace, KEYSPACE_PREFIX_LEN};
+use crate::mvcc::{Key, TimeStamp, Value};
+use crate::trace::{EncodeMethod, ParsingTrace};
+use crate::utils;
+use anyhow::{anyhow, bail};
+use std::convert::TryInto;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+/// Set in the meta flag byte of an API v2 value if an expire ts is present.
+const VALUE_META_EXPIRE_TS: u8 = 0b0000_0001;
+/// Set in the meta flag byte of an API v2 value if the value is deleted.
+const VALUE_META_DELETE_FLAG: u8 = 0b0000_0010;
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ApiVersion {
+    V1,
+    V1ttl,
+    V2,
+}
+
+impl ApiVersion {
+    /// Convert from `kvrpcpb::APIVersion`.
+    pub fn from_u64(n: u64) -> Option<ApiVersion> {
+        match n {
+            0 => Some(ApiVersion::V1),
+            1 => Some(ApiVersion::V1ttl),
+            2 => Some(ApiVersion::V2),
+            _ => None,
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawKey {
+    keyspace: Option<Keyspace>,
+    user_key: Vec<u8>,
+    /// Only API v2 keys saved in the storage have a timestamp.
+    ts: Option<TimeStamp>,
+    parsing_trace: ParsingTrace,
+}
+
+impl RawKey {
+    pub fn parse_rust(api_version: ApiVersion, b: &[u8]) -> anyhow::Result<RawKey> {
+        if api_version != ApiVersion::V2 {
+            let mut key = RawKey {
+                keyspace: None,
+                user_key: b.to_vec(),
+                ts: None,
+                parsing_trace: ParsingTrace::default(),
+            };
+            key.parsing_trace = ParsingTrace::node(
+                "raw_key",
+                vec![
+                    ParsingTrace::leaf(0, b.len(), "user_key", EncodeMethod::Bytes)
+                        .with_value(explain::escape(b)),
+                ],
+            )
+            .with_value(&key);
+            return Ok(key);
+        }
+        // The storage saves API v2 keys memcomparable encoded, with the ts appended.
+        let (raw, encoded) = match chunk::trace_bytes_prefix(b) {
+            Some((raw, encoded)) if b.len() - encoded.width == 8 => (raw, Some(encoded)),
+            _ => (b.to_vec(), None),
+        };
+        let (keyspace, user_key) =
+            Keyspace::split(&raw).ok_or_else(|| anyhow!("API v2 key without keyspace prefix"))?;
+        if keyspace.mode != KeyMode::Raw {
+            bail!("{} is not a RawKV keyspace", keyspace);
+        }
+        let user_key_trace = ParsingTrace::node(
+            "key",
+            vec![
+                keyspace.trace(),
+                ParsingTrace::leaf(
+                    KEYSPACE_PREFIX_LEN,
+                    user_key.len(),
+                    "user_key",
+                    EncodeMethod::Bytes,
+                )
+                .with_value(explain::escape(user_key)),
+            ],
+        );
+        let mut key = RawKey {
+            keyspace: Some(keyspace),
+            user_key: user_key.to_vec(),
+            ts: None,
+            parsing_trace: ParsingTrace::default(),
+        };
+        let children = match encoded {
+            Some(mut encoded) => {
+                key.ts = Some(Key::from_encoded(b.to_vec()).decode_ts()?);
+                encoded.field = "encoded_key".to_string();
+                let ts = ParsingTrace::leaf(encoded.width, 8, "ts", EncodeMethod::DescendingU64)
+                    .with_value(key.ts.unwrap_or_default());
+                vec![encoded.with_inner(user_key_trace.with_value(&key)), ts]
+            }
+            None => user_key_trace.children,
+        };
+        key.parsing_trace = ParsingTrace::node("raw_key", children).with_value(&key);
+        Ok(key)
+    }
+
+    pub fn keyspace(&self) -> Option<Keyspace> {
+        self.keyspace
+    }
+}
+
+#[wasm_bindgen]
+impl RawKey {
+    pub fn parse(api_version: ApiVersion, b: &[u8]) -> Result<RawKey, JsValue> {
+        Self::parse_rust(api_version, b).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let raw = match &self.keyspace {
+            Some(keyspace) => keyspace.apply(&self.user_key),
+            None => self.user_key.clone(),
+        };
+        match self.ts {
+            Some(ts) => Key::from_raw(&raw).append_ts(ts).into_encoded(),
+            None => raw,
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn keyspace_id(&self) -> Option<u32> {
+        self.keyspace.map(|keyspace| keyspace.id)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn user_key(&self) -> Vec<u8> {
+        self.user_key.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn ts(&self) -> Option<u64> {
+        self.ts.map(|ts| ts.0)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn parsing_trace(&self) -> JsValue {
+        utils::to_js_value(&self.parsing_trace)
+    }
+}
+
+impl fmt::Display for RawKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(keyspace) = &self.keyspace {
+            write!(f, "{}: ", keyspace)?;
+        }
+        write!(f, "{}", explain::escape(&self.user_key))?;
+        if let Some(ts) = self.ts {
+            write!(f, "@{}", ts)?;
+        }
+        Ok(())
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawValue {
+    pub api_version: ApiVersion,
+    user_value: Value,
+    /// Unix timestamp in seconds after which the value is expired.
+    expire_ts: Option<u64>,
+    pub is_delete: bool,
+    parsing_trace: ParsingTrace,
+}
+
+impl RawValue {
+    pub fn parse_rust(api_version: ApiVersion, b: &[u8]) -> anyhow::Result<RawValue> {
+        let mut suffix = Vec::new();
+        let (user_value_len, expire_ts, is_delete) = match api_version {
+            ApiVersion::V1 => (b.len(), None, false),
+            ApiVersion::V1ttl => {
+                if b.len() < 8 {
+                    bail!("API v1ttl value should have an 8 bytes expire ts");
+                }
+                let len = b.len() - 8;
+                let expire_ts = u64::from_be_bytes(b[len..].try_into()?);
+                suffix.push(
+                    ParsingTrace::leaf(len, 8, "expire_ts", EncodeMethod::BigEndian)
+                        .with_value(expire_ts),
+                );
+                // 0 means the value never expires
+                (len, Some(expire_ts).filter(|&ts| ts != 0), false)
+            }
+            ApiVersion::V2 => {
+                let meta_flag = *b
+                    .last()
+                    .ok_or_else(|| anyhow!("API v2 value should have a meta flag"))?;
+                let mut len = b.len() - 1;
+                let expire_ts = if meta_flag & VALUE_META_EXPIRE_TS != 0 {
+                    if len < 8 {
+                        bail!("API v2 value has the expire flag but no expire ts");
+                    }
+                    len -= 8;
+                    let expire_ts = u64::from_be_bytes(b[len..len + 8].try_into()?);
+                    suffix.push(
+                        ParsingTrace::leaf(len, 8, "expire_ts", EncodeMethod::BigEndian)
+                            .with_value(expire_ts),
+                    );
+                    Some(expire_ts)
+                } else {
+                    None
+                };
+                suffix.push(
+                    ParsingTrace::leaf(b.len() - 1, 1, "meta_flag", EncodeMethod::EnumFlag)
+                        .with_value(meta_flag),
+                );
+                (len, expire_ts, meta_flag & VALUE_META_DELETE_FLAG != 0)
+            }
+        };
+        let user_value = &b[..user_value_len];
+        let mut children =
+            vec![
+                ParsingTrace::leaf(0, user_value_len, "user_value", EncodeMethod::Bytes)
+                    .with_value(explain::escape(user_value)),
+            ];
+        children.extend(suffix);
+        let mut value = RawValue {
+            api_version,
+            user_value: user_value.to_vec(),
+            expire_ts,
+            is_delete,
+            parsing_trace: ParsingTrace::default(),
+        };
+        value.parsing_trace = ParsingTrace::node("raw_value", children).with_value(&value);
+        Ok(value)
+    }
+
+    /// Whether the value is expired at `now`, in unix seconds.
+    pub fn is_expired_at(&self, now: u64) -> bool {
+        self.expire_ts.is_some_and(|ts| ts <= now)
+    }
+}
+
+#[wasm_bindgen]
+impl RawValue {
+    pub fn parse(api_version: ApiVersion, b: &[u8]) -> Result<RawValue, JsValue> {
+        Self::parse_rust(api_version, b).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut b = self.user_value.clone();
+        match self.api_version {
+            ApiVersion::V1 => {}
+            ApiVersion::V1ttl => b.extend_from_slice(&self.expire_ts.unwrap_or(0).to_be_bytes()),
+            ApiVersion::V2 => {
+                let mut meta_flag = 0;
+                if let Some(ts) = self.expire_ts {
+                    b.extend_from_slice(&ts.to_be_bytes());
+                    meta_flag |= VALUE_META_EXPIRE_TS;
+                }
+                if self.is_delete {
+                    meta_flag |= VALUE_META_DELETE_FLAG;
+                }
+                b.push(meta_flag);
+            }
+        }
+        b
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn user_value(&self) -> Value {
+        self.user_value.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn expire_ts(&self) -> Option<u64> {
+        self.expire_ts
+    }
+
+    /// The expire time in a human readable form.
+    #[wasm_bindgen(getter)]
+    pub fn expire_time(&self) -> Option<String> {
+        self.expire_ts
+            .map(|ts| utils::format_unix_millis(ts.saturating_mul(1000)))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn parsing_trace(&self) -> JsValue {
+        utils::to_js_value(&self.parsing_trace)
+    }
+}
+
+impl fmt::Display for RawValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", explain::escape(&self.user_value))?;
+        if let (Some(ts), Some(time)) = (self.expire_ts, self.expire_time()) {
+            write!(f, ", expire at {} ({})", time, ts)?;
+        }
+        if self.is_delete {
+            write!(f, ", deleted")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_raw_value() {
+        let cases = vec![
+            (ApiVersion::V1, b"abc".to_vec(), "\"abc\"", 1),
+            (
+                ApiVersion::V1ttl,
+                b"abc\x00\x00\x00\x00\x60\xb7\x4e\x6d".to_vec(),
+                "\"abc\", expire at 2021-06-02 09:25:01.000 UTC (1622625901)",
+                2,
+            ),
+            (
+                ApiVersion::V1ttl,
+                b"abc\x00\x00\x00\x00\x00\x00\x00\x00".to_vec(),
+                "\"abc\"",
+                2,
+            ),
+            (ApiVersion::V2, b"abc\x00".to_vec(), "\"abc\"", 2),
+            (
+                ApiVersion::V2,
+                b"abc\x00\x00\x00\x00\x60\xb7\x4e\x6d\x01".to_vec(),
+                "\"abc\", expire at 2021-06-02 09:25:01.000 UTC (1622625901)",
+                3,
+            ),
+            (ApiVersion::V2, b"\x02".to_vec(), "\"\", deleted", 2),
+        ];
+        for (api_version, code, expected, trace_len) in cases {
+            let value = RawValue::parse_rust(api_version, &code).unwrap();
+            assert_eq!(value.to_string(), expected);
+            assert_eq!(value.parsing_trace.children.len(), trace_len);
+            assert_eq!(value.parsing_trace.width, code.len());
+            if api_version != ApiVersion::V1ttl || value.expire_ts.is_some() {
+                assert_eq!(value.to_bytes(), code);
+            }
+        }
+        assert!(RawValue::parse_rust(ApiVersion::V2, b"abc\x01").is_err());
+        assert!(RawValue::parse_rust(ApiVersion::V2, b"")
+            .unwrap_err()
+            .to_string()
+            .contains("meta flag"));
+    }
+
+    #[test]
+    fn test_parse_raw_key() {
+        let key = RawKey::parse_rust(ApiVersion::V2, b"r\x00\x00\x07abc").unwrap();
+        assert_eq!(key.keyspace().unwrap().id, 7);
+        assert_eq!(key.to_string(), "raw keyspace 7: \"abc\"");
+
+        let encoded = Key::from_raw(b"r\x00\x00\x07abc")
+            .append_ts(TimeStamp(10))
+            .into_encoded();
+        let key = RawKey::parse_rust(ApiVersion::V2, &encoded).unwrap();
+        assert_eq!(key.to_string(), "raw keyspace 7: \"abc\"@10");
+        let encoded_key = &key.parsing_trace.children[0];
+        assert_eq!(encoded_key.width, 9);
+        let inner = encoded_key.inner.as_ref().unwrap();
+        assert_eq!(inner.children[1].value.as_deref(), Some("\"abc\""));
+        assert_eq!(key.to_bytes(), encoded);
+
+        assert!(RawKey::parse_rust(ApiVersion::V2, b"x\x00\x00\x07abc").is_err());
+        let key = RawKey::parse_rust(ApiVersion::V1, b"abc").unwrap();
+        assert_eq!(key.to_string(), "\"abc\"");
+    }
+}
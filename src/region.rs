@@ -0,0 +1,229 @@
+//! Find the regions of keys from the region boundaries PD reports.
+//!
+//! PD reports the boundaries of TxnKV regions memcomparable encoded, while those of
+//! RawKV regions are the raw keys. A `RegionMap` tells which one it was given and
+//! keeps the raw ranges, so it can be asked about raw keys and MVCC keys alike.
+use crate::chunk;
+use crate::explain;
+use crate::input;
+use crate::range::KeyRange;
+use anyhow::{anyhow, bail};
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub id: u64,
+    /// The raw keys this region covers.
+    pub range: KeyRange,
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "region {}: {}", self.id, self.range)
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RegionMap {
+    /// Sorted by the start keys.
+    regions: Vec<Region>,
+    /// Whether the boundaries were memcomparable encoded.
+    pub encoded: bool,
+}
+
+fn is_encoded(key: &[u8]) -> bool {
+    key.is_empty()
+        || matches!(chunk::decode_bytes_prefix(key), Some((_, consumed)) if consumed == key.len())
+}
+
+impl RegionMap {
+    /// Build the map from `(region_id, start_key, end_key)`, the boundaries are
+    /// treated as encoded if all of them are.
+    pub fn from_boundaries(
+        boundaries: impl IntoIterator<Item = (u64, Vec<u8>, Vec<u8>)>,
+    ) -> anyhow::Result<RegionMap> {
+        let boundaries: Vec<_> = boundaries.into_iter().collect();
+        let encoded = boundaries
+            .iter()
+            .all(|(_, start, end)| is_encoded(start) && is_encoded(end));
+        let mut regions = boundaries
+            .into_iter()
+            .map(|(id, start, end)| {
+                let range = KeyRange::new(start, end);
+                let range = if encoded {
+                    KeyRange::from_encoded(&range)?
+                } else {
+                    range
+                };
+                Ok(Region { id, range })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        regions.sort_by(|a, b| a.range.start.cmp(&b.range.start));
+        for pair in regions.windows(2) {
+            if pair[0].range.is_unbounded() || pair[0].range.end > pair[1].range.start {
+                bail!("{} overlaps with {}", pair[0], pair[1]);
+            }
+        }
+        Ok(RegionMap { regions, encoded })
+    }
+
+    /// Same as `from_boundaries`, with the boundaries in any form `input` can read.
+    pub fn parse(boundaries: &[(u64, &str, &str)]) -> anyhow::Result<RegionMap> {
+        let boundaries = boundaries
+            .iter()
+            .map(|&(id, start, end)| {
+                Ok((
+                    id,
+                    input::parse_input_rust(start)?,
+                    input::parse_input_rust(end)?,
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Self::from_boundaries(boundaries)
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// The region holding the raw `key`.
+    pub fn locate(&self, key: &[u8]) -> Option<&Region> {
+        let i = self
+            .regions
+            .partition_point(|region| region.range.start.as_slice() <= key);
+        let region = self.regions.get(i.checked_sub(1)?)?;
+        region.range.contains(key).then_some(region)
+    }
+
+    /// The region holding the memcomparable encoded `key`, with or without a timestamp.
+    pub fn locate_encoded(&self, key: &[u8]) -> anyhow::Result<Option<&Region>> {
+        let (raw, consumed) = chunk::decode_bytes_prefix(key)
+            .ok_or_else(|| anyhow!("{} is not memcomparable encoded", explain::escape(key)))?;
+        if key.len() != consumed && key.len() != consumed + 8 {
+            bail!("unexpected bytes after the encoded key");
+        }
+        Ok(self.locate(&raw))
+    }
+
+    /// The regions the raw key range spans.
+    pub fn regions_in(&self, range: &KeyRange) -> Vec<&Region> {
+        self.regions
+            .iter()
+            .filter(|region| region.range.intersects(range))
+            .collect()
+    }
+}
+
+#[wasm_bindgen]
+impl RegionMap {
+    /// Build the map from the region ids and the boundaries in any input form,
+    /// `boundaries` are the start and end keys of each region in turn.
+    #[wasm_bindgen(constructor)]
+    pub fn new(ids: Vec<u64>, boundaries: Vec<String>) -> Result<RegionMap, JsValue> {
+        if boundaries.len() != ids.len() * 2 {
+            return Err(JsValue::from("need a start and an end key for each region"));
+        }
+        let boundaries: Vec<_> = ids
+            .iter()
+            .zip(boundaries.chunks(2))
+            .map(|(&id, pair)| (id, pair[0].as_str(), pair[1].as_str()))
+            .collect();
+        Self::parse(&boundaries).map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// The id of the region holding the raw `key`.
+    #[wasm_bindgen(js_name = locate)]
+    pub fn locate_js(&self, key: &[u8]) -> Option<u64> {
+        self.locate(key).map(|region| region.id)
+    }
+
+    /// The ids of the regions the raw key range spans.
+    #[wasm_bindgen(js_name = regionsIn)]
+    pub fn regions_in_js(&self, range: &KeyRange) -> Vec<u64> {
+        self.regions_in(range)
+            .into_iter()
+            .map(|region| region.id)
+            .collect()
+    }
+
+    pub fn explain(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for RegionMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for region in &self.regions {
+            writeln!(f, "{}", region)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db_to_kv;
+    use crate::mvcc::{Key, TimeStamp};
+    use crate::range;
+
+    #[test]
+    fn test_region_map() {
+        let split_1 =
+            chunk::encode_bytes(&db_to_kv::encode_record_key_rust(None, 53, 1000).unwrap());
+        let split_2 =
+            chunk::encode_bytes(&db_to_kv::encode_index_key_rust(None, 54, 1, &[]).unwrap());
+        let map = RegionMap::parse(&[
+            (12, &hex::encode(&split_1), &hex::encode(&split_2)),
+            (2, "", &format!("{:?}", split_1)),
+            (30, &hex::encode(&split_2), ""),
+        ])
+        .unwrap();
+        assert!(map.encoded);
+        assert_eq!(
+            map.to_string(),
+            "region 2: -inf .. t53_r1000\n\
+             region 12: t53_r1000 .. t54_i1\n\
+             region 30: t54_i1 .. +inf\n"
+        );
+
+        let row = |id| db_to_kv::encode_record_key_rust(None, 53, id).unwrap();
+        assert_eq!(map.locate(&row(1)).unwrap().id, 2);
+        assert_eq!(map.locate(&row(1000)).unwrap().id, 12);
+        assert_eq!(map.locate(b"zzz").unwrap().id, 30);
+        let mvcc_key = Key::from_raw(&row(1001))
+            .append_ts(TimeStamp(42))
+            .into_encoded();
+        assert_eq!(map.locate_encoded(&mvcc_key).unwrap().unwrap().id, 12);
+
+        let ids = |range| {
+            map.regions_in(&range)
+                .into_iter()
+                .map(|region| region.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ids(range::table_record_range_rust(None, 53).unwrap()),
+            vec![2, 12]
+        );
+        assert_eq!(
+            ids(range::table_index_range_rust(None, 53, Some(1)).unwrap()),
+            vec![2]
+        );
+        assert_eq!(
+            ids(range::table_range_rust(None, 54).unwrap()),
+            vec![12, 30]
+        );
+    }
+
+    #[test]
+    fn test_raw_region_map() {
+        let map = RegionMap::parse(&[(1, "", "\"b\""), (2, "\"b\"", "\"d\"")]).unwrap();
+        assert!(!map.encoded);
+        assert_eq!(map.locate(b"c").unwrap().id, 2);
+        assert!(map.locate(b"e").is_none());
+        assert!(RegionMap::parse(&[(1, "", "\"c\""), (2, "\"b\"", "\"d\"")]).is_err());
+    }
+}
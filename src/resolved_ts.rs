@@ -0,0 +1,152 @@
+//! Work out the resolved ts of regions from the locks in them, which is also the safe ts
+//! stale reads on the followers can use.
+//!
+//! A region is resolved up to the TSO it's given, unless there is a lock in it, whose
+//! transaction may commit at any ts after its start ts. Only `Put` and `Delete` locks
+//! are tracked, pessimistic locks and the `Lock` records of `SELECT ... FOR UPDATE`
+//! never change a value, so they don't block reads.
+use crate::explain;
+use crate::lock::{Lock, LockType};
+use crate::mvcc::TimeStamp;
+use crate::region::{Region, RegionMap};
+use crate::store;
+use crate::tikv_ctl::{self, MvccHistory};
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedTs {
+    pub region: Region,
+    pub resolved_ts: TimeStamp,
+    /// The raw key and the lock with the min start ts in the region, if it holds back
+    /// the resolved ts.
+    pub blocking: Option<(Vec<u8>, Lock)>,
+}
+
+impl fmt::Display for ResolvedTs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: resolved ts {} ({})",
+            self.region,
+            self.resolved_ts,
+            self.resolved_ts.wall_clock()
+        )?;
+        if let Some((key, lock)) = &self.blocking {
+            write!(
+                f,
+                ", blocked by the transaction {} with primary {}, locking {}",
+                lock.ts,
+                explain::explain_key(lock.primary_key()),
+                explain::explain_key(key)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The resolved ts of every region at the TSO `ts`, with the locks on the raw keys.
+/// Locks outside all the regions are ignored.
+pub fn resolve_regions(
+    regions: &RegionMap,
+    locks: &[(Vec<u8>, Lock)],
+    ts: TimeStamp,
+) -> Vec<ResolvedTs> {
+    let mut result: Vec<ResolvedTs> = regions
+        .regions()
+        .iter()
+        .map(|region| ResolvedTs {
+            region: region.clone(),
+            resolved_ts: ts,
+            blocking: None,
+        })
+        .collect();
+    for (key, lock) in locks {
+        if !matches!(lock.lock_type, LockType::Put | LockType::Delete) || lock.ts >= ts {
+            continue;
+        }
+        let id = match regions.locate(key) {
+            Some(region) => region.id,
+            None => continue,
+        };
+        let resolved = result.iter_mut().find(|r| r.region.id == id).unwrap();
+        if lock.ts < resolved.resolved_ts {
+            resolved.resolved_ts = lock.ts;
+            resolved.blocking = Some((key.clone(), lock.clone()));
+        }
+    }
+    result
+}
+
+/// The locks in the histories `tikv-ctl` or TiDB reports, on the raw keys.
+pub fn locks_of<'a>(histories: impl IntoIterator<Item = &'a MvccHistory>) -> Vec<(Vec<u8>, Lock)> {
+    histories
+        .into_iter()
+        .filter_map(|history| {
+            let lock = history.lock.clone()?;
+            Some((store::user_key_of(&history.key), lock))
+        })
+        .collect()
+}
+
+#[wasm_bindgen]
+impl RegionMap {
+    /// Explain the resolved ts of the regions at the TSO `ts`, with the locks in the
+    /// output of `tikv-ctl scan --show-cf lock`.
+    pub fn explain_resolved_ts(&self, lock_dump: &str, ts: u64) -> Result<String, JsValue> {
+        let histories =
+            tikv_ctl::parse_mvcc_dump(lock_dump).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(resolve_regions(self, &locks_of(&histories), TimeStamp(ts))
+            .iter()
+            .map(|resolved| format!("{}\n", resolved))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::NOW;
+
+    #[test]
+    fn test_resolve_regions() {
+        let regions = RegionMap::parse(&[(1, "", "\"m\""), (2, "\"m\"", "")]).unwrap();
+        let ts = |ms| TimeStamp::compose(NOW - 100 + ms, 0);
+        let lock = |lock_type, start| Lock::new(lock_type, b"a".to_vec(), ts(start), 3000);
+        let locks = vec![
+            (b"a".to_vec(), lock(LockType::Put, 80)),
+            (b"b".to_vec(), lock(LockType::Delete, 70)),
+            (b"n".to_vec(), lock(LockType::Pessimistic, 60)),
+            (b"o".to_vec(), lock(LockType::Lock, 50)),
+            (b"z".to_vec(), lock(LockType::Put, 110)),
+        ];
+        let result = resolve_regions(&regions, &locks, ts(100));
+        assert_eq!(result[0].resolved_ts, ts(70));
+        assert_eq!(result[0].blocking.as_ref().unwrap().0, b"b");
+        // The pessimistic lock, the `Lock` record and the lock newer than the TSO don't block.
+        assert_eq!(result[1].resolved_ts, ts(100));
+        assert_eq!(result[1].blocking, None);
+        assert_eq!(
+            result[0].to_string(),
+            format!(
+                "region 1: -inf .. \"m\": resolved ts {} (2021-06-02 09:25:01.093 UTC), \
+                 blocked by the transaction {} with primary \"a\", locking \"b\"",
+                ts(70),
+                ts(70)
+            )
+        );
+    }
+
+    #[test]
+    fn test_locks_of() {
+        let dump = "key: zk\\000\\000\\000\\000\\000\\000\\000\\370\n\
+                    \tlock cf value: start_ts: 10 primary: \"k\" ttl: 3000\n\
+                    key: zl\\000\\000\\000\\000\\000\\000\\000\\370\n\
+                    \twrite cf value: start_ts: 10 commit_ts: 11\n";
+        let histories = tikv_ctl::parse_mvcc_dump(dump).unwrap();
+        let locks = locks_of(&histories);
+        assert_eq!(locks.len(), 1);
+        assert_eq!(locks[0].0, b"k");
+        assert_eq!(locks[0].1.ts, TimeStamp(10));
+    }
+}
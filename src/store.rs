@@ -0,0 +1,404 @@
+//! An in-memory store of the three column families of TiKV, to see what a read gets.
+//!
+//! The keys are laid out like TiKV does: the lock CF is keyed by the encoded user key,
+//! the write CF by the encoded user key with the commit ts, and the default CF by the
+//! encoded user key with the start ts.
+use crate::chunk;
+use crate::explain;
+use crate::local::DATA_PREFIX;
+use crate::lock::{Lock, LockType};
+use crate::mvcc::{
+    CfName, Key, Modify, TimeStamp, Value, Write, WriteType, CF_DEFAULT, CF_LOCK, CF_WRITE,
+};
+use crate::tikv_ctl::MvccHistory;
+use anyhow::{anyhow, bail};
+use std::collections::BTreeMap;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+/// The name of a column family as `CfName`.
+pub fn cf_name(cf: &str) -> anyhow::Result<CfName> {
+    match cf {
+        "default" => Ok(CF_DEFAULT),
+        "lock" => Ok(CF_LOCK),
+        "write" => Ok(CF_WRITE),
+        _ => bail!("unknown column family {:?}", cf),
+    }
+}
+
+/// The user key of a key printed by tools, which can be the raw key, or memcomparable
+/// encoded, with or without the `z` prefix.
+pub fn user_key_of(key: &[u8]) -> Vec<u8> {
+    let decode = |key: &[u8]| match chunk::decode_bytes_prefix(key) {
+        Some((raw, consumed)) if consumed == key.len() => Some(raw),
+        _ => None,
+    };
+    match key.split_first() {
+        Some((&DATA_PREFIX, rest)) => decode(rest),
+        _ => None,
+    }
+    .or_else(|| decode(key))
+    .unwrap_or_else(|| key.to_vec())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadResult {
+    Found(Value),
+    NotFound,
+    /// The read has to wait for the lock to be resolved.
+    Locked(Box<Lock>),
+}
+
+/// What the write CF says about the transaction started at some ts on a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommitRecord {
+    Committed(TimeStamp),
+    RolledBack,
+    /// Another transaction committed at the start ts of this one.
+    Overlapped(Write),
+    NotFound,
+}
+
+/// The result of a read, along with what were looked at to get it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Read {
+    pub result: ReadResult,
+    pub steps: Vec<String>,
+}
+
+impl fmt::Display for Read {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{}", step)?;
+        }
+        match &self.result {
+            ReadResult::Found(value) => write!(f, "=> {}", explain::escape(value)),
+            ReadResult::NotFound => write!(f, "=> not found"),
+            ReadResult::Locked(lock) => write!(f, "=> locked by {}", lock),
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MvccStore {
+    default: BTreeMap<Vec<u8>, Value>,
+    lock: BTreeMap<Vec<u8>, Value>,
+    write: BTreeMap<Vec<u8>, Value>,
+}
+
+impl MvccStore {
+    fn cf(&self, cf: CfName) -> &BTreeMap<Vec<u8>, Value> {
+        match cf {
+            CF_LOCK => &self.lock,
+            CF_WRITE => &self.write,
+            _ => &self.default,
+        }
+    }
+
+    fn cf_mut(&mut self, cf: CfName) -> &mut BTreeMap<Vec<u8>, Value> {
+        match cf {
+            CF_LOCK => &mut self.lock,
+            CF_WRITE => &mut self.write,
+            _ => &mut self.default,
+        }
+    }
+
+    pub fn apply(&mut self, modifies: impl IntoIterator<Item = Modify>) {
+        for modify in modifies {
+            match modify {
+                Modify::Put(cf, key, value) => {
+                    self.cf_mut(cf).insert(key.into_encoded(), value);
+                }
+                Modify::Delete(cf, key) => {
+                    self.cf_mut(cf).remove(key.as_encoded());
+                }
+            }
+        }
+    }
+
+    /// Load a key value pair dumped from RocksDB, the `z` prefix of data keys is optional.
+    pub fn load(&mut self, cf: CfName, key: &[u8], value: &[u8]) {
+        let key = key.strip_prefix(&[DATA_PREFIX]).unwrap_or(key);
+        self.cf_mut(cf).insert(key.to_vec(), value.to_vec());
+    }
+
+    /// Load what `tikv-ctl` or TiDB reports about the keys.
+    pub fn load_histories<'a>(&mut self, histories: impl IntoIterator<Item = &'a MvccHistory>) {
+        for history in histories {
+            let key = Key::from_raw(&user_key_of(&history.key));
+            if let Some(lock) = &history.lock {
+                self.lock.insert(key.as_encoded().clone(), lock.to_bytes());
+            }
+            for write in &history.writes {
+                let write_key = key.clone().append_ts(write.commit_ts);
+                self.write
+                    .insert(write_key.into_encoded(), write.write.to_bytes());
+            }
+            for value in &history.values {
+                let default_key = key.clone().append_ts(value.start_ts);
+                self.default
+                    .insert(default_key.into_encoded(), value.value.clone());
+            }
+        }
+    }
+
+    /// All the key value pairs in `cf`, with the keys as they are stored.
+    pub fn entries(&self, cf: CfName) -> impl Iterator<Item = (&Vec<u8>, &Value)> {
+        self.cf(cf).iter()
+    }
+
+    pub fn lock(&self, key: &[u8]) -> anyhow::Result<Option<Lock>> {
+        self.lock
+            .get(Key::from_raw(key).as_encoded())
+            .map(|value| Lock::parse_rust(value))
+            .transpose()
+    }
+
+    /// The writes of `key` committed no later than `ts`, the newest first.
+    pub fn writes(&self, key: &[u8], ts: TimeStamp) -> anyhow::Result<Vec<(TimeStamp, Write)>> {
+        let encoded = Key::from_raw(key);
+        let start = encoded.clone().append_ts(ts).into_encoded();
+        let prefix = encoded.as_encoded();
+        self.write
+            .range(start..)
+            .take_while(|(k, _)| k.len() == prefix.len() + 8 && k.starts_with(prefix))
+            .map(|(k, v)| {
+                let commit_ts = Key::from_encoded(k.clone()).decode_ts()?;
+                Ok((commit_ts, Write::parse_rust(v)?))
+            })
+            .collect()
+    }
+
+    /// All the writes of `key`, the newest first.
+    pub fn all_writes(&self, key: &[u8]) -> anyhow::Result<Vec<(TimeStamp, Write)>> {
+        self.writes(key, TimeStamp(u64::MAX))
+    }
+
+    /// Find the commit or rollback record of the transaction started at `start_ts`.
+    pub fn commit_record(&self, key: &[u8], start_ts: TimeStamp) -> anyhow::Result<CommitRecord> {
+        for (commit_ts, write) in self.all_writes(key)? {
+            if write.start_ts == start_ts {
+                return Ok(match write.write_type {
+                    WriteType::Rollback => CommitRecord::RolledBack,
+                    _ => CommitRecord::Committed(commit_ts),
+                });
+            }
+            if commit_ts == start_ts {
+                if write.has_overlapped_rollback {
+                    return Ok(CommitRecord::RolledBack);
+                }
+                return Ok(CommitRecord::Overlapped(write));
+            }
+            if commit_ts < start_ts {
+                break;
+            }
+        }
+        Ok(CommitRecord::NotFound)
+    }
+
+    pub fn default_value(&self, key: &[u8], start_ts: TimeStamp) -> Option<&Value> {
+        let default_key = Key::from_raw(key).append_ts(start_ts);
+        self.default.get(default_key.as_encoded())
+    }
+
+    /// Read `key` at `ts` with snapshot isolation, the way TiKV's point getter does.
+    pub fn get(&self, key: &[u8], ts: TimeStamp) -> anyhow::Result<Read> {
+        let mut steps = Vec::new();
+        if let Some(lock) = self.lock(key)? {
+            let ignored = if lock.ts > ts {
+                Some("it is newer than the read")
+            } else if matches!(lock.lock_type, LockType::Lock | LockType::Pessimistic) {
+                Some("it doesn't change the value")
+            } else if lock.min_commit_ts > ts {
+                Some("its min_commit_ts is newer than the read")
+            } else {
+                None
+            };
+            match ignored {
+                Some(reason) => steps.push(format!("lock: {}, ignored as {}", lock, reason)),
+                None => {
+                    steps.push(format!("lock: {}, blocks the read", lock));
+                    return Ok(Read {
+                        result: ReadResult::Locked(Box::new(lock)),
+                        steps,
+                    });
+                }
+            }
+        }
+        for (commit_ts, write) in self.writes(key, ts)? {
+            let record = format!("write@{}: {}", commit_ts, write);
+            if !write.check_gc_fence_as_latest_version(ts) {
+                let (_, reason) = write.explain_gc_fence(None, ts);
+                steps.push(format!("{}, {}", record, reason));
+                return Ok(Read {
+                    result: ReadResult::NotFound,
+                    steps,
+                });
+            }
+            let result = match write.write_type {
+                WriteType::Lock | WriteType::Rollback => {
+                    steps.push(format!("{}, skipped", record));
+                    continue;
+                }
+                WriteType::Delete => {
+                    steps.push(format!("{}, deleted", record));
+                    ReadResult::NotFound
+                }
+                WriteType::Put => match write.short_value_ref() {
+                    Some(value) => {
+                        steps.push(format!("{}, the short value", record));
+                        ReadResult::Found(value.clone())
+                    }
+                    None => {
+                        let value = self.default_value(key, write.start_ts).ok_or_else(|| {
+                            anyhow!(
+                                "{}: default@{} is missing",
+                                explain::explain_key(key),
+                                write.start_ts
+                            )
+                        })?;
+                        steps.push(format!(
+                            "{}, the value at default@{}",
+                            record, write.start_ts
+                        ));
+                        ReadResult::Found(value.clone())
+                    }
+                },
+            };
+            return Ok(Read { result, steps });
+        }
+        steps.push(format!("no version committed no later than {}", ts));
+        Ok(Read {
+            result: ReadResult::NotFound,
+            steps,
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl MvccStore {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> MvccStore {
+        MvccStore::default()
+    }
+
+    #[wasm_bindgen(js_name = load)]
+    pub fn load_js(&mut self, cf: &str, key: &[u8], value: &[u8]) -> Result<(), JsValue> {
+        let cf = cf_name(cf).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.load(cf, key, value);
+        Ok(())
+    }
+
+    /// Load the output of `tikv-ctl mvcc` or `tikv-ctl scan`.
+    pub fn load_tikv_ctl(&mut self, text: &str) -> Result<(), JsValue> {
+        let histories = crate::tikv_ctl::parse_mvcc_dump(text)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.load_histories(&histories);
+        Ok(())
+    }
+
+    /// Explain how reading `key` at `ts` goes.
+    pub fn explain_get(&self, key: &[u8], ts: u64) -> Result<String, JsValue> {
+        self.get(key, TimeStamp(ts))
+            .map(|read| read.to_string())
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db_to_kv;
+
+    fn write(write_type: WriteType, start_ts: u64) -> Write {
+        Write::new(write_type, TimeStamp(start_ts), None)
+    }
+
+    fn read(store: &MvccStore, key: &[u8], ts: u64) -> ReadResult {
+        store.get(key, TimeStamp(ts)).unwrap().result
+    }
+
+    fn put_write(store: &mut MvccStore, key: &[u8], commit_ts: u64, write: Write) {
+        store.apply(vec![Modify::Put(
+            CF_WRITE,
+            Key::from_raw(key).append_ts(TimeStamp(commit_ts)),
+            write.to_bytes(),
+        )]);
+    }
+
+    #[test]
+    fn test_get() {
+        let key = db_to_kv::encode_record_key_rust(None, 53, 1).unwrap();
+        let mut store = MvccStore::new();
+        let short_put = Write::new(WriteType::Put, TimeStamp(10), Some(b"v1".to_vec()));
+        put_write(&mut store, &key, 11, short_put);
+        put_write(&mut store, &key, 21, write(WriteType::Put, 20));
+        store.apply(vec![Modify::Put(
+            CF_DEFAULT,
+            Key::from_raw(&key).append_ts(TimeStamp(20)),
+            vec![b'x'; 300],
+        )]);
+        put_write(&mut store, &key, 25, write(WriteType::Rollback, 25));
+        put_write(&mut store, &key, 31, write(WriteType::Lock, 30));
+        put_write(&mut store, &key, 41, write(WriteType::Delete, 40));
+
+        assert_eq!(read(&store, &key, 5), ReadResult::NotFound);
+        assert_eq!(read(&store, &key, 11), ReadResult::Found(b"v1".to_vec()));
+        assert_eq!(read(&store, &key, 21), ReadResult::Found(vec![b'x'; 300]));
+        assert_eq!(read(&store, &key, 40), ReadResult::Found(vec![b'x'; 300]));
+        assert_eq!(read(&store, &key, 41), ReadResult::NotFound);
+        let explanation = store.get(&key, TimeStamp(35)).unwrap().to_string();
+        assert!(explanation.starts_with(
+            "write@31: Lock, start_ts: 30, skipped\n\
+             write@25: Rollback, start_ts: 25, skipped\n\
+             write@21: Put, start_ts: 20, the value at default@20\n\
+             => \"xxx"
+        ));
+
+        // A pessimistic lock doesn't block, a prewrite lock does.
+        let lock = Lock::new(LockType::Pessimistic, key.clone(), TimeStamp(50), 3000);
+        let lock_key = Key::from_raw(&key).into_encoded();
+        let data_key = [&[DATA_PREFIX][..], &lock_key].concat();
+        store.load(CF_LOCK, &data_key, &lock.to_bytes());
+        assert_eq!(read(&store, &key, 60), ReadResult::NotFound);
+        let lock = Lock::new(LockType::Put, key.clone(), TimeStamp(50), 3000);
+        store.load(CF_LOCK, &lock_key, &lock.to_bytes());
+        assert!(matches!(read(&store, &key, 60), ReadResult::Locked(_)));
+        assert_eq!(read(&store, &key, 49), ReadResult::NotFound);
+
+        // The value of a removed default record is an error.
+        store.apply(vec![Modify::Delete(
+            CF_DEFAULT,
+            Key::from_raw(&key).append_ts(TimeStamp(20)),
+        )]);
+        assert!(store.get(&key, TimeStamp(21)).is_err());
+    }
+
+    #[test]
+    fn test_gc_fence() {
+        let key = b"k";
+        let mut store = MvccStore::new();
+        let mut write = Write::new(WriteType::Put, TimeStamp(10), Some(b"v".to_vec()));
+        write.gc_fence = Some(TimeStamp(30));
+        put_write(&mut store, key, 11, write);
+        assert_eq!(read(&store, key, 20), ReadResult::Found(b"v".to_vec()));
+        assert_eq!(read(&store, key, 30), ReadResult::NotFound);
+    }
+
+    #[test]
+    fn test_load_histories() {
+        let text = "key: zk\\000\\000\\000\\000\\000\\000\\000\\370\n\
+                    \twrite cf value: start_ts: 10 commit_ts: 11 short_value: \"v\"\n";
+        let histories = crate::tikv_ctl::parse_mvcc_dump(text).unwrap();
+        let mut store = MvccStore::new();
+        store.load_histories(&histories);
+        assert_eq!(
+            store.get(b"k", TimeStamp(11)).unwrap().result,
+            ReadResult::Found(b"v".to_vec())
+        );
+        assert_eq!(user_key_of(b"k"), b"k");
+        assert_eq!(cf_name("write").unwrap(), CF_WRITE);
+        assert!(cf_name("raft").is_err());
+    }
+}
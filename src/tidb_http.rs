@@ -0,0 +1,285 @@
+//! Read the responses of the MVCC APIs on TiDB's status port, like
+//! `curl http://tidb:10080/mvcc/key/test/t/1` and `/mvcc/index/test/t/idx/1?a=abc`.
+//!
+//! The key is in hex, the keys and values inside are base64 encoded and the
+//! lock and write types are the numbers of `kvrpcpb::Op`.
+use crate::input;
+use crate::lock::{Lock, LockType};
+use crate::mvcc::{TimeStamp, Write, WriteType};
+use crate::tikv_ctl::{MvccHistory, MvccValue, MvccWrite};
+use anyhow::{anyhow, bail};
+use serde::Deserialize;
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+const OP_PUT: u8 = 0;
+const OP_DEL: u8 = 1;
+const OP_LOCK: u8 = 2;
+const OP_ROLLBACK: u8 = 3;
+const OP_INSERT: u8 = 4;
+const OP_PESSIMISTIC_LOCK: u8 = 5;
+
+#[derive(Debug, Deserialize)]
+struct MvccKv {
+    key: String,
+    #[serde(default)]
+    region_id: u64,
+    value: MvccResponse,
+}
+
+#[derive(Debug, Deserialize)]
+struct MvccResponse {
+    #[serde(default)]
+    error: String,
+    #[serde(default)]
+    info: Option<MvccInfo>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct MvccInfo {
+    #[serde(default)]
+    lock: Option<JsonLock>,
+    #[serde(default)]
+    writes: Vec<JsonWrite>,
+    #[serde(default)]
+    values: Vec<JsonValue>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonLock {
+    #[serde(default, rename = "type")]
+    op: u8,
+    #[serde(default)]
+    start_ts: u64,
+    #[serde(default)]
+    primary: String,
+    #[serde(default)]
+    short_value: Option<String>,
+    #[serde(default)]
+    ttl: u64,
+    #[serde(default)]
+    for_update_ts: u64,
+    #[serde(default)]
+    txn_size: u64,
+    #[serde(default)]
+    use_async_commit: bool,
+    #[serde(default)]
+    secondaries: Vec<String>,
+    #[serde(default)]
+    rollback_ts: Vec<u64>,
+    #[serde(default)]
+    min_commit_ts: u64,
+    #[serde(default)]
+    last_change_ts: u64,
+    #[serde(default)]
+    versions_to_last_change: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonWrite {
+    #[serde(default, rename = "type")]
+    op: u8,
+    #[serde(default)]
+    start_ts: u64,
+    #[serde(default)]
+    commit_ts: u64,
+    #[serde(default)]
+    short_value: Option<String>,
+    #[serde(default)]
+    has_overlapped_rollback: bool,
+    #[serde(default)]
+    has_gc_fence: bool,
+    #[serde(default)]
+    gc_fence: u64,
+    #[serde(default)]
+    last_change_ts: u64,
+    #[serde(default)]
+    versions_to_last_change: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonValue {
+    #[serde(default)]
+    start_ts: u64,
+    #[serde(default)]
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Response {
+    One(Box<MvccKv>),
+    Many(Vec<MvccKv>),
+}
+
+/// The MVCC info of a key TiDB reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TidbMvcc {
+    pub region_id: u64,
+    /// The key is the raw key, ie. not memcomparable encoded.
+    pub history: MvccHistory,
+}
+
+impl fmt::Display for TidbMvcc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "region {}", self.region_id)?;
+        write!(f, "{}", self.history)
+    }
+}
+
+impl JsonLock {
+    fn decode(self) -> anyhow::Result<Lock> {
+        let lock_type = match self.op {
+            OP_PUT | OP_INSERT => LockType::Put,
+            OP_DEL => LockType::Delete,
+            OP_LOCK => LockType::Lock,
+            OP_PESSIMISTIC_LOCK => LockType::Pessimistic,
+            op => bail!("unknown lock type {}", op),
+        };
+        let primary = input::decode_base64(&self.primary)?;
+        let mut lock = Lock::new(lock_type, primary, self.start_ts.into(), self.ttl);
+        if let Some(value) = &self.short_value {
+            lock = lock.with_short_value(input::decode_base64(value)?);
+        }
+        if self.use_async_commit {
+            let secondaries = self
+                .secondaries
+                .iter()
+                .map(|key| input::decode_base64(key))
+                .collect::<anyhow::Result<_>>()?;
+            lock = lock.with_async_commit(secondaries);
+        }
+        let mut lock = lock.with_rollback_ts(self.rollback_ts.into_iter().map(TimeStamp).collect());
+        lock.for_update_ts = self.for_update_ts.into();
+        lock.txn_size = self.txn_size;
+        lock.min_commit_ts = self.min_commit_ts.into();
+        lock.last_change_ts = self.last_change_ts.into();
+        lock.versions_to_last_change = self.versions_to_last_change;
+        Ok(lock)
+    }
+}
+
+impl JsonWrite {
+    fn decode(self) -> anyhow::Result<MvccWrite> {
+        let write_type = match self.op {
+            OP_PUT => WriteType::Put,
+            OP_DEL => WriteType::Delete,
+            OP_LOCK => WriteType::Lock,
+            OP_ROLLBACK => WriteType::Rollback,
+            op => bail!("unknown write type {}", op),
+        };
+        let short_value = self
+            .short_value
+            .as_deref()
+            .map(input::decode_base64)
+            .transpose()?;
+        let mut write = Write::new(write_type, self.start_ts.into(), short_value);
+        write.has_overlapped_rollback = self.has_overlapped_rollback;
+        if self.has_gc_fence {
+            write.gc_fence = Some(self.gc_fence.into());
+        }
+        write.last_change_ts = self.last_change_ts.into();
+        write.versions_to_last_change = self.versions_to_last_change;
+        Ok(MvccWrite {
+            commit_ts: self.commit_ts.into(),
+            write,
+        })
+    }
+}
+
+impl MvccKv {
+    fn decode(self) -> anyhow::Result<TidbMvcc> {
+        if !self.value.error.is_empty() {
+            bail!("{}", self.value.error);
+        }
+        let key = hex::decode(&self.key)
+            .or_else(|_| input::decode_base64(&self.key))
+            .map_err(|_| anyhow!("cannot decode key {:?}", self.key))?;
+        let info = self.value.info.unwrap_or_default();
+        let history = MvccHistory {
+            key,
+            lock: info.lock.map(JsonLock::decode).transpose()?,
+            writes: info
+                .writes
+                .into_iter()
+                .map(JsonWrite::decode)
+                .collect::<anyhow::Result<_>>()?,
+            values: info
+                .values
+                .into_iter()
+                .map(|value| {
+                    Ok(MvccValue {
+                        start_ts: value.start_ts.into(),
+                        value: input::decode_base64(&value.value)?,
+                    })
+                })
+                .collect::<anyhow::Result<_>>()?,
+        };
+        Ok(TidbMvcc {
+            region_id: self.region_id,
+            history,
+        })
+    }
+}
+
+/// Parse the response of `/mvcc/key` or `/mvcc/index`, which is one key or a list of them.
+pub fn parse_tidb_mvcc(json: &str) -> anyhow::Result<Vec<TidbMvcc>> {
+    let keys = match serde_json::from_str(json)? {
+        Response::One(key) => vec![*key],
+        Response::Many(keys) => keys,
+    };
+    keys.into_iter().map(MvccKv::decode).collect()
+}
+
+/// Explain a pasted response of the MVCC APIs of TiDB.
+#[wasm_bindgen]
+pub fn explain_tidb_mvcc(json: &str) -> Result<String, JsValue> {
+    parse_tidb_mvcc(json)
+        .map(|keys| keys.iter().map(ToString::to_string).collect())
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = include_str!("../tests/fixtures/tidb-mvcc-key.json");
+    const INDEX: &str = include_str!("../tests/fixtures/tidb-mvcc-index.json");
+
+    #[test]
+    fn test_parse_mvcc_key() {
+        let keys = parse_tidb_mvcc(KEY).unwrap();
+        assert_eq!(keys.len(), 1);
+        let history = &keys[0].history;
+        let lock = history.lock.as_ref().unwrap();
+        assert_eq!(lock.lock_type, LockType::Pessimistic);
+        assert_eq!(crate::explain::explain_key(lock.primary_key()), "t53_r1");
+        assert_eq!(history.writes[1].write.write_type, WriteType::Rollback);
+        assert_eq!(history.values[0].value.len(), 300);
+        let text = keys[0].to_string();
+        assert!(text.starts_with(
+            "region 2\nkey: t53_r1\n  \
+             lock: Pessimistic, primary: t53_r1, start_ts: 424659320104550410, ttl: 20000, \
+             for_update_ts: 424659320104550411\n  \
+             write@424659320104550402: Put, start_ts: 424659320104550401, short_value: "
+        ));
+        assert!(text.contains("write@424659320104550399: Rollback, start_ts: 424659320104550399\n"));
+    }
+
+    #[test]
+    fn test_parse_mvcc_index() {
+        let list = format!("[{}]", INDEX);
+        let keys = parse_tidb_mvcc(&list).unwrap();
+        assert_eq!(
+            keys[0].to_string(),
+            "region 2\nkey: t53_i2(\"abc\")\n  \
+             write@424659320104550402: Put, start_ts: 424659320104550401, \
+             short_value: \"\\x00\\x00\\x00\\x00\\x00\\x00\\x00\\x01\"\n"
+        );
+        let error = r#"{"key": "74", "region_id": 2, "value": {"error": "txn not found"}}"#;
+        assert_eq!(
+            parse_tidb_mvcc(error).unwrap_err().to_string(),
+            "txn not found"
+        );
+    }
+}
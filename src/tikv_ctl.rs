@@ -0,0 +1,323 @@
+//! Read what `tikv-ctl mvcc` and `tikv-ctl scan` print with `--show-cf lock,write,default`.
+//!
+//! ```text
+//! key: zt\200\000\000\000\000\000\000\3775_r\200\000\000\000\000\377\000\000\001\000\000\000\000\000\372
+//!      lock cf value: type: Put start_ts: 12 primary: "t\200..." ttl: 3000
+//!      write cf value: start_ts: 10 commit_ts: 11 short_value: "abc"
+//!      default cf value: start_ts: 8 value: "..."
+//! ```
+//!
+//! The values are the protobuf text format of `kvrpcpb::MvccLock`, `MvccWrite` and
+//! `MvccValue`, where the fields having the default value are left out, so a write
+//! without `type` is a `Put`.
+use crate::explain;
+use crate::lock::{Lock, LockType};
+use crate::mvcc::{Key, TimeStamp, Value, Write, WriteType};
+use crate::store;
+use anyhow::{anyhow, bail};
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MvccWrite {
+    pub commit_ts: TimeStamp,
+    pub write: Write,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MvccValue {
+    pub start_ts: TimeStamp,
+    pub value: Value,
+}
+
+/// Everything `tikv-ctl` prints about a key.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MvccHistory {
+    /// The key as printed, ie. with the `z` prefix and memcomparable encoded.
+    pub key: Vec<u8>,
+    pub lock: Option<Lock>,
+    pub writes: Vec<MvccWrite>,
+    pub values: Vec<MvccValue>,
+}
+
+#[cfg(test)]
+impl MvccHistory {
+    /// The history of the key `k` with the `(commit_ts, write)` records, for tests.
+    pub(crate) fn from_writes(writes: &[(u64, Write)]) -> MvccHistory {
+        MvccHistory {
+            key: b"k".to_vec(),
+            writes: writes
+                .iter()
+                .map(|(commit_ts, write)| MvccWrite {
+                    commit_ts: TimeStamp(*commit_ts),
+                    write: write.clone(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for MvccHistory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "key: {}", explain::explain_key(&self.key))?;
+        if let Some(lock) = &self.lock {
+            write!(f, "  lock: {}", lock)?;
+            let key = Key::from_raw(&store::user_key_of(&self.key));
+            if lock.is_flashback_lock(key.as_encoded()) {
+                write!(f, " (flashback)")?;
+            }
+            writeln!(f)?;
+        }
+        for write in &self.writes {
+            writeln!(f, "  write@{}: {}", write.commit_ts, write.write)?;
+        }
+        for value in &self.values {
+            writeln!(
+                f,
+                "  default@{}: {}",
+                value.start_ts,
+                explain::escape(&value.value)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The `name: value` pairs of a message in protobuf text format, strings are unescaped.
+struct TextMessage<'a> {
+    fields: Vec<(&'a str, Vec<u8>)>,
+}
+
+impl<'a> TextMessage<'a> {
+    fn parse(mut s: &'a str) -> anyhow::Result<TextMessage<'a>> {
+        let mut fields = Vec::new();
+        loop {
+            s = s.trim_start();
+            if s.is_empty() {
+                return Ok(TextMessage { fields });
+            }
+            let (name, rest) = s
+                .split_once(':')
+                .ok_or_else(|| anyhow!("expect `name: value` at {:?}", s))?;
+            let rest = rest.trim_start();
+            let (value, rest) = if rest.starts_with('"') {
+                let end = string_end(rest).ok_or_else(|| anyhow!("unterminated string"))?;
+                (explain::unescape(&rest[..end])?, &rest[end..])
+            } else {
+                let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                (rest.as_bytes()[..end].to_vec(), &rest[end..])
+            };
+            fields.push((name.trim(), value));
+            s = rest;
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&[u8]> {
+        self.fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    fn get_all(&self, name: &str) -> Vec<&[u8]> {
+        self.fields
+            .iter()
+            .filter(|(field, _)| *field == name)
+            .map(|(_, value)| value.as_slice())
+            .collect()
+    }
+
+    fn get_u64(&self, name: &str) -> anyhow::Result<u64> {
+        match self.get(name) {
+            Some(value) => std::str::from_utf8(value)?
+                .parse()
+                .map_err(|_| anyhow!("invalid {}: {}", name, explain::escape(value))),
+            None => Ok(0),
+        }
+    }
+
+    fn get_ts(&self, name: &str) -> anyhow::Result<TimeStamp> {
+        self.get_u64(name).map(TimeStamp)
+    }
+
+    fn get_bool(&self, name: &str) -> bool {
+        self.get(name) == Some(b"true")
+    }
+}
+
+/// The end of the quoted string at the beginning of `s`, after the closing quote.
+fn string_end(s: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in s.char_indices().skip(1) {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return Some(i + 1),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+fn parse_lock(s: &str) -> anyhow::Result<Lock> {
+    let message = TextMessage::parse(s)?;
+    let lock_type = match message.get("type") {
+        None | Some(b"Put") | Some(b"Insert") => LockType::Put,
+        Some(b"Del") => LockType::Delete,
+        Some(b"Lock") => LockType::Lock,
+        Some(b"PessimisticLock") => LockType::Pessimistic,
+        Some(other) => bail!("unknown lock type {}", explain::escape(other)),
+    };
+    let primary = message.get("primary").unwrap_or_default().to_vec();
+    let mut lock = Lock::new(
+        lock_type,
+        primary,
+        message.get_ts("start_ts")?,
+        message.get_u64("ttl")?,
+    );
+    if let Some(value) = message.get("short_value") {
+        lock = lock.with_short_value(value.to_vec());
+    }
+    if message.get_bool("use_async_commit") {
+        let secondaries = message
+            .get_all("secondaries")
+            .into_iter()
+            .map(<[u8]>::to_vec)
+            .collect();
+        lock = lock.with_async_commit(secondaries);
+    }
+    let rollback_ts = message
+        .get_all("rollback_ts")
+        .into_iter()
+        .map(|ts| Ok(TimeStamp(std::str::from_utf8(ts)?.parse()?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    lock = lock.with_rollback_ts(rollback_ts);
+    lock.for_update_ts = message.get_ts("for_update_ts")?;
+    lock.txn_size = message.get_u64("txn_size")?;
+    lock.min_commit_ts = message.get_ts("min_commit_ts")?;
+    lock.last_change_ts = message.get_ts("last_change_ts")?;
+    lock.versions_to_last_change = message.get_u64("versions_to_last_change")?;
+    Ok(lock)
+}
+
+fn parse_write(s: &str) -> anyhow::Result<MvccWrite> {
+    let message = TextMessage::parse(s)?;
+    let write_type = match message.get("type") {
+        None | Some(b"Put") => WriteType::Put,
+        Some(b"Del") => WriteType::Delete,
+        Some(b"Lock") => WriteType::Lock,
+        Some(b"Rollback") => WriteType::Rollback,
+        Some(other) => bail!("unknown write type {}", explain::escape(other)),
+    };
+    let short_value = message.get("short_value").map(<[u8]>::to_vec);
+    let mut write = Write::new(write_type, message.get_ts("start_ts")?, short_value);
+    write.has_overlapped_rollback = message.get_bool("has_overlapped_rollback");
+    if message.get_bool("has_gc_fence") {
+        write.gc_fence = Some(message.get_ts("gc_fence")?);
+    }
+    write.last_change_ts = message.get_ts("last_change_ts")?;
+    write.versions_to_last_change = message.get_u64("versions_to_last_change")?;
+    Ok(MvccWrite {
+        commit_ts: message.get_ts("commit_ts")?,
+        write,
+    })
+}
+
+fn parse_value(s: &str) -> anyhow::Result<MvccValue> {
+    let message = TextMessage::parse(s)?;
+    Ok(MvccValue {
+        start_ts: message.get_ts("start_ts")?,
+        value: message.get("value").unwrap_or_default().to_vec(),
+    })
+}
+
+/// Parse the output of `tikv-ctl mvcc` or `tikv-ctl scan` into the history of each key.
+pub fn parse_mvcc_dump(text: &str) -> anyhow::Result<Vec<MvccHistory>> {
+    let mut result: Vec<MvccHistory> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let context = || format!("line {}: {}", i + 1, line);
+        if let Some(key) = line.strip_prefix("key:") {
+            let key = explain::unescape(key.trim()).map_err(|e| anyhow!("{}: {}", context(), e))?;
+            result.push(MvccHistory {
+                key,
+                ..Default::default()
+            });
+            continue;
+        }
+        let history = result
+            .last_mut()
+            .ok_or_else(|| anyhow!("{}: value before any key", context()))?;
+        let parsed = if let Some(lock) = line.strip_prefix("lock cf value:") {
+            parse_lock(lock).map(|lock| history.lock = Some(lock))
+        } else if let Some(write) = line.strip_prefix("write cf value:") {
+            parse_write(write).map(|write| history.writes.push(write))
+        } else if let Some(value) = line.strip_prefix("default cf value:") {
+            parse_value(value).map(|value| history.values.push(value))
+        } else {
+            Err(anyhow!("unknown entry"))
+        };
+        parsed.map_err(|e| anyhow!("{}: {}", context(), e))?;
+    }
+    Ok(result)
+}
+
+/// Explain every entry of a `tikv-ctl mvcc` or `tikv-ctl scan` output.
+#[wasm_bindgen]
+pub fn explain_tikv_ctl_mvcc(text: &str) -> Result<String, JsValue> {
+    parse_mvcc_dump(text)
+        .map(|histories| histories.iter().map(ToString::to_string).collect())
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = r#"key: zt\200\000\000\000\000\000\000\3775_r\200\000\000\000\000\377\000\000\001\000\000\000\000\000\372
+	 lock cf value: type: PessimisticLock start_ts: 424659320104550410 primary: "t\200\000\000\000\000\000\0005_r\200\000\000\000\000\000\000\001" ttl: 20000 for_update_ts: 424659320104550411
+	 write cf value: start_ts: 424659320104550401 commit_ts: 424659320104550402 short_value: "\200\000\002\000\000\000\001\002\005\000abc"
+	 write cf value: type: Rollback start_ts: 424659320104550399 commit_ts: 424659320104550399
+key: zmDB:29\000\000\377\000\374\000\000\000\000\000\000\377\000H\000\000\000\000\000\000\371
+	 write cf value: type: Del start_ts: 399650105239273474 commit_ts: 399650105239273475 has_gc_fence: true gc_fence: 399650105239273480
+	 default cf value: start_ts: 399650105199951882 value: "long value"
+"#;
+
+    #[test]
+    fn test_parse_mvcc_dump() {
+        let histories = parse_mvcc_dump(DUMP).unwrap();
+        assert_eq!(histories.len(), 2);
+        let row = &histories[0];
+        assert_eq!(explain::explain_key(&row.key), "data(encoded(t53_r1))");
+        let lock = row.lock.as_ref().unwrap();
+        assert_eq!(lock.lock_type, LockType::Pessimistic);
+        assert_eq!(lock.for_update_ts, TimeStamp(424659320104550411));
+        assert_eq!(explain::explain_key(lock.primary_key()), "t53_r1");
+        assert_eq!(row.writes.len(), 2);
+        assert_eq!(row.writes[0].write.write_type, WriteType::Put);
+        assert_eq!(row.writes[0].commit_ts, TimeStamp(424659320104550402));
+        assert_eq!(row.writes[1].write.write_type, WriteType::Rollback);
+
+        let meta = &histories[1];
+        assert_eq!(meta.writes[0].write.write_type, WriteType::Delete);
+        assert_eq!(
+            meta.writes[0].write.gc_fence,
+            Some(TimeStamp(399650105239273480))
+        );
+        assert_eq!(meta.values[0].value, b"long value");
+
+        let text = histories[1].to_string();
+        assert_eq!(
+            text,
+            "key: data(encoded(meta(\"DB:29\").H))\n  \
+             write@399650105239273475: Delete, start_ts: 399650105239273474, gc_fence: 399650105239273480\n  \
+             default@399650105199951882: \"long value\"\n"
+        );
+
+        assert!(parse_mvcc_dump("write cf value: start_ts: 1").is_err());
+        assert!(parse_mvcc_dump("key: a\nwrite cf value: type: Oops").is_err());
+    }
+}
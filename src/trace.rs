@@ -0,0 +1,403 @@
+//! Record how some bytes are parsed, as a tree of fields.
+//!
+//! Each node covers a span of the bytes being parsed, tells which field it is,
+//! how the field is encoded, and what the field's value is decoded into.
+//! A node made of several fields has them as children, eg. a short value in a
+//! `Write` consists of a flag, a length and the value itself.
+//! Some fields are encodings of other bytes (eg. a memcomparable encoded key),
+//! the trace of the decoded bytes is attached as `inner`, with offsets in the decoded bytes.
+use crate::endian;
+use crate::varint;
+use anyhow::{anyhow, bail};
+use serde::Serialize;
+use std::fmt::Display;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum EncodeMethod {
+    EnumFlag,
+    SingleByte,
+    Bytes,
+    BigEndian,
+    LittleEndian,
+    VarInt,
+    /// Fixed bytes marking what comes next, eg. the `t` of a table key.
+    Literal,
+    /// An i64 in big endian with the sign bit flipped.
+    ComparableI64,
+    /// An u64 in big endian with all bits flipped, used by timestamps in keys.
+    DescendingU64,
+    /// Bytes padded into groups of 8 followed by a marker byte.
+    Memcomparable,
+    /// A datum in TiDB's memcomparable codec, a flag followed by the payload.
+    Datum,
+    /// Made of several fields, see the children.
+    Composite,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct ParsingTrace {
+    pub start: usize,
+    pub width: usize,
+    #[wasm_bindgen(skip)]
+    pub field: String,
+    pub encoded_in: EncodeMethod,
+    #[wasm_bindgen(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[wasm_bindgen(skip)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<ParsingTrace>,
+    #[wasm_bindgen(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inner: Option<Box<ParsingTrace>>,
+}
+
+impl ParsingTrace {
+    pub fn leaf(start: usize, width: usize, field: &str, encoded_in: EncodeMethod) -> Self {
+        ParsingTrace {
+            start,
+            width,
+            field: field.to_string(),
+            encoded_in,
+            value: None,
+            children: Vec::new(),
+            inner: None,
+        }
+    }
+
+    /// A node covering all of `children`, which should be sorted and not empty.
+    pub fn node(field: &str, children: Vec<ParsingTrace>) -> Self {
+        let start = children.first().map_or(0, |c| c.start);
+        let end = children.last().map_or(0, |c| c.end());
+        ParsingTrace {
+            start,
+            width: end - start,
+            field: field.to_string(),
+            encoded_in: EncodeMethod::Composite,
+            value: None,
+            children,
+            inner: None,
+        }
+    }
+
+    pub fn with_value(mut self, value: impl Display) -> Self {
+        self.value = Some(value.to_string());
+        self
+    }
+
+    pub fn with_inner(mut self, inner: ParsingTrace) -> Self {
+        self.inner = Some(Box::new(inner));
+        self
+    }
+
+    pub fn end(&self) -> usize {
+        self.start + self.width
+    }
+
+    /// Move this node and all its children `offset` bytes later.
+    /// `inner` is left untouched since it is relative to other bytes.
+    pub fn shift(mut self, offset: usize) -> Self {
+        self.start += offset;
+        self.children = self
+            .children
+            .into_iter()
+            .map(|child| child.shift(offset))
+            .collect();
+        self
+    }
+
+    /// Visit every node in the tree (including `inner` ones) in depth first order.
+    pub fn walk<'a>(&'a self, f: &mut impl FnMut(&'a ParsingTrace, usize)) {
+        self.walk_with_depth(0, f)
+    }
+
+    /// The nodes without children, ie. the fields actually read from the bytes,
+    /// each with the path from the root like `short_value.length`.
+    /// `inner` traces are not included since they describe other bytes.
+    pub fn leaves(&self) -> Vec<(String, &ParsingTrace)> {
+        let mut result = Vec::new();
+        for child in &self.children {
+            child.collect_leaves("", &mut result);
+        }
+        result
+    }
+
+    fn collect_leaves<'a>(&'a self, parent: &str, result: &mut Vec<(String, &'a ParsingTrace)>) {
+        let path = if parent.is_empty() {
+            self.field.clone()
+        } else {
+            format!("{}.{}", parent, self.field)
+        };
+        if self.children.is_empty() {
+            result.push((path, self));
+        } else {
+            for child in &self.children {
+                child.collect_leaves(&path, result);
+            }
+        }
+    }
+
+    fn walk_with_depth<'a>(&'a self, depth: usize, f: &mut impl FnMut(&'a ParsingTrace, usize)) {
+        f(self, depth);
+        for child in &self.children {
+            child.walk_with_depth(depth + 1, f);
+        }
+        if let Some(inner) = &self.inner {
+            inner.walk_with_depth(depth + 1, f);
+        }
+    }
+}
+
+impl Default for ParsingTrace {
+    fn default() -> Self {
+        ParsingTrace::leaf(0, 0, "", EncodeMethod::Composite)
+    }
+}
+
+#[wasm_bindgen]
+impl ParsingTrace {
+    #[wasm_bindgen(getter)]
+    pub fn field(&self) -> String {
+        self.field.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn value(&self) -> Option<String> {
+        self.value.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn children(&self) -> JsValue {
+        crate::utils::to_js_value(&self.children)
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn inner(&self) -> Option<ParsingTrace> {
+        self.inner.as_deref().cloned()
+    }
+
+    #[wasm_bindgen(js_name = "toJSON")]
+    pub fn to_json(&self) -> JsValue {
+        crate::utils::to_js_value(self)
+    }
+}
+
+/// Reads fields one by one from some bytes, building a `ParsingTrace` tree on the way.
+pub(crate) struct TracingReader<'a> {
+    b: &'a [u8],
+    offset: usize,
+    /// The composite nodes being read, with their start offset, field name and finished children.
+    stack: Vec<(usize, String, Vec<ParsingTrace>)>,
+    nodes: Vec<ParsingTrace>,
+}
+
+impl<'a> TracingReader<'a> {
+    pub(crate) fn new(b: &'a [u8]) -> Self {
+        TracingReader {
+            b,
+            offset: 0,
+            stack: Vec::new(),
+            nodes: Vec::new(),
+        }
+    }
+
+    pub(crate) fn peek(&self) -> Option<u8> {
+        self.b.get(self.offset).cloned()
+    }
+
+    fn current(&mut self) -> &mut Vec<ParsingTrace> {
+        match self.stack.last_mut() {
+            Some((_, _, children)) => children,
+            None => &mut self.nodes,
+        }
+    }
+
+    /// Add a node built elsewhere, with offsets relative to the current offset.
+    pub(crate) fn push(&mut self, node: ParsingTrace) {
+        let offset = self.offset;
+        self.offset += node.width;
+        let node = node.shift(offset);
+        self.current().push(node);
+    }
+
+    fn advance(&mut self, width: usize, field: &str, encoded_in: EncodeMethod) {
+        let node = ParsingTrace::leaf(self.offset, width, field, encoded_in);
+        self.offset += width;
+        self.current().push(node);
+    }
+
+    /// Set the decoded value of the field just read.
+    pub(crate) fn annotate(&mut self, value: impl Display) {
+        if let Some(node) = self.current().last_mut() {
+            node.value = Some(value.to_string());
+        }
+    }
+
+    /// Start reading a field made of several fields.
+    pub(crate) fn begin(&mut self, field: &str) {
+        self.stack
+            .push((self.offset, field.to_string(), Vec::new()));
+    }
+
+    /// Finish the field started by the last `begin`.
+    pub(crate) fn end(&mut self, value: impl Display) {
+        self.end_as(EncodeMethod::Composite, value)
+    }
+
+    /// Finish the field started by the last `begin`, which is encoded in `encoded_in`.
+    pub(crate) fn end_as(&mut self, encoded_in: EncodeMethod, value: impl Display) {
+        if let Some((start, field, children)) = self.stack.pop() {
+            let node = ParsingTrace {
+                start,
+                width: self.offset - start,
+                field,
+                encoded_in,
+                value: Some(value.to_string()),
+                children,
+                inner: None,
+            };
+            self.current().push(node);
+        }
+    }
+
+    /// The root node covering everything read.
+    pub(crate) fn finish(mut self, field: &str, value: impl Display) -> ParsingTrace {
+        while !self.stack.is_empty() {
+            self.end("");
+        }
+        ParsingTrace {
+            start: 0,
+            width: self.offset,
+            field: field.to_string(),
+            encoded_in: EncodeMethod::Composite,
+            value: Some(value.to_string()),
+            children: self.nodes,
+            inner: None,
+        }
+    }
+
+    pub(crate) fn read_u8(&mut self, field: &str, encoded_in: EncodeMethod) -> anyhow::Result<u8> {
+        let result = self
+            .peek()
+            .ok_or_else(|| anyhow!("no byte left for {}", field))?;
+        self.advance(1, field, encoded_in);
+        Ok(result)
+    }
+
+    pub(crate) fn read_bytes(&mut self, len: usize, field: &str) -> anyhow::Result<&'a [u8]> {
+        self.read_fixed(len, field, EncodeMethod::Bytes)
+    }
+
+    pub(crate) fn read_fixed(
+        &mut self,
+        len: usize,
+        field: &str,
+        encoded_in: EncodeMethod,
+    ) -> anyhow::Result<&'a [u8]> {
+        let b: &'a [u8] = self.b;
+        let result = b
+            .get(self.offset..self.offset.saturating_add(len))
+            .ok_or_else(|| {
+                anyhow!(
+                    "content len [{}] shorter than {} len [{}]",
+                    self.b.len() - self.offset,
+                    field,
+                    len
+                )
+            })?;
+        self.advance(len, field, encoded_in);
+        Ok(result)
+    }
+
+    /// The bytes not read yet.
+    pub(crate) fn rest(&self) -> &'a [u8] {
+        let b: &'a [u8] = self.b;
+        &b[self.offset..]
+    }
+
+    pub(crate) fn read_var_u64(&mut self, field: &str) -> anyhow::Result<u64> {
+        let (result, width) = varint::try_decode_u64(&self.b[self.offset..])
+            .ok_or_else(|| anyhow!("invalid varint for {}", field))?;
+        self.advance(width, field, EncodeMethod::VarInt);
+        self.annotate(result);
+        Ok(result)
+    }
+
+    /// Read a signed varint in zigzag encoding.
+    pub(crate) fn read_var_i64(&mut self, field: &str) -> anyhow::Result<i64> {
+        let (result, width) = varint::try_decode_i64(&self.b[self.offset..])
+            .ok_or_else(|| anyhow!("invalid varint for {}", field))?;
+        self.advance(width, field, EncodeMethod::VarInt);
+        self.annotate(result);
+        Ok(result)
+    }
+
+    pub(crate) fn read_u64(&mut self, field: &str) -> anyhow::Result<u64> {
+        let b = self
+            .b
+            .get(self.offset..self.offset + 8)
+            .ok_or_else(|| anyhow!("need 8 bytes for {}", field))?;
+        let result = endian::big::decode_u64(b);
+        self.advance(8, field, EncodeMethod::BigEndian);
+        self.annotate(result);
+        Ok(result)
+    }
+
+    /// Consume all the bytes left.
+    pub(crate) fn read_rest(&mut self, field: &str) -> &'a [u8] {
+        let b: &'a [u8] = self.b;
+        let rest = &b[self.offset..];
+        self.advance(rest.len(), field, EncodeMethod::Bytes);
+        rest
+    }
+
+    /// Read bytes prefixed with their length encoded as a signed varint.
+    pub(crate) fn read_compact_bytes(&mut self, field: &str) -> anyhow::Result<&'a [u8]> {
+        let (len, width) = varint::try_decode_i64(&self.b[self.offset..])
+            .ok_or_else(|| anyhow!("invalid length for {}", field))?;
+        if len < 0 {
+            bail!("negative length for {}", field);
+        }
+        self.begin(field);
+        self.advance(width, "length", EncodeMethod::VarInt);
+        self.annotate(len);
+        let result = self.read_bytes(len as usize, "data")?;
+        self.end(crate::explain::escape(result));
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracing_reader() {
+        let code = [b'v', 2, b'a', b'b', 0, 0, 0, 0, 0, 0, 0, 7];
+        let mut reader = TracingReader::new(&code);
+        reader.begin("short_value");
+        reader.read_u8("flag", EncodeMethod::EnumFlag).unwrap();
+        let len = reader.read_u8("length", EncodeMethod::SingleByte).unwrap();
+        reader.annotate(len);
+        reader.read_bytes(len as usize, "value").unwrap();
+        reader.end("\"ab\"");
+        reader.read_u64("ts").unwrap();
+        assert!(reader.read_u8("nothing", EncodeMethod::EnumFlag).is_err());
+        let trace = reader.finish("value", "something");
+        assert_eq!((trace.start, trace.width), (0, 12));
+        assert_eq!(trace.children.len(), 2);
+        let short_value = &trace.children[0];
+        assert_eq!((short_value.start, short_value.width), (0, 4));
+        assert_eq!(short_value.children[1].value.as_deref(), Some("2"));
+        assert_eq!(trace.children[1].value.as_deref(), Some("7"));
+        let mut fields = Vec::new();
+        trace.walk(&mut |node, depth| fields.push((depth, node.field.clone())));
+        assert_eq!(fields.len(), 6);
+        assert_eq!(fields[2], (2, "flag".to_string()));
+        let shifted = trace.shift(3);
+        assert_eq!(shifted.children[0].children[2].start, 5);
+    }
+}
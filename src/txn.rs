@@ -0,0 +1,805 @@
+//! A simulator of TiKV's transaction commands, showing what each of them writes.
+//!
+//! Every command reads the `MvccStore` of the simulator, and returns the `Modify`s it
+//! makes, which are applied to the store before the next command, the same as TiKV
+//! writes the batch of a command to RocksDB.
+use crate::explain;
+use crate::lock::{Lock, LockType};
+use crate::mvcc::{
+    Key, Modify, TimeStamp, Value, Write, WriteType, CF_DEFAULT, CF_LOCK, CF_WRITE,
+    SHORT_VALUE_MAX_LEN,
+};
+use crate::store::{CommitRecord, MvccStore};
+use anyhow::bail;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mutation {
+    Put(Vec<u8>, Value),
+    Delete(Vec<u8>),
+    /// Lock the key without changing it, like `SELECT ... FOR UPDATE` does.
+    Lock(Vec<u8>),
+}
+
+impl Mutation {
+    pub fn key(&self) -> &[u8] {
+        match self {
+            Mutation::Put(key, _) | Mutation::Delete(key) | Mutation::Lock(key) => key,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxnOptions {
+    pub lock_ttl: u64,
+    /// Not zero for pessimistic transactions.
+    pub for_update_ts: TimeStamp,
+    pub async_commit: bool,
+    pub min_commit_ts: TimeStamp,
+}
+
+impl Default for TxnOptions {
+    fn default() -> Self {
+        TxnOptions {
+            lock_ttl: 3000,
+            for_update_ts: TimeStamp::zero(),
+            async_commit: false,
+            min_commit_ts: TimeStamp::zero(),
+        }
+    }
+}
+
+/// What `check_txn_status` finds out about a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxnStatus {
+    Committed(TimeStamp),
+    RolledBack,
+    /// The lock has expired and is rolled back now.
+    TtlExpired,
+    /// There is no lock, a rollback record is written to keep the transaction from committing.
+    LockNotExistRolledBack,
+    Uncommitted {
+        ttl: u64,
+        min_commit_ts: TimeStamp,
+    },
+}
+
+impl fmt::Display for TxnStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxnStatus::Committed(commit_ts) => write!(f, "committed at {}", commit_ts),
+            TxnStatus::RolledBack => write!(f, "rolled back"),
+            TxnStatus::TtlExpired => write!(f, "lock expired, rolled back"),
+            TxnStatus::LockNotExistRolledBack => write!(f, "lock not exist, rolled back"),
+            TxnStatus::Uncommitted { ttl, min_commit_ts } => {
+                write!(
+                    f,
+                    "uncommitted, ttl: {}, min_commit_ts: {}",
+                    ttl, min_commit_ts
+                )
+            }
+        }
+    }
+}
+
+/// The modifications of a command, reading the store as it was before the command.
+struct MvccTxn<'a> {
+    store: &'a MvccStore,
+    modifies: Vec<Modify>,
+}
+
+impl<'a> MvccTxn<'a> {
+    fn new(store: &'a MvccStore) -> Self {
+        MvccTxn {
+            store,
+            modifies: Vec::new(),
+        }
+    }
+
+    fn put_lock(&mut self, key: &[u8], lock: &Lock) {
+        self.modifies
+            .push(Modify::Put(CF_LOCK, Key::from_raw(key), lock.to_bytes()));
+    }
+
+    fn unlock_key(&mut self, key: &[u8]) {
+        self.modifies
+            .push(Modify::Delete(CF_LOCK, Key::from_raw(key)));
+    }
+
+    fn put_value(&mut self, key: &[u8], start_ts: TimeStamp, value: Value) {
+        let key = Key::from_raw(key).append_ts(start_ts);
+        self.modifies.push(Modify::Put(CF_DEFAULT, key, value));
+    }
+
+    fn delete_value(&mut self, key: &[u8], start_ts: TimeStamp) {
+        let key = Key::from_raw(key).append_ts(start_ts);
+        self.modifies.push(Modify::Delete(CF_DEFAULT, key));
+    }
+
+    fn put_write(&mut self, key: &[u8], commit_ts: TimeStamp, write: &Write) {
+        let key = Key::from_raw(key).append_ts(commit_ts);
+        self.modifies
+            .push(Modify::Put(CF_WRITE, key, write.to_bytes()));
+    }
+
+    fn delete_write(&mut self, key: &[u8], commit_ts: TimeStamp) {
+        let key = Key::from_raw(key).append_ts(commit_ts);
+        self.modifies.push(Modify::Delete(CF_WRITE, key));
+    }
+
+    /// Write the rollback record, or mark the overlapped record of another transaction.
+    fn put_rollback(
+        &mut self,
+        key: &[u8],
+        start_ts: TimeStamp,
+        protected: bool,
+    ) -> anyhow::Result<()> {
+        match self.store.commit_record(key, start_ts)? {
+            CommitRecord::Overlapped(mut write) => {
+                if protected {
+                    write.has_overlapped_rollback = true;
+                    self.put_write(key, start_ts, &write);
+                }
+            }
+            _ => self.put_write(key, start_ts, &Write::new_rollback(start_ts, protected)),
+        }
+        // The previous rollback which is not protected is useless now. Rollbacks newer than
+        // this one are kept, or their transactions could be prewritten again.
+        let previous = self
+            .store
+            .all_writes(key)?
+            .into_iter()
+            .find(|(commit_ts, _)| *commit_ts <= start_ts);
+        if let Some((commit_ts, write)) = previous {
+            if write.write_type == WriteType::Rollback && !write.is_protected() {
+                self.delete_write(key, commit_ts);
+            }
+        }
+        Ok(())
+    }
+
+    fn rollback_lock(&mut self, key: &[u8], lock: &Lock) -> anyhow::Result<()> {
+        if lock.lock_type == LockType::Put && lock.short_value_ref().is_none() {
+            self.delete_value(key, lock.ts);
+        }
+        // Only the primary key of a pessimistic transaction needs to be protected.
+        let protected = !lock.for_update_ts.is_zero() && lock.primary_key() == key;
+        self.put_rollback(key, lock.ts, protected)?;
+        self.unlock_key(key);
+        Ok(())
+    }
+
+    /// Check the transaction has no lock on the key, then roll it back if it's not committed.
+    fn rollback_missing_lock(
+        &mut self,
+        key: &[u8],
+        start_ts: TimeStamp,
+        protected: bool,
+    ) -> anyhow::Result<()> {
+        match self.store.commit_record(key, start_ts)? {
+            CommitRecord::Committed(commit_ts) => bail!(
+                "{}: the transaction {} is already committed at {}",
+                explain::explain_key(key),
+                start_ts,
+                commit_ts
+            ),
+            CommitRecord::RolledBack => Ok(()),
+            _ => self.put_rollback(key, start_ts, protected),
+        }
+    }
+
+    fn lock_of(&self, key: &[u8], start_ts: TimeStamp) -> anyhow::Result<Option<Lock>> {
+        Ok(self.store.lock(key)?.filter(|lock| lock.ts == start_ts))
+    }
+}
+
+fn locked_error(key: &[u8], lock: &Lock) -> anyhow::Error {
+    anyhow::anyhow!("{} is locked: {}", explain::explain_key(key), lock)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Simulator {
+    pub store: MvccStore,
+    /// The name and the modifications of every command run.
+    pub history: Vec<(String, Vec<Modify>)>,
+}
+
+impl Simulator {
+    pub fn new(store: MvccStore) -> Simulator {
+        Simulator {
+            store,
+            history: Vec::new(),
+        }
+    }
+
+    fn run<T>(
+        &mut self,
+        command: String,
+        f: impl FnOnce(&mut MvccTxn) -> anyhow::Result<T>,
+    ) -> anyhow::Result<(T, Vec<Modify>)> {
+        let mut txn = MvccTxn::new(&self.store);
+        let result = f(&mut txn)?;
+        let modifies = txn.modifies;
+        self.store.apply(modifies.clone());
+        self.history.push((command, modifies.clone()));
+        Ok((result, modifies))
+    }
+
+    /// Lock the key for a pessimistic transaction.
+    pub fn acquire_pessimistic_lock(
+        &mut self,
+        key: &[u8],
+        primary: &[u8],
+        start_ts: TimeStamp,
+        for_update_ts: TimeStamp,
+        lock_ttl: u64,
+    ) -> anyhow::Result<Vec<Modify>> {
+        let command = format!("acquire_pessimistic_lock {}", explain::explain_key(key));
+        self.run(command, |txn| {
+            if let Some(mut lock) = txn.store.lock(key)? {
+                if lock.ts != start_ts {
+                    return Err(locked_error(key, &lock));
+                }
+                if lock.lock_type != LockType::Pessimistic {
+                    bail!("{} is already prewritten", explain::explain_key(key));
+                }
+                if lock.for_update_ts < for_update_ts {
+                    lock.for_update_ts = for_update_ts;
+                    txn.put_lock(key, &lock);
+                }
+                return Ok(());
+            }
+            if let Some((commit_ts, write)) = txn.store.all_writes(key)?.into_iter().next() {
+                if commit_ts > for_update_ts {
+                    bail!(
+                        "write conflict on {}: committed at {} after for_update_ts {}",
+                        explain::explain_key(key),
+                        commit_ts,
+                        for_update_ts
+                    );
+                }
+                if write.start_ts == start_ts && write.write_type == WriteType::Rollback {
+                    bail!("the transaction {} is already rolled back", start_ts);
+                }
+            }
+            let mut lock = Lock::new(LockType::Pessimistic, primary.to_vec(), start_ts, lock_ttl);
+            lock.for_update_ts = for_update_ts;
+            txn.put_lock(key, &lock);
+            Ok(())
+        })
+        .map(|(_, modifies)| modifies)
+    }
+
+    /// Prewrite the mutations, the first phase of 2PC.
+    pub fn prewrite(
+        &mut self,
+        mutations: &[Mutation],
+        primary: &[u8],
+        start_ts: TimeStamp,
+        options: &TxnOptions,
+    ) -> anyhow::Result<Vec<Modify>> {
+        let pessimistic = !options.for_update_ts.is_zero();
+        let command = format!("prewrite {} keys at {}", mutations.len(), start_ts);
+        self.run(command, |txn| {
+            for mutation in mutations {
+                let key = mutation.key();
+                match txn.store.lock(key)? {
+                    Some(lock) if lock.ts != start_ts => return Err(locked_error(key, &lock)),
+                    // Prewritten already, the request is retried.
+                    Some(lock) if lock.lock_type != LockType::Pessimistic => continue,
+                    Some(_) => {}
+                    None if pessimistic => {
+                        bail!(
+                            "pessimistic lock on {} not found",
+                            explain::explain_key(key)
+                        )
+                    }
+                    None => {
+                        if let CommitRecord::RolledBack = txn.store.commit_record(key, start_ts)? {
+                            bail!("the transaction {} is already rolled back", start_ts);
+                        }
+                        let newest = txn.store.all_writes(key)?.into_iter().next();
+                        if let Some((commit_ts, _)) = newest.filter(|(ts, _)| *ts > start_ts) {
+                            bail!(
+                                "write conflict on {}: committed at {} after start_ts {}",
+                                explain::explain_key(key),
+                                commit_ts,
+                                start_ts
+                            );
+                        }
+                    }
+                }
+                let (lock_type, value) = match mutation {
+                    Mutation::Put(_, value) => (LockType::Put, Some(value)),
+                    Mutation::Delete(_) => (LockType::Delete, None),
+                    Mutation::Lock(_) => (LockType::Lock, None),
+                };
+                let mut lock = Lock::new(lock_type, primary.to_vec(), start_ts, options.lock_ttl);
+                match value {
+                    Some(value) if value.len() <= SHORT_VALUE_MAX_LEN => {
+                        lock = lock.with_short_value(value.clone());
+                    }
+                    Some(value) => txn.put_value(key, start_ts, value.clone()),
+                    None => {}
+                }
+                if options.async_commit {
+                    let secondaries = if key == primary {
+                        mutations
+                            .iter()
+                            .map(|m| m.key().to_vec())
+                            .filter(|k| k != primary)
+                            .collect()
+                    } else {
+                        vec![]
+                    };
+                    lock = lock.with_async_commit(secondaries);
+                    lock.min_commit_ts = options
+                        .min_commit_ts
+                        .max(TimeStamp(start_ts.0.max(options.for_update_ts.0) + 1));
+                } else {
+                    lock.min_commit_ts = options.min_commit_ts;
+                }
+                lock.for_update_ts = options.for_update_ts;
+                lock.txn_size = mutations.len() as u64;
+                txn.put_lock(key, &lock);
+            }
+            Ok(())
+        })
+        .map(|(_, modifies)| modifies)
+    }
+
+    /// Commit the keys, the second phase of 2PC.
+    pub fn commit(
+        &mut self,
+        keys: &[Vec<u8>],
+        start_ts: TimeStamp,
+        commit_ts: TimeStamp,
+    ) -> anyhow::Result<Vec<Modify>> {
+        let command = format!(
+            "commit {} keys of {} at {}",
+            keys.len(),
+            start_ts,
+            commit_ts
+        );
+        self.run(command, |txn| {
+            for key in keys {
+                let lock = match txn.lock_of(key, start_ts)? {
+                    Some(lock) => lock,
+                    None => match txn.store.commit_record(key, start_ts)? {
+                        CommitRecord::Committed(_) => continue,
+                        _ => bail!(
+                            "the lock of {} on {} is not found",
+                            start_ts,
+                            explain::explain_key(key)
+                        ),
+                    },
+                };
+                let write_type = match lock.lock_type {
+                    LockType::Put => WriteType::Put,
+                    LockType::Delete => WriteType::Delete,
+                    LockType::Lock => WriteType::Lock,
+                    LockType::Pessimistic => bail!(
+                        "the pessimistic lock on {} is not prewritten",
+                        explain::explain_key(key)
+                    ),
+                };
+                if commit_ts < lock.min_commit_ts {
+                    bail!(
+                        "commit_ts {} is older than the min_commit_ts {}",
+                        commit_ts,
+                        lock.min_commit_ts
+                    );
+                }
+                let write = Write::new(write_type, start_ts, lock.short_value_ref().cloned());
+                txn.put_write(key, commit_ts, &write);
+                txn.unlock_key(key);
+            }
+            Ok(())
+        })
+        .map(|(_, modifies)| modifies)
+    }
+
+    /// Roll back the keys, no matter the locks are expired or not.
+    pub fn rollback(
+        &mut self,
+        keys: &[Vec<u8>],
+        start_ts: TimeStamp,
+    ) -> anyhow::Result<Vec<Modify>> {
+        let command = format!("rollback {} keys of {}", keys.len(), start_ts);
+        self.run(command, |txn| {
+            for key in keys {
+                match txn.lock_of(key, start_ts)? {
+                    Some(lock) => txn.rollback_lock(key, &lock)?,
+                    None => txn.rollback_missing_lock(key, start_ts, false)?,
+                }
+            }
+            Ok(())
+        })
+        .map(|(_, modifies)| modifies)
+    }
+
+    /// Roll back the key if its lock has expired at `current_ts`.
+    pub fn cleanup(
+        &mut self,
+        key: &[u8],
+        start_ts: TimeStamp,
+        current_ts: TimeStamp,
+    ) -> anyhow::Result<Vec<Modify>> {
+        let command = format!("cleanup {} of {}", explain::explain_key(key), start_ts);
+        self.run(command, |txn| {
+            match txn.lock_of(key, start_ts)? {
+                Some(lock) if !lock.is_expired(current_ts) => return Err(locked_error(key, &lock)),
+                Some(lock) => txn.rollback_lock(key, &lock)?,
+                None => txn.rollback_missing_lock(key, start_ts, true)?,
+            }
+            Ok(())
+        })
+        .map(|(_, modifies)| modifies)
+    }
+
+    /// Check the status of the transaction by its primary key, rolling it back if it's
+    /// expired, or pushing its min_commit_ts forward so the reader at `caller_start_ts`
+    /// doesn't have to wait. An async-commit lock is left as it is, its status is decided
+    /// by the secondary locks.
+    pub fn check_txn_status(
+        &mut self,
+        primary: &[u8],
+        lock_ts: TimeStamp,
+        caller_start_ts: TimeStamp,
+        current_ts: TimeStamp,
+        rollback_if_not_exist: bool,
+    ) -> anyhow::Result<(TxnStatus, Vec<Modify>)> {
+        let command = format!(
+            "check_txn_status {} of {}",
+            explain::explain_key(primary),
+            lock_ts
+        );
+        self.run(command, |txn| {
+            if let Some(mut lock) = txn.lock_of(primary, lock_ts)? {
+                if lock.use_async_commit {
+                    return Ok(TxnStatus::Uncommitted {
+                        ttl: lock.ttl,
+                        min_commit_ts: lock.min_commit_ts,
+                    });
+                }
+                if lock.is_expired(current_ts) {
+                    txn.rollback_lock(primary, &lock)?;
+                    return Ok(TxnStatus::TtlExpired);
+                }
+                // A lock without min_commit_ts is of an old TiDB, which can't commit if it's
+                // pushed.
+                if !lock.min_commit_ts.is_zero()
+                    && !caller_start_ts.is_max()
+                    && caller_start_ts >= lock.min_commit_ts
+                {
+                    lock.min_commit_ts = TimeStamp(caller_start_ts.0 + 1).max(current_ts);
+                    txn.put_lock(primary, &lock);
+                }
+                return Ok(TxnStatus::Uncommitted {
+                    ttl: lock.ttl,
+                    min_commit_ts: lock.min_commit_ts,
+                });
+            }
+            match txn.store.commit_record(primary, lock_ts)? {
+                CommitRecord::Committed(commit_ts) => Ok(TxnStatus::Committed(commit_ts)),
+                CommitRecord::RolledBack => Ok(TxnStatus::RolledBack),
+                _ if rollback_if_not_exist => {
+                    txn.put_rollback(primary, lock_ts, true)?;
+                    Ok(TxnStatus::LockNotExistRolledBack)
+                }
+                _ => bail!("the transaction {} is not found", lock_ts),
+            }
+        })
+    }
+
+    /// Commit or roll back the locks of the transaction on the keys, once its status is known.
+    pub fn resolve_lock(
+        &mut self,
+        keys: &[Vec<u8>],
+        start_ts: TimeStamp,
+        commit_ts: Option<TimeStamp>,
+    ) -> anyhow::Result<Vec<Modify>> {
+        let command = format!("resolve_lock {} keys of {}", keys.len(), start_ts);
+        self.run(command, |txn| {
+            for key in keys {
+                let lock = match txn.lock_of(key, start_ts)? {
+                    Some(lock) => lock,
+                    None => continue,
+                };
+                match commit_ts {
+                    Some(commit_ts) => {
+                        let write_type = match lock.lock_type {
+                            LockType::Put => WriteType::Put,
+                            LockType::Delete => WriteType::Delete,
+                            LockType::Lock => WriteType::Lock,
+                            // A pessimistic lock left behind is simply removed.
+                            LockType::Pessimistic => {
+                                txn.unlock_key(key);
+                                continue;
+                            }
+                        };
+                        let write =
+                            Write::new(write_type, start_ts, lock.short_value_ref().cloned());
+                        txn.put_write(key, commit_ts, &write);
+                        txn.unlock_key(key);
+                    }
+                    None => txn.rollback_lock(key, &lock)?,
+                }
+            }
+            Ok(())
+        })
+        .map(|(_, modifies)| modifies)
+    }
+
+    /// All the commands run and what they wrote.
+    pub fn explain(&self) -> String {
+        let mut result = String::new();
+        for (command, modifies) in &self.history {
+            result.push_str(command);
+            result.push('\n');
+            for modify in modifies {
+                result.push_str(&format!("  {}\n", modify));
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::ReadResult;
+
+    const PK: &[u8] = b"pk";
+    const SK: &[u8] = b"sk";
+
+    fn ts(physical: u64) -> TimeStamp {
+        TimeStamp::compose(physical, 0)
+    }
+
+    #[test]
+    fn test_optimistic_txn() {
+        let mut sim = Simulator::default();
+        let mutations = vec![
+            Mutation::Put(PK.to_vec(), b"v".to_vec()),
+            Mutation::Put(SK.to_vec(), vec![b'x'; SHORT_VALUE_MAX_LEN + 1]),
+        ];
+        let modifies = sim
+            .prewrite(&mutations, PK, TimeStamp(10), &TxnOptions::default())
+            .unwrap();
+        // The long value goes to the default CF.
+        assert_eq!(modifies.len(), 3);
+        assert!(matches!(&modifies[1], Modify::Put(CF_DEFAULT, ..)));
+        assert!(matches!(
+            sim.store.get(PK, TimeStamp(20)).unwrap().result,
+            ReadResult::Locked(_)
+        ));
+
+        let modifies = sim
+            .commit(&[PK.to_vec(), SK.to_vec()], TimeStamp(10), TimeStamp(11))
+            .unwrap();
+        assert_eq!(
+            modifies[0].to_string(),
+            "put write encoded(\"pk\")@11 = Put, start_ts: 10, short_value: \"v\""
+        );
+        assert_eq!(modifies[1].to_string(), "delete lock encoded(\"pk\")");
+        let read = |sim: &Simulator, key, at| sim.store.get(key, TimeStamp(at)).unwrap().result;
+        assert_eq!(read(&sim, PK, 11), ReadResult::Found(b"v".to_vec()));
+        assert_eq!(read(&sim, SK, 11), ReadResult::Found(vec![b'x'; 256]));
+
+        // A transaction started before the commit conflicts.
+        let mutations = vec![Mutation::Delete(PK.to_vec())];
+        assert!(sim
+            .prewrite(&mutations, PK, TimeStamp(9), &TxnOptions::default())
+            .is_err());
+        // Committing again is fine.
+        assert!(sim
+            .commit(&[PK.to_vec()], TimeStamp(10), TimeStamp(11))
+            .unwrap()
+            .is_empty());
+        assert!(sim
+            .explain()
+            .starts_with("prewrite 2 keys at 10\n  put lock encoded(\"pk\") = Put"));
+    }
+
+    #[test]
+    fn test_pessimistic_txn() {
+        let mut sim = Simulator::default();
+        let (start_ts, for_update_ts) = (ts(100), ts(101));
+        sim.acquire_pessimistic_lock(PK, PK, start_ts, for_update_ts, 3000)
+            .unwrap();
+        sim.acquire_pessimistic_lock(SK, PK, start_ts, for_update_ts, 3000)
+            .unwrap();
+        assert!(sim
+            .acquire_pessimistic_lock(SK, SK, ts(102), ts(102), 3000)
+            .is_err());
+        // Committing a pessimistic lock is an error.
+        assert!(sim.commit(&[PK.to_vec()], start_ts, ts(103)).is_err());
+
+        // The primary lock expires, check_txn_status rolls it back with a protected rollback.
+        let (status, modifies) = sim
+            .check_txn_status(PK, start_ts, ts(200), ts(100 + 3001), true)
+            .unwrap();
+        assert_eq!(status, TxnStatus::TtlExpired);
+        assert_eq!(
+            modifies[0].to_string(),
+            format!(
+                "put write encoded(\"pk\")@{} = Rollback, start_ts: {}, short_value: \"p\"",
+                start_ts, start_ts
+            )
+        );
+        let (status, _) = sim
+            .check_txn_status(PK, start_ts, ts(200), ts(4000), true)
+            .unwrap();
+        assert_eq!(status, TxnStatus::RolledBack);
+
+        // The secondary lock is resolved, its rollback is not protected.
+        sim.resolve_lock(&[SK.to_vec()], start_ts, None).unwrap();
+        let writes = sim.store.all_writes(SK).unwrap();
+        assert_eq!(writes.len(), 1);
+        assert!(!writes[0].1.is_protected());
+        assert!(sim.store.lock(SK).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_check_txn_status() {
+        let mut sim = Simulator::default();
+        let mutations = vec![Mutation::Put(PK.to_vec(), b"v".to_vec())];
+        let options = TxnOptions {
+            min_commit_ts: ts(101),
+            ..Default::default()
+        };
+        sim.prewrite(&mutations, PK, ts(100), &options).unwrap();
+
+        // A reader pushes the min_commit_ts of an alive lock.
+        let (status, _) = sim
+            .check_txn_status(PK, ts(100), ts(150), ts(101), false)
+            .unwrap();
+        assert_eq!(
+            status,
+            TxnStatus::Uncommitted {
+                ttl: 3000,
+                min_commit_ts: TimeStamp(ts(150).0 + 1)
+            }
+        );
+        // TiDB reads with the max ts without pushing the lock.
+        let (status, modifies) = sim
+            .check_txn_status(PK, ts(100), TimeStamp::max(), ts(101), false)
+            .unwrap();
+        assert!(modifies.is_empty());
+        assert!(
+            matches!(status, TxnStatus::Uncommitted { min_commit_ts, .. } if min_commit_ts == TimeStamp(ts(150).0 + 1))
+        );
+        // It's pushed to the current ts at least.
+        let (status, _) = sim
+            .check_txn_status(PK, ts(100), ts(151), ts(155), false)
+            .unwrap();
+        assert!(
+            matches!(status, TxnStatus::Uncommitted { min_commit_ts, .. } if min_commit_ts == ts(155))
+        );
+        assert!(sim.commit(&[PK.to_vec()], ts(100), ts(120)).is_err());
+        assert!(sim.cleanup(PK, ts(100), ts(101)).is_err());
+        sim.commit(&[PK.to_vec()], ts(100), ts(160)).unwrap();
+        let (status, _) = sim
+            .check_txn_status(PK, ts(100), ts(170), ts(170), false)
+            .unwrap();
+        assert_eq!(status, TxnStatus::Committed(ts(160)));
+
+        // A transaction whose lock never arrived is rolled back, so it cannot be prewritten.
+        let (status, _) = sim
+            .check_txn_status(SK, ts(200), ts(210), ts(210), true)
+            .unwrap();
+        assert_eq!(status, TxnStatus::LockNotExistRolledBack);
+        let mutations = vec![Mutation::Put(SK.to_vec(), b"v".to_vec())];
+        assert!(sim
+            .prewrite(&mutations, SK, ts(200), &TxnOptions::default())
+            .is_err());
+        assert!(sim
+            .check_txn_status(b"none", ts(1), ts(2), ts(2), false)
+            .is_err());
+    }
+
+    #[test]
+    fn test_check_txn_status_without_push() {
+        let mut sim = Simulator::default();
+        // The lock of an old TiDB has no min_commit_ts, it's not pushed.
+        let mutations = vec![Mutation::Put(SK.to_vec(), b"v".to_vec())];
+        sim.prewrite(&mutations, SK, ts(100), &TxnOptions::default())
+            .unwrap();
+        let (status, modifies) = sim
+            .check_txn_status(SK, ts(100), ts(150), ts(101), false)
+            .unwrap();
+        assert_eq!(
+            status,
+            TxnStatus::Uncommitted {
+                ttl: 3000,
+                min_commit_ts: TimeStamp::zero()
+            }
+        );
+        assert!(modifies.is_empty());
+
+        // An async-commit lock is neither pushed nor rolled back, even if it's expired.
+        let options = TxnOptions {
+            async_commit: true,
+            ..Default::default()
+        };
+        let mutations = vec![Mutation::Put(PK.to_vec(), b"v".to_vec())];
+        sim.prewrite(&mutations, PK, ts(100), &options).unwrap();
+        let (status, modifies) = sim
+            .check_txn_status(PK, ts(100), ts(150), ts(100 + 3001), true)
+            .unwrap();
+        assert_eq!(
+            status,
+            TxnStatus::Uncommitted {
+                ttl: 3000,
+                min_commit_ts: TimeStamp(ts(100).0 + 1)
+            }
+        );
+        assert!(modifies.is_empty());
+        assert!(sim.store.lock(PK).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_async_commit_and_rollback() {
+        let mut sim = Simulator::default();
+        let options = TxnOptions {
+            async_commit: true,
+            ..Default::default()
+        };
+        let mutations = vec![
+            Mutation::Put(PK.to_vec(), b"v".to_vec()),
+            Mutation::Lock(SK.to_vec()),
+        ];
+        sim.prewrite(&mutations, PK, TimeStamp(10), &options)
+            .unwrap();
+        let primary = sim.store.lock(PK).unwrap().unwrap();
+        assert!(primary.use_async_commit);
+        assert_eq!(primary.secondary_keys(), &[SK.to_vec()]);
+        assert_eq!(primary.min_commit_ts, TimeStamp(11));
+        assert!(sim
+            .store
+            .lock(SK)
+            .unwrap()
+            .unwrap()
+            .secondary_keys()
+            .is_empty());
+
+        sim.rollback(&[PK.to_vec(), SK.to_vec()], TimeStamp(10))
+            .unwrap();
+        assert!(sim.store.lock(PK).unwrap().is_none());
+        // Rolling back again changes nothing.
+        assert!(sim
+            .rollback(&[PK.to_vec()], TimeStamp(10))
+            .unwrap()
+            .is_empty());
+        // An older rollback which is not protected is collapsed by the next one.
+        let modifies = sim.rollback(&[PK.to_vec()], TimeStamp(20)).unwrap();
+        assert_eq!(modifies.len(), 2);
+        assert!(matches!(&modifies[1], Modify::Delete(CF_WRITE, _)));
+    }
+
+    #[test]
+    fn test_rollback_order() {
+        let mut sim = Simulator::default();
+        sim.rollback(&[PK.to_vec()], TimeStamp(30)).unwrap();
+        // The newer rollback is not collapsed by an older one.
+        let modifies = sim.rollback(&[PK.to_vec()], TimeStamp(20)).unwrap();
+        assert_eq!(modifies.len(), 1);
+        let mutations = vec![Mutation::Put(PK.to_vec(), b"v".to_vec())];
+        assert!(sim
+            .prewrite(&mutations, PK, TimeStamp(30), &TxnOptions::default())
+            .is_err());
+
+        // A record committed at the start ts is not a write conflict.
+        sim.prewrite(&mutations, PK, TimeStamp(35), &TxnOptions::default())
+            .unwrap();
+        sim.commit(&[PK.to_vec()], TimeStamp(35), TimeStamp(40))
+            .unwrap();
+        let error = sim
+            .prewrite(&mutations, PK, TimeStamp(39), &TxnOptions::default())
+            .unwrap_err();
+        assert!(error.to_string().starts_with("write conflict"));
+        sim.prewrite(&mutations, PK, TimeStamp(40), &TxnOptions::default())
+            .unwrap();
+    }
+}
@@ -1,3 +1,6 @@
+use serde::Serialize;
+use wasm_bindgen::JsValue;
+
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
     // `set_panic_hook` function at least once during initialization, and then
@@ -8,3 +11,60 @@ pub fn set_panic_hook() {
     #[cfg(feature = "console_error_panic_hook")]
     console_error_panic_hook::set_once();
 }
+
+/// Convert a serializable rust value into a plain js object.
+// `JsValue::from_serde` is what the `serde-serialize` feature of wasm-bindgen gives us,
+// it is deprecated in favor of `serde-wasm-bindgen`, but still works fine for our simple values.
+#[allow(deprecated)]
+pub fn to_js_value<T: Serialize + ?Sized>(value: &T) -> JsValue {
+    JsValue::from_serde(value).unwrap()
+}
+
+/// Format milliseconds since unix epoch as an UTC date time, eg. `2021-06-02 09:25:01.123 UTC`.
+pub fn format_unix_millis(millis: u64) -> String {
+    let secs = millis / 1000;
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+    // Convert days since epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03} UTC",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        millis % 1000
+    )
+}
+
+/// The clock of the tests, 2021-06-02 09:25:01.123 UTC in milliseconds.
+#[cfg(test)]
+pub(crate) const NOW: u64 = 1622625901123;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_unix_millis() {
+        assert_eq!(format_unix_millis(0), "1970-01-01 00:00:00.000 UTC");
+        assert_eq!(
+            format_unix_millis(1622625901123),
+            "2021-06-02 09:25:01.123 UTC"
+        );
+        assert_eq!(
+            format_unix_millis(951782400000),
+            "2000-02-29 00:00:00.000 UTC"
+        );
+    }
+}
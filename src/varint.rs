@@ -4,6 +4,20 @@ pub fn decode_u64(code: &[u8]) -> (u64, usize) {
     u64::decode_var(code).unwrap()
 }
 
+/// Like `decode_u64`, but returns `None` instead of panicking on malformed input.
+pub fn try_decode_u64(code: &[u8]) -> Option<(u64, usize)> {
+    u64::decode_var(code)
+}
+
 pub fn encode_u64(i: u64) -> Vec<u8> {
     i.encode_var_vec()
 }
+
+/// Decode a zigzag encoded signed varint.
+pub fn try_decode_i64(code: &[u8]) -> Option<(i64, usize)> {
+    i64::decode_var(code)
+}
+
+pub fn encode_i64(i: i64) -> Vec<u8> {
+    i.encode_var_vec()
+}